@@ -0,0 +1,124 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! A cache of the change set of a pull request, for `--reuse-changeset`
+//!
+//! Fetching the list of files of a big pull request takes a request per page of files. Rerunning
+//! after some downloads failed can reuse the cached list as long as the head of the pull request
+//! hasn't moved.
+
+use crate::change_set::{Change, ChangeSet};
+use crate::gh_interface::PullRequest;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Deserialize)]
+struct Entry {
+    head_sha: String,
+    changes: Vec<Change>,
+}
+
+/// The cached change sets, a file per pull request
+pub struct ChangeSetCache {
+    dir: PathBuf,
+}
+
+impl ChangeSetCache {
+    /// A cache of the change sets in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache in the user's cache directory
+    ///
+    /// This is `$XDG_CACHE_HOME`, or `~/.cache`, falling back to the temporary directory when
+    /// neither is set.
+    pub fn in_user_cache() -> Self {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        Self::new(dir.join("gh-difftool").join("changesets"))
+    }
+
+    /// The cached change set of `pr`, when it was cached for `head_sha`
+    ///
+    /// A missing or unreadable cache entry is treated the same as one for another head.
+    pub fn load(&self, pr: &PullRequest, head_sha: &str) -> Option<ChangeSet> {
+        let contents = fs::read_to_string(self.path(pr)).ok()?;
+        let entry = serde_json::from_str::<Entry>(&contents).ok()?;
+        (entry.head_sha == head_sha).then(|| ChangeSet::new(entry.changes))
+    }
+
+    /// Cache the `change_set` of `pr` at `head_sha`, replacing the one for any previous head
+    pub fn store(&self, pr: &PullRequest, head_sha: &str, change_set: &ChangeSet) -> Result<()> {
+        let path = self.path(pr);
+        let dir = path
+            .parent()
+            .expect("Cache entries are always in a directory");
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the cache directory {}", dir.display()))?;
+        let entry = serde_json::json!({
+            "head_sha": head_sha,
+            "changes": change_set.changes,
+        });
+        fs::write(&path, entry.to_string())
+            .with_context(|| format!("Failed to write the cached change set {}", path.display()))
+    }
+
+    fn path(&self, pr: &PullRequest) -> PathBuf {
+        self.dir.join(&pr.repo).join(format!("{}.json", pr.number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_testdir::TempDir;
+
+    fn pr() -> PullRequest {
+        PullRequest {
+            repo: "owner/repo".to_string(),
+            number: 7,
+        }
+    }
+
+    fn change_set() -> ChangeSet {
+        ChangeSet::new(vec![Change {
+            filename: "src/main.rs".to_string(),
+            status: "modified".to_string(),
+            patch: Some("@@ -1 +1 @@\n-old\n+new".to_string()),
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    fn reused_for_same_head() {
+        let temp = TempDir::default();
+        let cache = ChangeSetCache::new(temp.as_ref());
+        cache.store(&pr(), "abc123", &change_set()).unwrap();
+
+        assert_eq!(cache.load(&pr(), "abc123"), Some(change_set()));
+    }
+
+    #[test]
+    fn invalidated_by_new_head() {
+        let temp = TempDir::default();
+        let cache = ChangeSetCache::new(temp.as_ref());
+        cache.store(&pr(), "abc123", &change_set()).unwrap();
+
+        assert_eq!(cache.load(&pr(), "def456"), None);
+    }
+
+    #[test]
+    fn nothing_cached() {
+        let temp = TempDir::default();
+        let cache = ChangeSetCache::new(temp.as_ref());
+
+        assert_eq!(cache.load(&pr(), "abc123"), None);
+    }
+}
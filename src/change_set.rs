@@ -6,11 +6,16 @@
 //! Set of changes that goes from one version of files to another
 
 use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fs;
-use std::io::{Error, ErrorKind, Write};
+use std::io::{Error, Write};
 use std::path::Path;
 use std::process::{Command, Stdio};
+use url::Url;
 
 #[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Change {
@@ -22,10 +27,258 @@ pub struct Change {
     /// and large binary diffs
     pub patch: Option<String>,
     pub status: String,
+    /// The number of lines added and removed
+    #[serde(default)]
+    pub changes: usize,
+    /// The number of lines added
+    #[serde(default)]
+    pub additions: usize,
+    /// The number of lines removed
+    #[serde(default)]
+    pub deletions: usize,
+    /// The blob sha of the new version of the file
+    #[serde(default)]
+    pub sha: Option<String>,
+    /// What kind of file changed, determined from the patch when the [`ChangeSet`] is created
+    #[serde(skip_deserializing)]
+    pub kind: ChangeKind,
+}
+
+/// The percent-decoded `encoded`, `None` when it doesn't decode to UTF-8
+fn percent_decoded(encoded: &str) -> Option<String> {
+    percent_decode_str(encoded)
+        .decode_utf8()
+        .ok()
+        .map(Cow::into_owned)
+}
+
+/// The git blob sha of an empty file
+const EMPTY_BLOB_SHA: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// What kind of file a [`Change`] is for
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ChangeKind {
+    #[default]
+    File,
+    /// A submodule, with the commits it points to in the base and new versions
+    ///
+    /// The base commit is missing when the submodule was added, and the new commit is missing when
+    /// the submodule was removed.
+    Submodule {
+        base: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl ChangeKind {
+    /// The kind of file changed by `patch`
+    ///
+    /// GitHub has no file contents for submodules, the patch only changes the "Subproject commit"
+    /// line which git uses to show the commit a submodule points to.
+    pub fn from_patch(patch: Option<&str>) -> Self {
+        const SUBPROJECT: &str = "Subproject commit ";
+        let Some(patch) = patch else {
+            return ChangeKind::File;
+        };
+        let mut base = None;
+        let mut new = None;
+        for line in patch.lines().filter(|line| !line.starts_with("@@")) {
+            let subproject = |sign| line.strip_prefix(sign)?.strip_prefix(SUBPROJECT);
+            if let Some(commit) = subproject('-') {
+                base = Some(commit);
+            } else if let Some(commit) = subproject('+') {
+                new = Some(commit);
+            } else {
+                return ChangeKind::File;
+            }
+        }
+        if base.is_none() && new.is_none() {
+            return ChangeKind::File;
+        }
+        ChangeKind::Submodule {
+            base: base.map(str::to_string),
+            new: new.map(str::to_string),
+        }
+    }
+}
+
+/// How the base version of a file is reconstructed from the new version by
+/// [`Change::reverse_apply`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reconstruction {
+    /// The base version is the commit the submodule pointed to
+    Submodule,
+    /// The new version is the removed file, it becomes the base and the new version is emptied
+    Removed,
+    /// There's no patch to reverse apply, the base version is the same as the new version
+    Copy,
+    /// The patch is reverse applied to the new version
+    ReversePatch,
 }
 
 impl Change {
+    /// How [`Change::reverse_apply`] gets the base version of the file
+    pub fn reconstruction(&self) -> Reconstruction {
+        if let ChangeKind::Submodule { .. } = &self.kind {
+            return Reconstruction::Submodule;
+        }
+        if self.status == "removed" {
+            return Reconstruction::Removed;
+        }
+        // Renamed files don't have a patch, an empty one changes nothing either
+        match &self.patch {
+            Some(patch) if !patch.trim().is_empty() => Reconstruction::ReversePatch,
+            _ => Reconstruction::Copy,
+        }
+    }
+
+    /// Whether the base version of the file needs to be downloaded instead of derived from the patch
+    ///
+    /// GitHub omits the patch for binary files, and for modified and renamed files whose diff is
+    /// too large, even when the content changed. For these the base version can not be
+    /// reconstructed from the new version. A renamed file without a patch or any changed lines
+    /// was only renamed.
+    pub fn needs_base_contents(&self) -> bool {
+        match self.status.as_str() {
+            "modified" => self.patch.is_none(),
+            "renamed" => self.patch.is_none() && self.changes > 0,
+            _ => false,
+        }
+    }
+
+    /// Whether the file is likely binary
+    ///
+    /// GitHub provides no patch for binary files and doesn't count their lines as changes. Files
+    /// that are only renamed also come without a patch, so they are not considered binary, nor
+    /// are empty files which were added or removed. Large text diffs are omitted as well, but
+    /// these still count their changed lines.
+    pub fn is_binary(&self) -> bool {
+        self.patch.is_none()
+            && self.changes == 0
+            && !self.is_rename()
+            && self.sha.as_deref() != Some(EMPTY_BLOB_SHA)
+    }
+
+    /// The (base, new) commits of a submodule change, `None` when the change isn't a submodule
+    ///
+    /// Like git, a submodule which was added or removed uses the null sha, all zeros, for the
+    /// commit it doesn't have.
+    pub fn submodule_shas(&self) -> Option<(String, String)> {
+        const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+        match &self.kind {
+            ChangeKind::Submodule { base, new } => Some((
+                base.as_deref().unwrap_or(NULL_SHA).to_string(),
+                new.as_deref().unwrap_or(NULL_SHA).to_string(),
+            )),
+            ChangeKind::File => None,
+        }
+    }
+
+    /// Whether the file was renamed or copied from `previous_filename`
+    pub fn is_rename(&self) -> bool {
+        self.status == "renamed" || self.status == "copied"
+    }
+
+    /// The contents url for the base version of the file at `base_ref`
+    ///
+    /// Uses the `previous_filename` when present since the file may have been renamed.
+    ///
+    /// # Arguments
+    /// * `base_ref` - The commit, branch, or tag to get the base version of the file from
+    pub fn base_contents_url(&self, base_ref: impl AsRef<str>) -> Result<String> {
+        let filename = self.previous_filename.as_ref().unwrap_or(&self.filename);
+        self.contents_url_at(filename, base_ref)
+    }
+
+    /// Decode the filenames when GitHub gave them percent-encoded
+    ///
+    /// An encoded filename would create directories with a literal `%2F` in their name. The
+    /// filename is only taken as encoded when it decodes to the path of the `contents_url`, so a
+    /// file literally named `a%20b.txt` is kept as is. Decoding the filenames again changes
+    /// nothing.
+    fn decode_filenames(&mut self) {
+        let Some(path) = self.contents_path() else {
+            return;
+        };
+        if self.filename == path || percent_decoded(&self.filename).as_ref() != Some(&path) {
+            return;
+        }
+        self.filename = path;
+        if let Some(previous) = self.previous_filename.take() {
+            self.previous_filename = Some(percent_decoded(&previous).unwrap_or(previous));
+        }
+    }
+
+    /// The decoded path of the file in the repo from the `contents_url`
+    fn contents_path(&self) -> Option<String> {
+        let url = Url::parse(&self.contents_url).ok()?;
+        let (_, path) = url.path().split_once("/contents/")?;
+        percent_decoded(path)
+    }
+
+    /// The contents url for `filename` at `git_ref` in the same repo as this change
+    ///
+    /// # Arguments
+    /// * `filename` - The path of the file in the repo
+    /// * `git_ref` - The commit, branch, or tag to get the file from
+    pub fn contents_url_at(
+        &self,
+        filename: impl AsRef<str>,
+        git_ref: impl AsRef<str>,
+    ) -> Result<String> {
+        let mut url = self.repo_api_url(format!("contents/{}", filename.as_ref()))?;
+        url.query_pairs_mut().append_pair("ref", git_ref.as_ref());
+        Ok(url.to_string())
+    }
+
+    /// The git blob url for `sha` in the same repo as this change
+    ///
+    /// # Arguments
+    /// * `sha` - The sha of the blob
+    pub fn blob_url(&self, sha: impl AsRef<str>) -> Result<String> {
+        let url = self.repo_api_url(format!("git/blobs/{}", sha.as_ref()))?;
+        Ok(url.to_string())
+    }
+
+    /// A url to `path` in the GitHub api for the repo of this change
+    ///
+    /// The repo is derived from the `contents_url`.
+    fn repo_api_url(&self, path: impl AsRef<str>) -> Result<Url> {
+        let mut url = Url::parse(&self.contents_url)?;
+        let prefix = url
+            .path()
+            .split_once("/contents/")
+            .map(|(prefix, _)| prefix.to_string())
+            .ok_or_else(|| {
+                Error::other(format!("Unexpected contents url {}", self.contents_url))
+            })?;
+        url.set_path(&format!("{prefix}/{}", path.as_ref()));
+        url.set_query(None);
+        Ok(url)
+    }
+
     pub fn reverse_apply<P1, P2>(&self, src: P1, dest: P2) -> Result<()>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        self.reverse_apply_with(src, dest, false)
+    }
+
+    /// Same as [`Change::reverse_apply`], but passes `--binary` to the system `patch`
+    ///
+    /// This prevents `patch` from translating line endings, the way git invokes `patch` on
+    /// Windows, so patches with CRLF line endings are applied verbatim.
+    pub fn reverse_apply_binary<P1, P2>(&self, src: P1, dest: P2) -> Result<()>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        self.reverse_apply_with(src, dest, true)
+    }
+
+    fn reverse_apply_with<P1, P2>(&self, src: P1, dest: P2, binary: bool) -> Result<()>
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
@@ -37,17 +290,29 @@ impl Change {
         // [`Change`] happen to create the original and new files instead of letting [`Change`] do
         // it. Because of this lack of encapsulation, [`Change`] will swap out the new version for
         // the old version and write an empty new version
-        if self.status == "removed" {
-            fs::copy(&src, &dest)?;
-            fs::write(src, "")?;
-            return Ok(());
+        match self.reconstruction() {
+            // Submodules have no contents, the base version is the commit the submodule pointed to
+            Reconstruction::Submodule => {
+                if let ChangeKind::Submodule { base, .. } = &self.kind {
+                    fs::write(dest, submodule_contents(base.as_deref()))?;
+                }
+                return Ok(());
+            }
+            Reconstruction::Removed => {
+                fs::copy(&src, &dest)?;
+                fs::write(src, "")?;
+                return Ok(());
+            }
+            Reconstruction::Copy => {
+                fs::copy(&src, &dest)?;
+                return Ok(());
+            }
+            Reconstruction::ReversePatch => (),
         }
-
-        // Renamed files don't have a patch
-        let Some(patch) = self.patch.as_ref() else {
-            fs::copy(&src, &dest)?;
-            return Ok(());
-        };
+        let patch = self
+            .patch
+            .as_ref()
+            .expect("A patch is reverse applied only when there is one");
 
         let mut cmd = Command::new("patch");
         cmd.args([
@@ -56,6 +321,9 @@ impl Change {
             "-o",
             &dest.as_ref().to_string_lossy(),
         ]);
+        if binary {
+            cmd.arg("--binary");
+        }
         cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
@@ -68,53 +336,166 @@ impl Change {
 
         // Not sure how to force this in a minimum reproducible example.
         // When using patch and deleting things close to the end of the file it seems that missing
-        // a newline at the end of the patch will cause it to fail. Always Adding a newline to the
-        // end never seems to be an issue.
-        contents.push('\n');
-
-        // If one doesn't use a thread for writing stdin then it will block indefinitely
-        std::thread::spawn(move || {
-            stdin
-                .write_all(contents.as_bytes())
-                .expect("Failed to write to stdin");
-        });
+        // a newline at the end of the patch will cause it to fail. GitHub leaves off the newline
+        // ending the last line, so it's added back. A patch which already ends with a newline is
+        // left alone, an extra empty line could be taken as more of the hunk and lose the
+        // `\ No newline at end of file` marker of a file without a trailing newline.
+        if !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+
+        // If one doesn't use a thread for writing stdin then it will block indefinitely. The thread
+        // owns stdin, so the pipe is closed as soon as the patch has been written.
+        let writer = std::thread::spawn(move || stdin.write_all(contents.as_bytes()));
 
         let output = child.wait_with_output()?;
 
+        // Joining ensures no thread or pipe outlives the call, even when many files are patched
+        let written = writer
+            .join()
+            .expect("The thread writing the patch to `patch` panicked");
+
         let status = output.status;
         if status.success() {
-            Ok(())
-        } else {
-            Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to patch {:?} to {:?}: {}",
-                    src.as_ref(),
-                    dest.as_ref(),
-                    String::from_utf8_lossy(&output.stderr)
-                ),
-            ))?
+            return Ok(written?);
         }
+        // `patch` may exit before reading all of stdin, the resulting broken pipe is less
+        // helpful than what `patch` reports. Failed hunks are only reported on stdout.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let error = Error::other(format!(
+            "Failed to patch {:?} to {:?}: {stderr}{stdout}",
+            src.as_ref(),
+            dest.as_ref(),
+        ));
+        if hunks_failed(&stdout) {
+            let filename = &self.filename;
+            return Err(anyhow::Error::new(error).context(format!(
+                "The patch of {filename} doesn't match its new version, the pull request was \
+                likely pushed to while it was being fetched, try again"
+            )));
+        }
+        Err(error)?
     }
 }
 
+/// Whether `patch` failed, with `stdout`, because the hunks didn't match the file
+///
+/// The patch from GitHub was made from the file downloaded, unless the pull request changed in
+/// between. A malformed patch is a different problem.
+fn hunks_failed(stdout: &str) -> bool {
+    stdout.contains("hunk FAILED") || stdout.contains("hunks FAILED")
+}
+
+/// How to order the files of a [`ChangeSet`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// The order GitHub's API gives the files in
+    #[default]
+    Api,
+    /// By filename, closer to the "Files changed" tab of a pull request
+    Path,
+    /// Grouped by status, then by filename
+    Status,
+}
+
 #[derive(Default, PartialEq, Eq, Debug)]
 pub struct ChangeSet {
     pub changes: Vec<Change>,
 }
 
 impl ChangeSet {
+    /// Create from the `changes` GitHub provided, determining the [`ChangeKind`] of each and
+    /// decoding any percent-encoded filenames
+    pub fn new(mut changes: Vec<Change>) -> Self {
+        for change in &mut changes {
+            change.decode_filenames();
+            change.kind = ChangeKind::from_patch(change.patch.as_deref());
+        }
+        Self { changes }
+    }
+
     /// Will keep only changes related to `files`
     ///
+    /// Entries in `files` may be glob patterns, e.g. `src/**/*.rs`. A `*` or `?` will not match
+    /// a path separator, use `**` to match across directories.
+    ///
     /// Any `files` which aren't in the current [`Changeset`] will be ignored.
     /// This ignoring of unmatched entries in `files` mimics the behavior of `git-difftool`.
     ///
     /// # Arguments
-    /// * `files` - The files to keep the changes for
+    /// * `files` - The files, or glob patterns, to keep the changes for
     pub fn filter_files<T: AsRef<str>>(&mut self, files: &[T]) -> &mut Self {
         let files = files.iter().map(T::as_ref).collect::<Vec<_>>();
+        let patterns = glob_set(&files);
+        self.changes
+            .retain(|c| files.contains(&c.filename.as_str()) || patterns.is_match(&c.filename));
+        self
+    }
+
+    /// Will drop the changes matching `patterns`
+    ///
+    /// Entries in `patterns` may be exact paths or glob patterns, the same as
+    /// [`ChangeSet::filter_files`].
+    ///
+    /// # Arguments
+    /// * `patterns` - The files, or glob patterns, to drop the changes for
+    pub fn exclude_files<T: AsRef<str>>(&mut self, patterns: &[T]) -> &mut Self {
+        let files = patterns.iter().map(T::as_ref).collect::<Vec<_>>();
+        let patterns = glob_set(&files);
+        self.changes
+            .retain(|c| !files.contains(&c.filename.as_str()) && !patterns.is_match(&c.filename));
+        self
+    }
+
+    /// Will keep only changes with one of the `statuses`
+    ///
+    /// The statuses are the GitHub file statuses, e.g. "added", "modified", "removed", "renamed".
+    ///
+    /// # Arguments
+    /// * `statuses` - The statuses to keep the changes for
+    pub fn filter_status<T: AsRef<str>>(&mut self, statuses: &[T]) -> &mut Self {
+        let statuses = statuses.iter().map(T::as_ref).collect::<Vec<_>>();
         self.changes
-            .retain(|c| files.contains(&c.filename.as_str()));
+            .retain(|c| statuses.contains(&c.status.as_str()));
+        self
+    }
+
+    /// Will keep only changes under the directory `subdir`
+    ///
+    /// `subdir` is treated as a directory, so "src" matches "src/main.rs" but not "src2/main.rs".
+    ///
+    /// # Arguments
+    /// * `subdir` - The directory, relative to the repository root, to keep the changes for
+    pub fn filter_subdir(&mut self, subdir: impl AsRef<str>) -> &mut Self {
+        let subdir = subdir.as_ref().trim_end_matches('/');
+        if subdir.is_empty() {
+            return self;
+        }
+        let prefix = format!("{subdir}/");
+        self.changes.retain(|c| c.filename.starts_with(&prefix));
+        self
+    }
+
+    /// Will keep only changes to the exact `paths`, by either the new or the previous filename
+    ///
+    /// Unlike [`ChangeSet::filter_files`] the `paths` aren't globs, so a file named `a*b.rs` only
+    /// keeps itself.
+    pub fn filter_paths(&mut self, paths: &BTreeSet<String>) -> &mut Self {
+        self.changes.retain(|c| {
+            paths.contains(&c.filename)
+                || c.previous_filename
+                    .as_ref()
+                    .is_some_and(|previous| paths.contains(previous))
+        });
+        self
+    }
+
+    /// Will keep only changes to files which are likely binary
+    ///
+    /// See [`Change::is_binary`] for how binary files are detected.
+    pub fn filter_binary(&mut self) -> &mut Self {
+        self.changes.retain(Change::is_binary);
         self
     }
 
@@ -149,6 +530,34 @@ impl ChangeSet {
         Ok(self)
     }
 
+    /// Keep only the first `count` files in the changeset.
+    pub fn limit(&mut self, count: usize) -> &mut Self {
+        self.changes.truncate(count);
+        self
+    }
+
+    /// Reverse the order of the files in the changeset.
+    pub fn reverse(&mut self) -> &mut Self {
+        self.changes.reverse();
+        self
+    }
+
+    /// Sort the files in the changeset by `order`.
+    ///
+    /// The sort is stable, files which compare equal keep the order GitHub's API gave them in.
+    pub fn sort_by(&mut self, order: SortOrder) -> &mut Self {
+        match order {
+            SortOrder::Api => (),
+            SortOrder::Path => self.changes.sort_by(|a, b| a.filename.cmp(&b.filename)),
+            SortOrder::Status => self.changes.sort_by(|a, b| {
+                a.status
+                    .cmp(&b.status)
+                    .then_with(|| a.filename.cmp(&b.filename))
+            }),
+        }
+        self
+    }
+
     /// Position of `file` in the changeset.
     ///
     /// # Arguments
@@ -162,12 +571,7 @@ impl ChangeSet {
             .changes
             .iter()
             .position(|c| c.filename.as_str() == file)
-            .ok_or_else(|| {
-                Error::new(
-                    ErrorKind::Other,
-                    format!("No such path '{file}' in the diff."),
-                )
-            })?)
+            .ok_or_else(|| Error::other(format!("No such path '{file}' in the diff.")))?)
     }
 }
 
@@ -176,8 +580,33 @@ impl TryFrom<&str> for ChangeSet {
 
     fn try_from(value: &str) -> Result<Self> {
         let changes = serde_json::from_str(value)?;
-        Ok(Self { changes })
+        Ok(Self::new(changes))
+    }
+}
+
+/// The contents to diff for a submodule pointing at `commit`, the same as git shows
+///
+/// A submodule which doesn't exist, has no `commit`, and is an empty file.
+pub fn submodule_contents(commit: Option<&str>) -> String {
+    commit
+        .map(|commit| format!("Subproject commit {commit}\n"))
+        .unwrap_or_default()
+}
+
+/// Build a [GlobSet] from `patterns`
+///
+/// Patterns which aren't valid globs are skipped, they can still match as exact filenames.
+fn glob_set(patterns: &[&str]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for glob in patterns.iter().filter_map(|pattern| {
+        GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .ok()
+    }) {
+        builder.add(glob);
     }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
 }
 
 #[cfg(test)]
@@ -206,10 +635,80 @@ mod tests {
                 patch: Some("patch".into()),
                 status: String::from("modified"),
                 previous_filename: None,
+                ..Default::default()
             })
             .collect::<Vec<_>>()
     }
 
+    #[test]
+    fn encoded_filenames_decoded() {
+        let json = r#"
+            [
+              {
+                "filename": "src%2Fnew%20name.rs",
+                "previous_filename": "src%2Fold.rs",
+                "contents_url": "https://api.github.com/repos/me/repo/contents/src%2Fnew%20name.rs?ref=abc",
+                "status": "renamed"
+              },
+              {
+                "filename": "100%.txt",
+                "contents_url": "https://api.github.com/repos/me/repo/contents/100%25.txt?ref=abc",
+                "status": "added"
+              }
+            ]
+        "#;
+
+        let change_set = ChangeSet::try_from(json).unwrap();
+        let filenames = change_set
+            .changes
+            .iter()
+            .map(|change| {
+                (
+                    change.filename.as_str(),
+                    change.previous_filename.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            [("src/new name.rs", Some("src/old.rs")), ("100%.txt", None)]
+        );
+    }
+
+    #[test]
+    fn literal_percent_filename_not_decoded() {
+        let json = r#"
+            [
+              {
+                "filename": "a%20b.txt",
+                "previous_filename": "old%20b.txt",
+                "contents_url": "https://api.github.com/repos/me/repo/contents/a%2520b.txt?ref=abc",
+                "status": "renamed"
+              }
+            ]
+        "#;
+
+        let change_set = ChangeSet::try_from(json).unwrap();
+        let change = &change_set.changes[0];
+        assert_eq!(change.filename, "a%20b.txt");
+        assert_eq!(change.previous_filename.as_deref(), Some("old%20b.txt"));
+    }
+
+    #[test]
+    fn decoding_filenames_again_changes_nothing() {
+        let mut change = Change {
+            filename: "src%2Fa%2520b.txt".to_string(),
+            contents_url: "https://api.github.com/repos/me/repo/contents/src/a%2520b.txt?ref=abc"
+                .to_string(),
+            ..Default::default()
+        };
+
+        change.decode_filenames();
+        assert_eq!(change.filename, "src/a%20b.txt");
+        change.decode_filenames();
+        assert_eq!(change.filename, "src/a%20b.txt");
+    }
+
     #[test]
     fn empty_changeset_parses() {
         let empty_json = r#"
@@ -251,6 +750,11 @@ mod tests {
                     patch: Some("@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\"".into()),
                     status: String::from("modified"),
                     previous_filename: None,
+                    changes: 4,
+                    additions: 4,
+                    deletions: 0,
+                    sha: Some(String::from("b0a3777df4afc764c34234524267970025d55467")),
+                    kind: ChangeKind::File,
                 }]
             }
         );
@@ -270,6 +774,88 @@ mod tests {
         );
     }
 
+    #[parameterized(
+    no_patch = {None, ChangeKind::File},
+    file = {Some("@@ -1 +1 @@\n-line one\n+line two"), ChangeKind::File},
+    file_mentioning_subproject = {Some("@@ -1,2 +1,2 @@\n-Subproject commit abc\n+other"), ChangeKind::File},
+    updated = {Some("@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def"), ChangeKind::Submodule { base: Some("abc".into()), new: Some("def".into()) }},
+    added = {Some("@@ -0,0 +1 @@\n+Subproject commit def"), ChangeKind::Submodule { base: None, new: Some("def".into()) }},
+    removed = {Some("@@ -1 +0,0 @@\n-Subproject commit abc"), ChangeKind::Submodule { base: Some("abc".into()), new: None }},
+    )]
+    fn change_kind_from_patch(patch: Option<&str>, expected: ChangeKind) {
+        assert_eq!(ChangeKind::from_patch(patch), expected);
+    }
+
+    #[parameterized(
+    updated = {"@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def", Some(("abc", "def"))},
+    added = {"@@ -0,0 +1 @@\n+Subproject commit def", Some(("0000000000000000000000000000000000000000", "def"))},
+    file = {"@@ -1 +1 @@\n-line one\n+line two", None},
+    )]
+    fn submodule_shas_of_change(patch: &str, expected: Option<(&str, &str)>) {
+        let change = ChangeSet::new(vec![Change {
+            patch: Some(patch.to_string()),
+            ..Default::default()
+        }])
+        .changes
+        .remove(0);
+        assert_eq!(
+            change.submodule_shas(),
+            expected.map(|(base, new)| (base.to_string(), new.to_string()))
+        );
+    }
+
+    #[test]
+    fn submodule_kind_set_when_parsed() {
+        let submodule_json = r#"
+            [
+              {
+                "filename": "vendor/lib",
+                "contents_url": "stuff",
+                "patch": "@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def",
+                "status": "modified"
+              }
+            ]
+        "#;
+
+        let changes = ChangeSet::try_from(submodule_json).unwrap().changes;
+
+        assert_eq!(
+            changes[0].kind,
+            ChangeKind::Submodule {
+                base: Some("abc".to_string()),
+                new: Some("def".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn line_counts_parsed() {
+        let counted_json = r#"
+            [
+              {
+                "filename": "src/lib.rs",
+                "contents_url": "stuff",
+                "patch": "more_stuff",
+                "status": "modified",
+                "additions": 7,
+                "deletions": 3,
+                "changes": 10
+              }
+            ]
+        "#;
+
+        let changes = ChangeSet::try_from(counted_json).unwrap().changes;
+
+        assert_eq!(
+            (
+                changes[0].additions,
+                changes[0].deletions,
+                changes[0].changes
+            ),
+            (7, 3, 10)
+        );
+    }
+
     #[test]
     fn multiple_changes_parsed() {
         let multiple_files = r#"
@@ -305,6 +891,7 @@ mod tests {
                         patch: Some("more_stuff".into()),
                         status: String::from("modified"),
                         previous_filename: None,
+                        ..Default::default()
                     },
                     Change {
                         filename: String::from("yes/no/maybe.idk"),
@@ -312,6 +899,7 @@ mod tests {
                         patch: Some("why not".into()),
                         status: String::from("modified"),
                         previous_filename: None,
+                        ..Default::default()
                     },
                     Change {
                         filename: String::from("what/when/where.stuff"),
@@ -319,6 +907,7 @@ mod tests {
                         patch: Some("I guess".into()),
                         status: String::from("modified"),
                         previous_filename: None,
+                        ..Default::default()
                     }
                 ]
             }
@@ -335,6 +924,7 @@ mod tests {
                     patch: Some("more_stuff".into()),
                     status: String::from("modified"),
                     previous_filename: None,
+                    ..Default::default()
                 },
                 Change {
                     filename: String::from("yes/no/maybe.idk"),
@@ -342,6 +932,7 @@ mod tests {
                     patch: Some("why not".into()),
                     status: String::from("modified"),
                     previous_filename: None,
+                    ..Default::default()
                 },
                 Change {
                     filename: String::from("what/when/where.stuff"),
@@ -349,6 +940,7 @@ mod tests {
                     patch: Some("I guess".into()),
                     status: String::from("modified"),
                     previous_filename: None,
+                    ..Default::default()
                 },
             ],
         };
@@ -365,6 +957,7 @@ mod tests {
                         patch: Some("more_stuff".into()),
                         status: String::from("modified"),
                         previous_filename: None,
+                        ..Default::default()
                     },
                     Change {
                         filename: String::from("yes/no/maybe.idk"),
@@ -372,12 +965,245 @@ mod tests {
                         patch: Some("why not".into()),
                         status: String::from("modified"),
                         previous_filename: None,
+                        ..Default::default()
+                    },
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn filter_status_from_changeset() {
+        let mut changeset = ChangeSet {
+            changes: vec![
+                Change {
+                    filename: String::from("Cargo.toml"),
+                    contents_url: String::from("stuff"),
+                    patch: Some("more_stuff".into()),
+                    status: String::from("modified"),
+                    previous_filename: None,
+                    ..Default::default()
+                },
+                Change {
+                    filename: String::from("yes/no/maybe.idk"),
+                    contents_url: String::from("sure"),
+                    patch: Some("why not".into()),
+                    status: String::from("added"),
+                    previous_filename: None,
+                    ..Default::default()
+                },
+                Change {
+                    filename: String::from("what/when/where.stuff"),
+                    contents_url: String::from("idk"),
+                    patch: None,
+                    status: String::from("renamed"),
+                    previous_filename: Some("what/when/there.stuff".into()),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        changeset.filter_status(&["added", "renamed"]);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: vec![
+                    Change {
+                        filename: String::from("yes/no/maybe.idk"),
+                        contents_url: String::from("sure"),
+                        patch: Some("why not".into()),
+                        status: String::from("added"),
+                        previous_filename: None,
+                        ..Default::default()
+                    },
+                    Change {
+                        filename: String::from("what/when/where.stuff"),
+                        contents_url: String::from("idk"),
+                        patch: None,
+                        status: String::from("renamed"),
+                        previous_filename: Some("what/when/there.stuff".into()),
+                        ..Default::default()
                     },
                 ]
             }
         );
     }
 
+    #[parameterized(
+    exact = {&["Cargo.toml"], &["yes/no/maybe.idk", "what/when/where.stuff"]},
+    glob = {&["**/*.idk", "*.toml"], &["what/when/where.stuff"]},
+    nothing_matched = {&["*.rs"], &["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]},
+    )]
+    fn exclude_files_with_patterns(patterns: &[&str], expected: &[&str]) {
+        let changes =
+            filenames_to_changes(&["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]);
+        let mut changeset = ChangeSet { changes };
+
+        changeset.exclude_files(patterns);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(expected)
+            },
+        );
+    }
+
+    #[parameterized(
+    star = {&["*.toml"], &["Cargo.toml"]},
+    star_does_not_cross_directories = {&["*.idk"], &[]},
+    double_star = {&["**/*.idk"], &["yes/no/maybe.idk"]},
+    double_star_prefix = {&["what/**"], &["what/when/where.stuff"]},
+    question_mark = {&["yes/no/mayb?.idk"], &["yes/no/maybe.idk"]},
+    pattern_and_exact = {&["Cargo.toml", "*/*/*.stuff"], &["Cargo.toml", "what/when/where.stuff"]},
+    unmatched_pattern = {&["*.rs"], &[]},
+    invalid_pattern = {&["[Cargo.toml"], &[]},
+    )]
+    fn filter_files_with_patterns(patterns: &[&str], expected: &[&str]) {
+        let changes =
+            filenames_to_changes(&["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]);
+        let mut changeset = ChangeSet { changes };
+
+        changeset.filter_files(patterns);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(expected)
+            },
+        );
+    }
+
+    #[parameterized(
+    without_trailing_slash = {"src", &["src/main.rs", "src/nested/lib.rs"]},
+    with_trailing_slash = {"src/", &["src/main.rs", "src/nested/lib.rs"]},
+    nested = {"src/nested", &["src/nested/lib.rs"]},
+    similar_prefix = {"src2", &["src2/main.rs"]},
+    partial_name = {"sr", &[]},
+    file_is_not_a_directory = {"src/main.rs", &[]},
+    root = {"/", &["src/main.rs", "src2/main.rs", "src/nested/lib.rs", "srcfile.rs"]},
+    )]
+    fn filter_subdir_from_changeset(subdir: &str, expected: &[&str]) {
+        let changes = filenames_to_changes(&[
+            "src/main.rs",
+            "src2/main.rs",
+            "src/nested/lib.rs",
+            "srcfile.rs",
+        ]);
+        let mut changeset = ChangeSet { changes };
+
+        changeset.filter_subdir(subdir);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(expected)
+            },
+        );
+    }
+
+    #[test]
+    fn filter_subdir_and_files_compose() {
+        let mut changeset = ChangeSet {
+            changes: filenames_to_changes(&["src/main.rs", "src/lib.rs", "src2/main.rs"]),
+        };
+
+        changeset.filter_subdir("src").filter_files(&["*/main.rs"]);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(&["src/main.rs"])
+            },
+        );
+    }
+
+    #[test]
+    fn filter_paths_not_globs() {
+        let mut changes =
+            filenames_to_changes(&["docs/[draft].md", "docs/d.md", "a*b.rs", "ab.rs"]);
+        changes.push(Change {
+            filename: "new.rs".to_string(),
+            previous_filename: Some("old.rs".to_string()),
+            ..Default::default()
+        });
+        let mut changeset = ChangeSet { changes };
+        let paths = BTreeSet::from(["docs/[draft].md", "a*b.rs", "old.rs"].map(String::from));
+
+        changeset.filter_paths(&paths);
+
+        let filenames = changeset
+            .changes
+            .iter()
+            .map(|c| c.filename.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(filenames, ["docs/[draft].md", "a*b.rs", "new.rs"]);
+    }
+
+    #[parameterized(
+    text = {"modified", Some("@@ -1 +1 @@"), 2, None, false},
+    binary = {"modified", None, 0, None, true},
+    added_binary = {"added", None, 0, Some("abc"), true},
+    removed_binary = {"removed", None, 0, Some("abc"), true},
+    added_empty = {"added", None, 0, Some(EMPTY_BLOB_SHA), false},
+    removed_empty = {"removed", None, 0, Some(EMPTY_BLOB_SHA), false},
+    large_text = {"modified", None, 20000, None, false},
+    pure_rename = {"renamed", None, 0, None, false},
+    copy = {"copied", None, 0, None, false},
+    )]
+    fn binary_files(
+        status: &str,
+        patch: Option<&str>,
+        changes: usize,
+        sha: Option<&str>,
+        expected: bool,
+    ) {
+        let change = Change {
+            status: status.to_string(),
+            patch: patch.map(String::from),
+            changes,
+            sha: sha.map(String::from),
+            ..Default::default()
+        };
+        assert_eq!(change.is_binary(), expected);
+    }
+
+    #[test]
+    fn filter_binary_from_changeset() {
+        let mut changeset = ChangeSet {
+            changes: filenames_to_changes(&["image.png", "src/main.rs", "archive.zip", "big.txt"]),
+        };
+        changeset.changes[0].patch = None;
+        changeset.changes[2].patch = None;
+        changeset.changes[3].patch = None;
+        changeset.changes[3].changes = 20000;
+
+        changeset.filter_binary();
+
+        let names = changeset
+            .changes
+            .iter()
+            .map(|c| c.filename.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["image.png", "archive.zip"]);
+    }
+
+    #[test]
+    fn filter_status_and_files_compose() {
+        let mut changeset = ChangeSet {
+            changes: filenames_to_changes(&["Cargo.toml", "yes/no/maybe.idk"]),
+        };
+        changeset.changes[1].status = String::from("added");
+
+        changeset
+            .filter_files(&["Cargo.toml", "yes/no/maybe.idk"])
+            .filter_status(&["added"]);
+
+        assert_eq!(changeset.changes.len(), 1);
+        assert_eq!(changeset.changes[0].filename, "yes/no/maybe.idk");
+    }
+
     #[parameterized(
     first = {"Cargo.toml", &["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]},
     middle = {"yes/no/maybe.idk", &["yes/no/maybe.idk", "what/when/where.stuff"]},
@@ -432,6 +1258,73 @@ mod tests {
         );
     }
 
+    #[parameterized(
+    api = { SortOrder::Api, &["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"] },
+    path = { SortOrder::Path, &["Cargo.toml", "what/when/where.stuff", "yes/no/maybe.idk"] },
+    status = { SortOrder::Status, &["yes/no/maybe.idk", "Cargo.toml", "what/when/where.stuff"] },
+    )]
+    fn sort_files(order: SortOrder, expected: &[&str]) {
+        let mut changes =
+            filenames_to_changes(&["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]);
+        changes[1].status = "added".to_string();
+        let mut changeset = ChangeSet { changes };
+
+        changeset.sort_by(order);
+
+        let filenames = changeset
+            .changes
+            .iter()
+            .map(|c| c.filename.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(filenames, expected);
+    }
+
+    #[test]
+    fn rotate_then_reverse() {
+        let changes =
+            filenames_to_changes(&["Cargo.toml", "yes/no/maybe.idk", "what/when/where.stuff"]);
+        let mut changeset = ChangeSet { changes };
+
+        changeset
+            .rotate_to("yes/no/maybe.idk")
+            .expect("Should be able to rotate to")
+            .reverse();
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(&[
+                    "Cargo.toml",
+                    "what/when/where.stuff",
+                    "yes/no/maybe.idk"
+                ])
+            },
+        );
+    }
+
+    #[test]
+    fn skip_then_limit() {
+        let changes = filenames_to_changes(&[
+            "Cargo.toml",
+            "yes/no/maybe.idk",
+            "what/when/where.stuff",
+            "README.md",
+        ]);
+        let mut changeset = ChangeSet { changes };
+
+        changeset
+            .skip_to("yes/no/maybe.idk")
+            .expect("Should be able to skip to")
+            .limit(2);
+
+        assert_eq!(
+            changeset,
+            ChangeSet {
+                changes: filenames_to_changes(&["yes/no/maybe.idk", "what/when/where.stuff"])
+            },
+        );
+    }
+
     #[test]
     fn rotate_to_non_existent_file_is_an_error() {
         let changes =
@@ -464,12 +1357,150 @@ mod tests {
             patch: Some(diff.to_string()),
             status: String::from("modified"),
             previous_filename: None,
+            ..Default::default()
         };
         let expected = format!("{EOL}line one{EOL}line two{EOL}line three{EOL}");
         change.reverse_apply(&b, &a).unwrap();
         assert_eq!(fs::read(&a).unwrap(), expected.into_bytes());
     }
 
+    #[parameterized(
+    github_patch = { "one\ntwo\n", "@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+two" },
+    marker_last = { "one\nthree", "@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+three\n\\ No newline at end of file" },
+    ending_newline = { "one\ntwo\n", "@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+two\n" },
+    )]
+    fn reverse_apply_no_newline_at_end_of_file(new: &str, patch: &str) {
+        let temp = TempDir::default().permanent();
+        let a = temp.join("a");
+        let b = temp.join("b");
+        fs::write(&b, new).unwrap();
+        let change = Change {
+            filename: "what/when/where.stuff".to_string(),
+            contents_url: "idk".to_string(),
+            patch: Some(patch.to_string()),
+            status: String::from("modified"),
+            ..Default::default()
+        };
+        // Binary so `patch` leaves the line endings alone on Windows
+        change.reverse_apply_binary(&b, &a).unwrap();
+        assert_eq!(fs::read(&a).unwrap(), b"one\ntwo".to_vec());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn reverse_apply_binary_crlf() {
+        let temp = TempDir::default().permanent();
+        let a = temp.join("a");
+        let b = temp.join("b");
+        fs::write(&b, "line one\r\nline changed\r\nline three\r\n").unwrap();
+        let diff = "@@ -1,3 +1,3 @@\r\n line one\r\n-line two\r\n+line changed\r\n line three\r\n";
+        let change = Change {
+            filename: "what/when/where.stuff".to_string(),
+            contents_url: "idk".to_string(),
+            patch: Some(diff.to_string()),
+            status: String::from("modified"),
+            previous_filename: None,
+            ..Default::default()
+        };
+        change.reverse_apply_binary(&b, &a).unwrap();
+        assert_eq!(
+            fs::read(&a).unwrap(),
+            b"line one\r\nline two\r\nline three\r\n".to_vec()
+        );
+    }
+
+    /// The number of open file descriptors of the test process
+    #[cfg(target_os = "linux")]
+    fn open_fds() -> usize {
+        fs::read_dir("/proc/self/fd").unwrap().count()
+    }
+
+    /// Set for the test process where [`descriptors_of_large_change_set`] counts descriptors
+    #[cfg(target_os = "linux")]
+    const COUNT_DESCRIPTORS: &str = "GH_DIFFTOOL_COUNT_DESCRIPTORS";
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn large_change_set_closes_descriptors() {
+        // Other tests open descriptors at the same time, so they're counted in a process of its own
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "change_set::tests::descriptors_of_large_change_set",
+                "--nocapture",
+            ])
+            .env(COUNT_DESCRIPTORS, "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"), "{stdout}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn descriptors_of_large_change_set() {
+        if std::env::var_os(COUNT_DESCRIPTORS).is_none() {
+            return;
+        }
+        let temp = TempDir::default().permanent();
+        let change_set = ChangeSet::new(
+            (0..400)
+                .map(|i| Change {
+                    filename: format!("file_{i}.txt"),
+                    patch: Some(format!(
+                        "@@ -1,2 +1,2 @@\n line one\n-line {i}\n+line changed"
+                    )),
+                    status: String::from("modified"),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+
+        let before = open_fds();
+        std::thread::scope(|scope| {
+            for changes in change_set.changes.chunks(50) {
+                let temp = &temp;
+                scope.spawn(move || {
+                    for change in changes {
+                        let new = temp.join(&change.filename);
+                        let original = temp.join(format!("base_{}", change.filename));
+                        fs::write(&new, "line one\nline changed\n").unwrap();
+                        change.reverse_apply(&new, &original).unwrap();
+                    }
+                });
+            }
+        });
+
+        // A leak is at least one descriptor for each of the 400 changes
+        let after = open_fds();
+        assert!(after < before + 10, "{before} descriptors grew to {after}");
+        assert_eq!(
+            fs::read_to_string(temp.join("base_file_399.txt")).unwrap(),
+            "line one\nline 399\n"
+        );
+    }
+
+    #[test]
+    fn reverse_apply_submodule() {
+        let temp = TempDir::default().permanent();
+        let a = temp.join("a");
+        let b = temp.join("b");
+        fs::write(&b, "Subproject commit def\n").unwrap();
+        let change = Change {
+            filename: "vendor/lib".to_string(),
+            patch: Some("@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def".into()),
+            status: String::from("modified"),
+            kind: ChangeKind::Submodule {
+                base: Some("abc".to_string()),
+                new: Some("def".to_string()),
+            },
+            ..Default::default()
+        };
+        change.reverse_apply(&b, &a).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "Subproject commit abc\n");
+    }
+
     #[test]
     fn only_deleting_lines() {
         let temp = TempDir::default().permanent();
@@ -490,6 +1521,7 @@ mod tests {
             patch: Some(diff.to_string()),
             status: String::from("modified"),
             previous_filename: None,
+            ..Default::default()
         };
         let expected = format!("{EOL}line one{EOL}line three{EOL}");
         change.reverse_apply(&b, &a).unwrap();
@@ -511,6 +1543,7 @@ mod tests {
             patch: Some(diff.to_string()),
             status: String::from("modified"),
             previous_filename: None,
+            ..Default::default()
         };
 
         let error = change.reverse_apply(&b, &a).unwrap_err();
@@ -519,6 +1552,26 @@ mod tests {
         assert!(message.starts_with(&message_start));
     }
 
+    #[test]
+    fn stale_patch_names_file() {
+        let temp = TempDir::default().permanent();
+        let a = temp.join("a");
+        let b = temp.join("b");
+        fs::write(&b, "something else\nentirely\nthan\nthe patch\n").unwrap();
+        let change = Change {
+            filename: "what/when/where.stuff".to_string(),
+            patch: Some("@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three".into()),
+            status: String::from("modified"),
+            ..Default::default()
+        };
+
+        let error = change.reverse_apply(&b, &a).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("The patch of what/when/where.stuff doesn't match its new version"));
+        assert!(error.root_cause().to_string().contains("Hunk #1 FAILED"));
+    }
+
     #[test]
     fn file_removed() {
         let temp = TempDir::default().permanent();
@@ -540,6 +1593,7 @@ mod tests {
             patch: Some(diff.to_string()),
             status: String::from("removed"),
             previous_filename: None,
+            ..Default::default()
         };
         let expected = format!("\nline one\nline two\nline three\n");
         change.reverse_apply(&b, &a).unwrap();
@@ -547,6 +1601,54 @@ mod tests {
         assert_eq!(fs::read(&b).unwrap(), "".as_bytes());
     }
 
+    #[parameterized(
+    renamed_with_changes = {"renamed", None, 3, true},
+    renamed_without_changes = {"renamed", None, 0, false},
+    renamed_with_patch = {"renamed", Some("@@ -1 +1 @@"), 2, false},
+    modified_without_patch = {"modified", None, 5, true},
+    modified_binary = {"modified", None, 0, true},
+    modified_with_patch = {"modified", Some("@@ -1 +1 @@"), 2, false},
+    added_without_patch = {"added", None, 5, false},
+    )]
+    fn needing_base_contents(status: &str, patch: Option<&str>, changes: usize, expected: bool) {
+        let change = Change {
+            status: status.to_string(),
+            patch: patch.map(str::to_string),
+            changes,
+            ..Default::default()
+        };
+        assert_eq!(change.needs_base_contents(), expected);
+    }
+
+    #[test]
+    fn base_contents_url_uses_previous_filename() {
+        let change = Change {
+            filename: "new/name.rs".to_string(),
+            previous_filename: Some("old/name.rs".to_string()),
+            contents_url: "https://api.github.com/repos/me/repo/contents/new%2Fname.rs?ref=abc123"
+                .to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            change.base_contents_url("def456").unwrap(),
+            "https://api.github.com/repos/me/repo/contents/old/name.rs?ref=def456"
+        );
+    }
+
+    #[test]
+    fn blob_url_in_same_repo() {
+        let change = Change {
+            filename: "src/main.rs".to_string(),
+            contents_url: "https://api.github.com/repos/me/repo/contents/src%2Fmain.rs?ref=abc123"
+                .to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            change.blob_url("cb71da67691cdf5f595b4e64d4feaf0bdd7798f6").unwrap(),
+            "https://api.github.com/repos/me/repo/git/blobs/cb71da67691cdf5f595b4e64d4feaf0bdd7798f6"
+        );
+    }
+
     #[test]
     fn no_patch() {
         let temp = TempDir::default().permanent();
@@ -567,9 +1669,29 @@ mod tests {
             patch: None,
             status: String::from("renamed"),
             previous_filename: Some("foo/bar/baz/me.txt".into()),
+            ..Default::default()
         };
         let expected = format!("\nline one\nline two\nline three\n");
         change.reverse_apply(&b, &a).unwrap();
         assert_eq!(fs::read(&a).unwrap(), expected.into_bytes());
     }
+
+    #[test]
+    fn empty_patch() {
+        let temp = TempDir::default().permanent();
+        let a = temp.join("a");
+        let b = temp.join("b");
+        let contents = "line one\nline two\n";
+        fs::write(&b, contents).unwrap();
+
+        let change = Change {
+            filename: "what/when/where.stuff".to_string(),
+            contents_url: "idk".to_string(),
+            patch: Some("".into()),
+            status: String::from("modified"),
+            ..Default::default()
+        };
+        change.reverse_apply(&b, &a).unwrap();
+        assert_eq!(fs::read(&a).unwrap(), contents.as_bytes());
+    }
 }
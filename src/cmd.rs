@@ -15,6 +15,7 @@ pub trait Cmd {
     fn arg(&mut self, arg: OsString) -> &mut Self;
     fn stdout(&mut self, cfg: Stdio) -> &mut Self;
     fn stderr(&mut self, cfg: Stdio) -> &mut Self;
+    fn env(&mut self, key: OsString, val: OsString) -> &mut Self;
     fn output(&mut self) -> io::Result<Output>;
     fn new_from_self(&self) -> Self;
 }
@@ -29,6 +30,9 @@ impl Cmd for Command {
     fn stderr(&mut self, cfg: Stdio) -> &mut Self {
         self.stderr(cfg)
     }
+    fn env(&mut self, key: OsString, val: OsString) -> &mut Self {
+        self.env(key, val)
+    }
     fn output(&mut self) -> io::Result<Output> {
         self.output()
     }
@@ -0,0 +1,135 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! The changes of a pull request as comma separated values, for tracking a review in a
+//! spreadsheet
+
+use crate::change_set::ChangeSet;
+
+const HEADER: [&str; 6] = [
+    "filename",
+    "previous_filename",
+    "status",
+    "additions",
+    "deletions",
+    "sha",
+];
+
+/// One row per change of `change_set`, after a header row
+///
+/// Fields which are missing, like the `previous_filename` of a file that wasn't renamed, are
+/// left empty.
+pub fn change_set_csv(change_set: &ChangeSet) -> String {
+    let mut csv = row(&HEADER);
+    for change in &change_set.changes {
+        csv.push_str(&row(&[
+            &change.filename,
+            change.previous_filename.as_deref().unwrap_or_default(),
+            &change.status,
+            &change.additions.to_string(),
+            &change.deletions.to_string(),
+            change.sha.as_deref().unwrap_or_default(),
+        ]));
+    }
+    csv
+}
+
+/// The `fields` as a line of CSV, with the line ending RFC 4180 uses
+fn row(fields: &[&str]) -> String {
+    let fields = fields.iter().map(|field| quote(field)).collect::<Vec<_>>();
+    format!("{}\r\n", fields.join(","))
+}
+
+/// Quote `field` when it contains a character that would break up the row
+///
+/// Quotes within a quoted field are escaped by doubling them.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change_set::Change;
+    use yare::parameterized;
+
+    #[parameterized(
+    plain = { "src/main.rs", "src/main.rs" },
+    comma = { "a,b.txt", "\"a,b.txt\"" },
+    double_quote = { "say \"hi\".txt", "\"say \"\"hi\"\".txt\"" },
+    newline = { "two\nlines", "\"two\nlines\"" },
+    )]
+    fn quoted_field(field: &str, expected: &str) {
+        assert_eq!(quote(field), expected);
+    }
+
+    #[test]
+    fn change_set_as_csv() {
+        let change_set = ChangeSet {
+            changes: vec![
+                Change {
+                    filename: "src/main.rs".to_string(),
+                    status: "modified".to_string(),
+                    additions: 3,
+                    deletions: 1,
+                    sha: Some("abc123".to_string()),
+                    ..Default::default()
+                },
+                Change {
+                    filename: "docs/a,b.md".to_string(),
+                    previous_filename: Some("docs/old.md".to_string()),
+                    status: "renamed".to_string(),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let csv = change_set_csv(&change_set);
+        let rows = csv
+            .split_terminator("\r\n")
+            .map(split_row)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                vec![
+                    "filename",
+                    "previous_filename",
+                    "status",
+                    "additions",
+                    "deletions",
+                    "sha"
+                ],
+                vec!["src/main.rs", "", "modified", "3", "1", "abc123"],
+                vec!["docs/a,b.md", "docs/old.md", "renamed", "0", "0", ""],
+            ]
+        );
+    }
+
+    /// Split a row of CSV into its fields, a minimal parser that undoes [`quote`]
+    fn split_row(line: &str) -> Vec<String> {
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(std::mem::take(&mut field)),
+                _ => field.push(c),
+            }
+        }
+        fields.push(field);
+        fields
+    }
+}
@@ -5,103 +5,1022 @@
 
 //! Launches a difftool to compare changes
 
+use crate::change_set::{submodule_contents, ChangeKind, Reconstruction};
 use crate::gh_interface;
-use crate::git_config;
+use crate::git_config::{self, DriverArgs};
+use crate::suggestions::{self, Suggestion};
 use crate::Change;
-use anyhow::Result;
-use std::ffi::OsString;
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tempfile::{Builder, TempDir};
+use tokio::process::Command;
+
+#[derive(Debug, displaydoc::Display, Eq, PartialEq)]
+pub enum Error {
+    /// The temporary directory name must be a single directory name, not a path: {0}
+    TempName(String),
+}
+
+impl std::error::Error for Error {}
 
 #[derive(Debug)]
 pub struct Diff {
     difftool: git_config::Difftool,
     temp_dir: TempDir,
+    base_sha: Option<String>,
+    merge_sha: Option<String>,
+    working_tree: Option<PathBuf>,
+    base_blobs: HashMap<String, String>,
+    verify_renames: bool,
+    binary_patch: bool,
+    crlf_normalize: bool,
+    no_reverse: bool,
+    hostname: Option<String>,
+    retries: u32,
+    strict: bool,
+    prev_head: Option<String>,
+    head_sha: Option<String>,
+    /// The commit to get the new version of files at, instead of the current head
+    pinned_head: Option<String>,
+    base_ref: Option<String>,
+    open_with_default: Vec<String>,
+    suggestions: Option<HashMap<String, Vec<Suggestion>>>,
+    /// The tool for files larger than the threshold, in bytes
+    large_file_tool: Option<(u64, git_config::Difftool)>,
+    /// The difftool for files with an extension, keyed by the lowercase extension without a dot
+    extension_tools: HashMap<String, git_config::Difftool>,
+    /// The git file mode of each file in the pull request, keyed by filename
+    file_modes: HashMap<String, String>,
 }
 
-#[derive(Debug)]
+/// Where the base version of a file comes from
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BaseSource {
+    /// Reconstructed from the new version, see [`Explanation::reconstruction`]
+    Reconstructed,
+    /// Downloaded from a commit, like the base commit or a previous head
+    Download,
+    /// Downloaded as a blob from `--base-blob`
+    Blob,
+    /// The file in the working tree
+    WorkingTree,
+    /// The head version, to show the suggestions as the changes
+    Head,
+}
+
+/// How a file is diffed, for `--explain`
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct Explanation {
+    pub filename: String,
+    pub previous_filename: Option<String>,
+    pub status: String,
+    pub has_patch: bool,
+    pub submodule: bool,
+    pub binary: bool,
+    pub rename: bool,
+    pub base: BaseSource,
+    /// How the base is reconstructed, only when it is
+    pub reconstruction: Option<Reconstruction>,
+    /// The temporary file for the base version
+    pub local: PathBuf,
+    /// The temporary file for the new version
+    pub remote: PathBuf,
+}
+
+/// What the two versions of a file are shown with
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Viewer {
+    /// The difftool from git
+    #[default]
+    Difftool,
+    /// The default application of the OS for the file type, each version opened on its own
+    OsDefault,
+}
+
+#[derive(Clone, Debug)]
 pub struct Difftool<'a> {
     tool: &'a git_config::Difftool,
+    filename: String,
     local: OsString,
     remote: OsString,
+    temp_dir: &'a Path,
+    viewer: Viewer,
+    /// The arguments for a `diff.external` driver, when the tool is one
+    driver_args: Option<DriverArgs>,
 }
 
 impl<'a> Difftool<'a> {
-    fn new(tool: &'a git_config::Difftool, local: OsString, remote: OsString) -> Self {
+    fn new(
+        tool: &'a git_config::Difftool,
+        filename: String,
+        local: OsString,
+        remote: OsString,
+        temp_dir: &'a Path,
+    ) -> Self {
         Self {
             tool,
+            filename,
             local,
             remote,
+            temp_dir,
+            viewer: Viewer::Difftool,
+            driver_args: None,
+        }
+    }
+
+    /// The name of the file in the pull request being diffed
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The name of the difftool that will be launched
+    pub fn tool_name(&self) -> &str {
+        match self.viewer {
+            Viewer::Difftool => self.tool.name(),
+            Viewer::OsDefault => "the default application",
         }
     }
 
     pub async fn launch(&self) -> Result<()> {
-        self.tool.launch(&self.local, &self.remote).await
+        match self.viewer {
+            Viewer::Difftool => match &self.driver_args {
+                Some(driver_args) => self.tool.launch_external(driver_args).await,
+                None => self.tool.launch(&self.local, &self.remote).await,
+            },
+            Viewer::OsDefault => {
+                for file in [&self.local, &self.remote] {
+                    let mut command = os_open_command(file);
+                    log::debug!("Launching {}", git_config::command_line(&command));
+                    let status = command.status().await?;
+                    // Explorer exits with 1 even when it opened the file
+                    if !status.success() && !cfg!(windows) {
+                        return Err(anyhow!(
+                            "Failed to open {file:?} with the default application, {status}"
+                        ));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The command line [`Difftool::launch`] would run, two commands joined by `;` when each
+    /// version is opened on its own
+    pub fn command_line(&self) -> String {
+        match self.viewer {
+            Viewer::Difftool => match &self.driver_args {
+                Some(driver_args) => self.tool.external_command_line(driver_args),
+                None => self.tool.command_line(&self.local, &self.remote),
+            },
+            Viewer::OsDefault => [&self.local, &self.remote]
+                .map(|file| git_config::command_line(&os_open_command(file)))
+                .join("; "),
+        }
+    }
+
+    /// Copy the local and remote files into `dir`
+    ///
+    /// The files keep the same layout they have in the temporary directory, so the base version
+    /// will be under `local/` and the new version under `remote/`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to copy the files into
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        for file in [&self.local, &self.remote] {
+            let file = Path::new(file);
+            let dest = dir.join(file.strip_prefix(self.temp_dir)?);
+            fs::create_dir_all(
+                dest.parent()
+                    .expect("Should always have a parent save path"),
+            )?;
+            fs::copy(file, dest)?;
+        }
+        Ok(())
     }
 }
 
 impl Diff {
     pub fn new(difftool: git_config::Difftool) -> Result<Self> {
-        let temp_dir = Builder::new().prefix("gh-difftool").tempdir()?;
-        Ok(Self { difftool, temp_dir })
+        Self::new_in(difftool, std::env::temp_dir())
+    }
+
+    /// Create with the temporary directory in `root` instead of the system temporary directory
+    pub fn new_in(difftool: git_config::Difftool, root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let temp_dir = Builder::new()
+            .prefix("gh-difftool")
+            .tempdir_in(root)
+            .with_context(|| format!("Failed to create a temporary directory in {root:?}"))?;
+        Ok(Self::from_temp_dir(difftool, temp_dir))
+    }
+
+    /// Create using a fixed temporary directory name instead of a random one
+    ///
+    /// # Arguments
+    /// * `difftool` - The difftool to launch for each change
+    /// * `root` - The directory to create the temporary directory in
+    /// * `name` - The name of the directory to create in `root`
+    /// * `force` - Replace the directory if it already exists
+    ///
+    /// # Errors
+    /// When `name` is a path instead of a single directory name, so `force` can only ever remove a
+    /// directory in `root`, or when the directory already exists and `force` is false
+    pub fn new_with_temp_name(
+        difftool: git_config::Difftool,
+        root: impl AsRef<Path>,
+        name: impl AsRef<str>,
+        force: bool,
+    ) -> Result<Self> {
+        let root = root.as_ref();
+        let name = name.as_ref();
+        let mut components = Path::new(name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(Error::TempName(name.to_string()).into());
+        }
+        let path = root.join(name);
+        if force && path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        let temp_dir = Builder::new()
+            .prefix(name)
+            .rand_bytes(0)
+            .tempdir_in(root)
+            .with_context(|| format!("Failed to create temporary directory {path:?}"))?;
+        Ok(Self::from_temp_dir(difftool, temp_dir))
+    }
+
+    /// Keep the temporary directory, and the files in it, instead of deleting it when dropped
+    ///
+    /// # Returns
+    /// The path to the temporary directory
+    pub fn keep(self) -> PathBuf {
+        self.temp_dir.keep()
+    }
+
+    fn from_temp_dir(difftool: git_config::Difftool, temp_dir: TempDir) -> Self {
+        Self {
+            difftool,
+            temp_dir,
+            base_sha: None,
+            merge_sha: None,
+            working_tree: None,
+            base_blobs: HashMap::new(),
+            verify_renames: false,
+            binary_patch: false,
+            crlf_normalize: true,
+            no_reverse: false,
+            hostname: None,
+            retries: gh_interface::DEFAULT_RETRIES,
+            strict: false,
+            prev_head: None,
+            head_sha: None,
+            pinned_head: None,
+            base_ref: None,
+            open_with_default: vec![],
+            suggestions: None,
+            large_file_tool: None,
+            extension_tools: HashMap::new(),
+            file_modes: HashMap::new(),
+        }
+    }
+
+    /// Set the base commit to download base files from when they can't be derived from the patch
+    pub fn with_base_sha(mut self, base_sha: Option<String>) -> Self {
+        self.base_sha = base_sha;
+        self
+    }
+
+    /// Set the merge commit to diff against the base commit, previewing the merged result
+    ///
+    /// Requires the base commit to also be set.
+    pub fn with_merge_sha(mut self, merge_sha: Option<String>) -> Self {
+        self.merge_sha = merge_sha;
+        self
+    }
+
+    /// Use the files in the working tree at `root` as the local version instead of the base
+    pub fn with_working_tree(mut self, root: Option<PathBuf>) -> Self {
+        self.working_tree = root;
+        self
+    }
+
+    /// Use the blob with the given sha as the base version of a file, keyed by filename
+    pub fn with_base_blobs(mut self, base_blobs: HashMap<String, String>) -> Self {
+        self.base_blobs = base_blobs;
+        self
+    }
+
+    /// Always download the base version of renamed and copied files instead of assuming it
+    /// matches the new version
+    ///
+    /// Requires the base commit to also be set.
+    pub fn with_verify_renames(mut self, verify_renames: bool) -> Self {
+        self.verify_renames = verify_renames;
+        self
+    }
+
+    /// Pass `--binary` to the system `patch` so line endings are not translated
+    pub fn with_binary_patch(mut self, binary_patch: bool) -> Self {
+        self.binary_patch = binary_patch;
+        self
+    }
+
+    /// Keep the line endings of the base version as they are in the patch on Windows, matching
+    /// the new version from GitHub
+    ///
+    /// `patch` on Windows writes CRLF line endings, while the new version keeps the LF line endings
+    /// from GitHub, making every line show as changed. Disabling this leaves the line endings to
+    /// `patch`.
+    pub fn with_crlf_normalize(mut self, crlf_normalize: bool) -> Self {
+        self.crlf_normalize = crlf_normalize;
+        self
+    }
+
+    /// Use the new version of each file as the base version instead of reverse applying the patch
+    ///
+    /// A debugging aid, the difftool should show no differences. If it does show differences the
+    /// download is at fault, otherwise the reverse apply is.
+    pub fn with_no_reverse(mut self, no_reverse: bool) -> Self {
+        self.no_reverse = no_reverse;
+        self
+    }
+
+    /// Download the files from `hostname`, e.g. a GitHub Enterprise server, instead of the
+    /// default host of `gh`
+    pub fn with_hostname(mut self, hostname: Option<String>) -> Self {
+        self.hostname = hostname;
+        self
+    }
+
+    /// Retry a download that fails with a rate limit or server error up to `retries` times
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Fail a download whose contents don't match the blob sha GitHub gives for the file,
+    /// instead of only warning about it
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the head commit of the pull request
+    pub fn with_head_sha(mut self, head_sha: Option<String>) -> Self {
+        self.head_sha = head_sha;
+        self
+    }
+
+    /// Get the new version of files at `pinned_head` instead of the current head of the pull
+    /// request
+    ///
+    /// Keeps the files consistent when the pull request is updated in the middle of a review.
+    pub fn with_pinned_head(mut self, pinned_head: Option<String>) -> Self {
+        self.pinned_head = pinned_head;
+        self
+    }
+
+    /// Diff the files at a previous head commit of the pull request against the current head
+    ///
+    /// Requires the head commit to also be set.
+    pub fn with_prev_head(mut self, prev_head: Option<String>) -> Self {
+        self.prev_head = prev_head;
+        self
+    }
+
+    /// Diff the files at `base_ref`, any commit, branch, or tag, against the pull request instead
+    /// of the base of the pull request
+    pub fn with_base_ref(mut self, base_ref: Option<String>) -> Self {
+        self.base_ref = base_ref;
+        self
+    }
+
+    /// Open both versions of files with one of the `extensions` in the default application of
+    /// the OS instead of the difftool
+    ///
+    /// Useful for files like images or PDFs which a difftool may not be able to show.
+    pub fn with_open_with_default(mut self, extensions: Vec<String>) -> Self {
+        self.open_with_default = extensions;
+        self
+    }
+
+    /// Set the git file mode, e.g. "100755", of each file keyed by filename
+    ///
+    /// On Unix the new version of an executable file is made executable, which carries over to
+    /// the saved files. A `diff.external` driver is given the mode of the new version.
+    pub fn with_file_modes(mut self, file_modes: HashMap<String, String>) -> Self {
+        self.file_modes = file_modes;
+        self
+    }
+
+    /// Use `tool` instead of the difftool for files whose new version is larger than `threshold`
+    /// bytes
+    ///
+    /// Useful for a terminal diff on files which are slow to show in a GUI difftool.
+    pub fn with_large_file_tool(
+        mut self,
+        large_file_tool: Option<(u64, git_config::Difftool)>,
+    ) -> Self {
+        self.large_file_tool = large_file_tool;
+        self
+    }
+
+    /// Use a different difftool for files with an extension, the `tools` are keyed by extension
+    ///
+    /// Useful for an image diff tool on images while text files use the usual difftool. A tool
+    /// for an extension takes precedence over the tool for large files.
+    pub fn with_extension_tools(mut self, tools: HashMap<String, git_config::Difftool>) -> Self {
+        self.extension_tools = tools
+            .into_iter()
+            .map(|(extension, tool)| {
+                let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+                (extension, tool)
+            })
+            .collect();
+        self
+    }
+
+    /// Diff the head version of each file against the version with the suggested changes
+    /// applied, the suggestions are keyed by filename
+    pub fn with_suggestions(
+        mut self,
+        suggestions: Option<HashMap<String, Vec<Suggestion>>>,
+    ) -> Self {
+        self.suggestions = suggestions;
+        self
+    }
+
+    fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// Create the [`Difftool`] for each of the `changes`
+    ///
+    /// At most `jobs` changes are downloaded and reconstructed at once, to avoid tripping GitHub's
+    /// secondary rate limits and to bound the open files and `patch` processes. The difftools are
+    /// always yielded in the same order as the `changes`.
+    pub fn difftools(
+        &self,
+        changes: Vec<Change>,
+        jobs: usize,
+    ) -> impl Stream<Item = Result<Difftool<'_>>> + '_ {
+        stream::iter(changes)
+            .map(|change| self.difftool(change))
+            .buffered(jobs)
+    }
+
+    pub async fn difftool(&self, change: Change) -> Result<Difftool<'_>> {
+        let viewer = self.viewer(&change.filename);
+        let filename = change.filename.clone();
+        let mut difftool = self
+            .download(&change)
+            .await
+            .with_context(|| format!("Failed to fetch contents for {filename}"))?;
+        difftool.viewer = viewer;
+        #[cfg(unix)]
+        self.set_mode(&change, &difftool.remote)?;
+        if let Some(tool) = self.extension_tool(&change.filename) {
+            difftool.tool = tool;
+        } else if let Some((threshold, tool)) = &self.large_file_tool {
+            if fs::metadata(&difftool.remote)?.len() > *threshold {
+                difftool.tool = tool;
+            }
+        }
+        if difftool.tool.is_external() {
+            difftool.driver_args = Some(self.driver_args(&change, &difftool));
+        }
+        Ok(difftool)
+    }
+
+    /// The arguments git would give a `diff.external` driver for `change`
+    ///
+    /// The base blob sha is only known when it's been looked up, otherwise the null sha is used
+    /// like git does for a file that isn't in the object store. Only the mode of the new version
+    /// is known, from the file modes, the base version is taken to be a regular file.
+    fn driver_args(&self, change: &Change, difftool: &Difftool) -> DriverArgs {
+        const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+        let submodule_shas = change.submodule_shas();
+        let mode = match submodule_shas {
+            Some(_) => "160000",
+            None => "100644",
+        };
+        let new_mode = match submodule_shas {
+            Some(_) => mode,
+            None => self
+                .file_modes
+                .get(&change.filename)
+                .map_or(mode, String::as_str),
+        };
+        let (old_hex, new_hex) = submodule_shas.unwrap_or_else(|| {
+            (
+                self.base_blobs
+                    .get(&change.filename)
+                    .map_or(NULL_SHA, String::as_str)
+                    .to_string(),
+                change.sha.as_deref().unwrap_or(NULL_SHA).to_string(),
+            )
+        });
+        let mut args = DriverArgs {
+            path: change.filename.clone(),
+            old_file: difftool.local.clone(),
+            old_hex,
+            old_mode: mode.to_string(),
+            new_file: difftool.remote.clone(),
+            new_hex,
+            new_mode: new_mode.to_string(),
+        };
+        // Like git, the side a file doesn't exist on is `/dev/null` with no sha or mode
+        match change.status.as_str() {
+            "added" => {
+                args.old_file = "/dev/null".into();
+                args.old_hex = ".".to_string();
+                args.old_mode = ".".to_string();
+            }
+            "removed" => {
+                args.new_file = "/dev/null".into();
+                args.new_hex = ".".to_string();
+                args.new_mode = ".".to_string();
+            }
+            _ => (),
+        }
+        args
+    }
+
+    /// Diff `new`, a local file, against the base version from reverse applying `patch` to it
+    ///
+    /// Nothing is downloaded, the file keeps only its name in the temporary directory.
+    pub fn local_patch(&self, new: impl AsRef<Path>, patch: String) -> Result<Difftool<'_>> {
+        let new = new.as_ref();
+        let filename = new
+            .file_name()
+            .ok_or_else(|| anyhow!("{new:?} is not a file"))?
+            .to_string_lossy()
+            .to_string();
+        let change = Change {
+            filename,
+            patch: Some(patch),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let remote = self.temp_new_path(&change)?;
+        fs::copy(new, &remote).with_context(|| format!("Failed to read {new:?}"))?;
+        let original = self.create_temp_original(&change, &remote)?;
+        Ok(Difftool::new(
+            &self.difftool,
+            change.filename.clone(),
+            original.into_os_string(),
+            remote.into_os_string(),
+            self.temp_dir.as_ref(),
+        ))
+    }
+
+    /// Make `file`, the new version of `change`, executable when it is in the pull request
+    #[cfg(unix)]
+    fn set_mode(&self, change: &Change, file: impl AsRef<Path>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        if self.file_modes.get(&change.filename).map(String::as_str) != Some("100755") {
+            return Ok(());
+        }
+        let mut permissions = fs::metadata(&file)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        fs::set_permissions(file, permissions)?;
+        Ok(())
+    }
+
+    /// The difftool for the extension of `filename`, when one was given for it
+    fn extension_tool(&self, filename: &str) -> Option<&git_config::Difftool> {
+        let extension = Path::new(filename).extension()?.to_str()?;
+        self.extension_tools.get(&extension.to_ascii_lowercase())
+    }
+
+    /// What to show the versions of `filename` with, based on its extension
+    fn viewer(&self, filename: &str) -> Viewer {
+        let extension = Path::new(filename).extension().and_then(OsStr::to_str);
+        let open_with_default = extension.is_some_and(|extension| {
+            self.open_with_default.iter().any(|other| {
+                other
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(extension)
+            })
+        });
+        if open_with_default {
+            Viewer::OsDefault
+        } else {
+            Viewer::Difftool
+        }
+    }
+
+    /// How `change` would be diffed, without downloading anything
+    pub fn explain(&self, change: &Change) -> Explanation {
+        let base = self.base_source(change);
+        Explanation {
+            filename: change.filename.clone(),
+            previous_filename: change.previous_filename.clone(),
+            status: change.status.clone(),
+            has_patch: change.patch.is_some(),
+            submodule: change.submodule_shas().is_some(),
+            binary: change.is_binary(),
+            rename: change.is_rename(),
+            base,
+            reconstruction: (base == BaseSource::Reconstructed).then(|| change.reconstruction()),
+            local: self.original_path(change),
+            remote: self.new_path(change),
+        }
+    }
+
+    /// Where the base version of `change` comes from, [`Diff::download`] gets it from here
+    fn base_source(&self, change: &Change) -> BaseSource {
+        if self.merge_sha.is_some() || self.prev_head.is_some() {
+            return BaseSource::Download;
+        }
+        if self.working_tree.is_some() {
+            return BaseSource::WorkingTree;
+        }
+        if self.base_ref.is_some() {
+            return BaseSource::Download;
+        }
+        if self.suggestions.is_some() {
+            return BaseSource::Head;
+        }
+        let needs_base_contents =
+            change.needs_base_contents() || (self.verify_renames && change.is_rename());
+        if self.base_blobs.contains_key(&change.filename) {
+            BaseSource::Blob
+        } else if needs_base_contents && self.base_sha.is_some() {
+            BaseSource::Download
+        } else {
+            BaseSource::Reconstructed
+        }
+    }
+
+    /// Download the versions of the file in `change` to diff, the base from where
+    /// [`Diff::base_source`] says
+    async fn download(&self, change: &Change) -> Result<Difftool<'_>> {
+        let source = self.base_source(change);
+        match source {
+            BaseSource::Download => {
+                if let Some(merge_sha) = &self.merge_sha {
+                    return self.merged_preview(change, merge_sha).await;
+                }
+                if let Some(prev_head) = &self.prev_head {
+                    return self.interdiff(change, prev_head).await;
+                }
+                if let Some(base_ref) = &self.base_ref {
+                    return self.base_ref_diff(change, base_ref).await;
+                }
+            }
+            BaseSource::WorkingTree => {
+                let root = self
+                    .working_tree
+                    .as_deref()
+                    .expect("Set for a working tree base");
+                return self.working_tree_diff(change, root).await;
+            }
+            BaseSource::Head => {
+                let suggestions = self
+                    .suggestions
+                    .as_ref()
+                    .and_then(|suggestions| suggestions.get(&change.filename))
+                    .map_or(&[][..], Vec::as_slice);
+                return self.suggestions_preview(change, suggestions).await;
+            }
+            BaseSource::Blob | BaseSource::Reconstructed => {}
+        }
+
+        let new = self.new_file_contents(change).await?;
+        let original = match source {
+            BaseSource::Blob => {
+                let sha = &self.base_blobs[&change.filename];
+                self.base_blob_contents(change, sha).await?
+            }
+            BaseSource::Download => {
+                let base_sha = self.base_sha.as_deref().expect("Set for a base download");
+                self.base_file_contents(change, base_sha).await?
+            }
+            _ => self.create_temp_original(change, &new)?,
+        };
+        Ok(Difftool::new(
+            &self.difftool,
+            change.filename.clone(),
+            original.into_os_string(),
+            new.into_os_string(),
+            self.temp_dir.as_ref(),
+        ))
     }
 
-    pub async fn difftool(&self, change: Change) -> Result<Difftool> {
-        let new = self.new_file_contents(&change).await?;
-        let original = self.create_temp_original(&change, &new)?;
+    /// Diff the version of the file in the merge commit against the base commit
+    ///
+    /// Both versions are downloaded so that changes made to the base branch since the pull request
+    /// was created are shown as well.
+    async fn merged_preview(&self, change: &Change, merge_sha: &str) -> Result<Difftool<'_>> {
+        let base_sha = self
+            .base_sha
+            .as_deref()
+            .ok_or_else(|| anyhow!("A base commit is required to preview the merge"))?;
+        self.revisions_diff(change, base_sha, merge_sha).await
+    }
+
+    /// Diff the version of the file at the previous head commit against the current head commit
+    async fn interdiff(&self, change: &Change, prev_head: &str) -> Result<Difftool<'_>> {
+        let head_sha = self
+            .head_sha
+            .as_deref()
+            .ok_or_else(|| anyhow!("A head commit is required for an interdiff"))?;
+        self.revisions_diff(change, prev_head, head_sha).await
+    }
+
+    /// Diff the version of the file at `original_ref` against the version at `new_ref`
+    ///
+    /// Files which are added have no original version and files which are removed have no new
+    /// version, these are diffed against an empty file.
+    async fn revisions_diff(
+        &self,
+        change: &Change,
+        original_ref: &str,
+        new_ref: &str,
+    ) -> Result<Difftool<'_>> {
+        let new = self.temp_new_path(change)?;
+        let new_contents = match change.status.as_str() {
+            "removed" => Vec::new(),
+            _ => {
+                let url = change.contents_url_at(&change.filename, new_ref)?;
+                gh_interface::url_contents(&url, self.hostname(), self.retries).await?
+            }
+        };
+        fs::write(&new, new_contents)?;
+
+        let original = self.temp_original_path(change)?;
+        let original_contents = match change.status.as_str() {
+            "added" => Vec::new(),
+            _ => {
+                let url = change.base_contents_url(original_ref)?;
+                gh_interface::url_contents(&url, self.hostname(), self.retries).await?
+            }
+        };
+        fs::write(&original, original_contents)?;
+
+        Ok(Difftool::new(
+            &self.difftool,
+            change.filename.clone(),
+            original.into_os_string(),
+            new.into_os_string(),
+            self.temp_dir.as_ref(),
+        ))
+    }
+
+    /// Diff the file in the working tree against the version in the pull request
+    ///
+    /// Files that don't exist in the working tree, or were removed in the pull request, will be
+    /// diffed against an empty file.
+    async fn working_tree_diff(&self, change: &Change, root: &Path) -> Result<Difftool<'_>> {
+        let new = match change.status.as_str() {
+            // The contents url of removed files points to the old version of the file
+            "removed" => {
+                let file = self.temp_new_path(change)?;
+                fs::write(&file, "")?;
+                file
+            }
+            _ => self.new_file_contents(change).await?,
+        };
+
+        let original = self.temp_original_path(change)?;
+        let local = root.join(&change.filename);
+        if local.exists() {
+            fs::copy(&local, &original)?;
+        } else {
+            fs::write(&original, "")?;
+        }
+
+        Ok(Difftool::new(
+            &self.difftool,
+            change.filename.clone(),
+            original.into_os_string(),
+            new.into_os_string(),
+            self.temp_dir.as_ref(),
+        ))
+    }
+
+    /// Diff the version of the file at `base_ref` against the version in the pull request
+    ///
+    /// Files that don't exist at `base_ref`, or were removed in the pull request, will be diffed
+    /// against an empty file.
+    async fn base_ref_diff(&self, change: &Change, base_ref: &str) -> Result<Difftool<'_>> {
+        let new = match change.status.as_str() {
+            // The contents url of removed files points to the old version of the file
+            "removed" => {
+                let file = self.temp_new_path(change)?;
+                fs::write(&file, "")?;
+                file
+            }
+            _ => self.new_file_contents(change).await?,
+        };
+
+        let original = self.temp_original_path(change)?;
+        let url = change.base_contents_url(base_ref)?;
+        let contents = gh_interface::url_contents_if_exists(&url, self.hostname(), self.retries)
+            .await?
+            .unwrap_or_default();
+        fs::write(&original, contents)?;
+
+        Ok(Difftool::new(
+            &self.difftool,
+            change.filename.clone(),
+            original.into_os_string(),
+            new.into_os_string(),
+            self.temp_dir.as_ref(),
+        ))
+    }
+
+    /// Diff the head version of the file against it with the `suggestions` applied
+    ///
+    /// The head version is used as the local version so the suggestions show as the changes.
+    async fn suggestions_preview(
+        &self,
+        change: &Change,
+        suggestions: &[Suggestion],
+    ) -> Result<Difftool<'_>> {
+        let head = gh_interface::file_contents(
+            change,
+            self.pinned_head.as_deref(),
+            self.hostname(),
+            self.retries,
+            self.strict,
+        )
+        .await?;
+        let head = String::from_utf8(head).with_context(|| {
+            format!("Can't apply suggestions to binary file {}", change.filename)
+        })?;
+
+        let original = self.temp_original_path(change)?;
+        fs::write(&original, &head)?;
+        let new = self.temp_new_path(change)?;
+        fs::write(&new, suggestions::apply(&head, suggestions))?;
+
         Ok(Difftool::new(
             &self.difftool,
+            change.filename.clone(),
             original.into_os_string(),
             new.into_os_string(),
+            self.temp_dir.as_ref(),
         ))
     }
 
+    /// Whether the base version reconstructed from the patch of `change` matches the actual base
+    ///
+    /// A self check of reverse applying patches. Only changes whose base is reverse patched, see
+    /// [`Change::reconstruction`], are checked, others are considered matching.
+    ///
+    /// # Errors
+    /// When there is no base commit to download the actual base from
+    pub async fn reconstruction_matches(&self, change: &Change) -> Result<bool> {
+        if change.reconstruction() != Reconstruction::ReversePatch {
+            return Ok(true);
+        }
+        let base_sha = self
+            .base_sha
+            .as_deref()
+            .ok_or_else(|| anyhow!("A base commit is required to audit the reconstruction"))?;
+        let new = self.new_file_contents(change).await?;
+        let original = self.create_temp_original(change, &new)?;
+        // An added file doesn't exist at the base, reverse patching should leave nothing
+        let actual = match change.status.as_str() {
+            "added" => vec![],
+            _ => {
+                gh_interface::url_contents(
+                    &change.base_contents_url(base_sha)?,
+                    self.hostname(),
+                    self.retries,
+                )
+                .await?
+            }
+        };
+        Ok(fs::read(original)? == actual)
+    }
+
     async fn new_file_contents(&self, change: &Change) -> Result<PathBuf> {
-        let dir = self.temp_dir.as_ref();
-        let file = dir.join(&change.filename);
-        fs::create_dir_all(
-            file.parent()
-                .expect("Should always have a parent temp path"),
-        )?;
+        let file = self.temp_new_path(change)?;
+        let contents = match &change.kind {
+            ChangeKind::Submodule { new, .. } => submodule_contents(new.as_deref()).into_bytes(),
+            ChangeKind::File => {
+                gh_interface::file_contents(
+                    change,
+                    self.pinned_head.as_deref(),
+                    self.hostname(),
+                    self.retries,
+                    self.strict,
+                )
+                .await?
+            }
+        };
+        fs::write(&file, contents)?;
+        Ok(file)
+    }
+
+    async fn base_file_contents(&self, change: &Change, base_sha: &str) -> Result<PathBuf> {
+        let file = self.temp_original_path(change)?;
+        let contents = gh_interface::url_contents(
+            &change.base_contents_url(base_sha)?,
+            self.hostname(),
+            self.retries,
+        )
+        .await?;
+        fs::write(&file, contents)?;
+        Ok(file)
+    }
 
-        let contents = gh_interface::file_contents(change).await?;
+    async fn base_blob_contents(&self, change: &Change, sha: &str) -> Result<PathBuf> {
+        let file = self.temp_original_path(change)?;
+        let contents =
+            gh_interface::url_contents(&change.blob_url(sha)?, self.hostname(), self.retries)
+                .await?;
         fs::write(&file, contents)?;
         Ok(file)
     }
 
     fn create_temp_original(&self, change: &Change, new: impl AsRef<Path>) -> Result<PathBuf> {
-        let dir = self.temp_dir.as_ref();
-        let old_file_name = change
-            .previous_filename
-            .as_ref()
-            .unwrap_or(&change.filename);
-        let file = dir.join(format!("{}_{}", "base", old_file_name));
+        let file = self.temp_original_path(change)?;
+        if self.no_reverse {
+            fs::copy(new, &file)?;
+        } else if self.binary_patch || (cfg!(windows) && self.crlf_normalize) {
+            change.reverse_apply_binary(new, &file)?;
+        } else {
+            change.reverse_apply(new, &file)?;
+        }
+        Ok(file)
+    }
+
+    /// The path for the new version of `change`, creating any needed parent directories
+    ///
+    /// The file keeps its name, and extension, so difftools can still highlight the syntax.
+    fn temp_new_path(&self, change: &Change) -> Result<PathBuf> {
+        let file = self.new_path(change);
         fs::create_dir_all(
             file.parent()
                 .expect("Should always have a parent temp path"),
         )?;
+        Ok(file)
+    }
 
-        change.reverse_apply(new, &file)?;
+    /// The path for the base version of `change`, creating any needed parent directories
+    ///
+    /// The file keeps its name, in a separate directory from the new version.
+    fn temp_original_path(&self, change: &Change) -> Result<PathBuf> {
+        let file = self.original_path(change);
+        fs::create_dir_all(
+            file.parent()
+                .expect("Should always have a parent temp path"),
+        )?;
         Ok(file)
     }
+
+    fn new_path(&self, change: &Change) -> PathBuf {
+        self.temp_dir.path().join("remote").join(&change.filename)
+    }
+
+    fn original_path(&self, change: &Change) -> PathBuf {
+        let old_file_name = change
+            .previous_filename
+            .as_ref()
+            .unwrap_or(&change.filename);
+        self.temp_dir.path().join("local").join(old_file_name)
+    }
+}
+
+/// The command to open `path` with the default application of the OS for its file type
+fn os_open_command(path: impl AsRef<OsStr>) -> Command {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    // Not `cmd /C start`, the filename comes from the pull request and `cmd` would run anything
+    // after a `&` in it
+    #[cfg(windows)]
+    let mut command = Command::new("explorer.exe");
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    command
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[cfg(windows)]
-    const EOL: &'static str = "\r\n";
-    #[cfg(not(windows))]
-    const EOL: &'static str = "\n";
-
     use base64::{engine::general_purpose::STANDARD, Engine as _};
     use httpmock::prelude::GET;
-    use httpmock::MockServer;
+    use httpmock::{Mock, MockServer};
     use std::fs;
     use temp_testdir::TempDir;
     use textwrap::dedent;
+    use yare::parameterized;
 
     fn difftool(dir: impl AsRef<Path>) -> git_config::Difftool {
         let dir = dir.as_ref();
@@ -109,7 +1028,28 @@ mod tests {
         let config = git_dir.join("config");
         fs::create_dir_all(&git_dir).unwrap();
         fs::write(&config, "[difftool.bc]\n    path = bcomp").unwrap();
-        git_config::Difftool::new(&dir, Some("bc")).unwrap()
+        git_config::Difftool::new(&dir, Some("bc"), false).unwrap()
+    }
+
+    /// Mock the GitHub api at `path` answering with base64 encoded `contents`
+    ///
+    /// A `?ref=REF` at the end of `path` is matched as the query.
+    fn mock_contents<'a>(server: &'a MockServer, path: &str, contents: &[u8]) -> Mock<'a> {
+        let (path, git_ref) = match path.split_once("?ref=") {
+            Some((path, git_ref)) => (path, Some(git_ref)),
+            None => (path, None),
+        };
+        let body = format!(
+            "{{\"content\":\"{}\", \"encoding\":\"base64\"}}",
+            STANDARD.encode(contents)
+        );
+        server.mock(|when, then| {
+            let when = when.method(GET).path(path);
+            if let Some(git_ref) = git_ref {
+                when.query_param("ref", git_ref);
+            }
+            then.status(200).body(body);
+        })
     }
 
     #[test]
@@ -125,18 +1065,77 @@ mod tests {
         );
         fs::write(&b, new).unwrap();
         let diff = "@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three";
-        let expected = format!("{EOL}line one{EOL}line two{EOL}line three{EOL}");
+        let expected = "\nline one\nline two\nline three\n";
         let change = Change {
             filename: "ignore_me".to_string(),
             contents_url: "sure".to_string(),
             patch: Some(diff.to_string()),
             status: "modified".to_string(),
             previous_filename: None,
+            ..Default::default()
         };
         let diff = Diff::new(difftool(&temp)).unwrap();
         let original = diff.create_temp_original(&change, b).unwrap();
         assert!(original.to_str().unwrap().ends_with(&change.filename));
-        assert_eq!(fs::read(&original).unwrap(), expected.into_bytes());
+        assert_eq!(fs::read(&original).unwrap(), expected.as_bytes());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn crlf_from_patch_without_normalizing() {
+        let temp = TempDir::default();
+        let b = temp.join("b");
+        fs::write(&b, "line one\nline changed\nline three\n").unwrap();
+        let change = Change {
+            filename: "ignore_me".to_string(),
+            patch: Some("@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_crlf_normalize(false);
+        let original = diff.create_temp_original(&change, b).unwrap();
+        assert_eq!(
+            fs::read_to_string(&original).unwrap(),
+            "line one\r\nline two\r\nline three\r\n"
+        );
+    }
+
+    #[test]
+    fn local_patch_reconstructs_base() {
+        let temp = TempDir::default();
+        let new = temp.join("new.txt");
+        fs::write(&new, "line one\nline changed\nline three\n").unwrap();
+        let patch = "@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three";
+        let diff = Diff::new(difftool(&temp)).unwrap().with_binary_patch(true);
+
+        let tool = diff.local_patch(&new, patch.to_string()).unwrap();
+
+        assert_eq!(tool.filename(), "new.txt");
+        assert_eq!(
+            fs::read_to_string(&tool.local).unwrap(),
+            "line one\nline two\nline three\n"
+        );
+        assert_eq!(fs::read(&tool.remote).unwrap(), fs::read(&new).unwrap());
+    }
+
+    #[test]
+    fn no_reverse_copies_new() {
+        let temp = TempDir::default().permanent();
+        let b = temp.join("b");
+        let new = "line one\nline changed\nline three\n";
+        fs::write(&b, new).unwrap();
+        let change = Change {
+            filename: "ignore_me".to_string(),
+            contents_url: "sure".to_string(),
+            patch: Some("@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp)).unwrap().with_no_reverse(true);
+        let original = diff.create_temp_original(&change, &b).unwrap();
+        assert_eq!(fs::read(&original).unwrap(), fs::read(&b).unwrap());
     }
 
     #[test]
@@ -152,13 +1151,14 @@ mod tests {
         );
         fs::write(&b, new).unwrap();
         let diff = "@@ -1,3 +1,3 @@\n line one\n-line two\n+line changed\n line three";
-        let expected = format!("{EOL}line one{EOL}line two{EOL}line three{EOL}");
+        let expected = "\nline one\nline two\nline three\n";
         let change = Change {
             filename: "ignore_me".to_string(),
             contents_url: "sure".to_string(),
             patch: Some(diff.to_string()),
             status: "renamed".to_string(),
             previous_filename: Some("new_filename".to_string()),
+            ..Default::default()
         };
         let diff = Diff::new(difftool(&temp)).unwrap();
         let original = diff.create_temp_original(&change, b).unwrap();
@@ -166,19 +1166,136 @@ mod tests {
             .to_str()
             .unwrap()
             .ends_with(change.previous_filename.as_ref().unwrap()));
-        assert_eq!(fs::read(&original).unwrap(), expected.into_bytes());
+        assert_eq!(fs::read(&original).unwrap(), expected.as_bytes());
     }
 
-    #[tokio::test]
-    async fn get_new_content() {
+    #[test]
+    fn fixed_temp_name() {
         let temp = TempDir::default();
-        let contents = "line one\nline two";
-        let encoded = STANDARD.encode(contents.as_bytes());
-        let server = MockServer::start();
-        let mock = server.mock(|when, then| {
-            when.method(GET).path("/one.c");
-            then.status(200)
-                .header("content-type", "text/html")
+        let name = "gh-difftool-fixed-temp-name";
+        let diff =
+            Diff::new_with_temp_name(difftool(&temp), std::env::temp_dir(), name, true).unwrap();
+        assert_eq!(diff.temp_dir.path(), std::env::temp_dir().join(name));
+    }
+
+    #[test]
+    fn kept_temp_dir_not_deleted() {
+        let temp = TempDir::default();
+        let diff = Diff::new_in(difftool(&temp), &*temp).unwrap();
+        let file = diff.temp_dir.path().join("foo.txt");
+        fs::write(&file, "kept").unwrap();
+
+        let kept = diff.keep();
+        assert_eq!(fs::read_to_string(kept.join("foo.txt")).unwrap(), "kept");
+    }
+
+    #[test]
+    fn temp_dir_in_root() {
+        let temp = TempDir::default();
+        let root = temp.join("root");
+        fs::create_dir_all(&root).unwrap();
+
+        let diff = Diff::new_in(difftool(&temp), &root).unwrap();
+        assert_eq!(diff.temp_dir.path().parent(), Some(root.as_path()));
+    }
+
+    #[test]
+    fn fixed_temp_name_already_exists() {
+        let temp = TempDir::default();
+        let name = "gh-difftool-fixed-temp-name-exists";
+        let existing = std::env::temp_dir().join(name);
+        fs::create_dir_all(&existing).unwrap();
+
+        let root = std::env::temp_dir();
+        assert!(Diff::new_with_temp_name(difftool(&temp), &root, name, false).is_err());
+        let diff = Diff::new_with_temp_name(difftool(&temp), &root, name, true).unwrap();
+        assert_eq!(diff.temp_dir.path(), existing);
+    }
+
+    #[parameterized(
+    absolute = { "/" },
+    parent = { "../../home/me" },
+    nested = { "a/b" },
+    current = { "." },
+    empty = { "" },
+    )]
+    fn temp_name_not_a_directory_name(name: &str) {
+        let temp = TempDir::default();
+        let root = temp.join("root");
+        fs::create_dir_all(&root).unwrap();
+
+        let error = Diff::new_with_temp_name(difftool(&temp), &root, name, true).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Error>(),
+            Some(&Error::TempName(name.to_string()))
+        );
+        assert!(root.exists());
+    }
+
+    #[test]
+    fn save_copies_local_and_remote() {
+        let temp = TempDir::default();
+        let save_dir = temp.join("saved");
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let dir = diff.temp_dir.path();
+        let local = dir.join("local/foo/bar.txt");
+        let remote = dir.join("remote/foo/bar.txt");
+        fs::create_dir_all(local.parent().unwrap()).unwrap();
+        fs::create_dir_all(remote.parent().unwrap()).unwrap();
+        fs::write(&local, "old").unwrap();
+        fs::write(&remote, "new").unwrap();
+
+        let tool = Difftool::new(
+            &diff.difftool,
+            "foo/bar.txt".to_string(),
+            local.into_os_string(),
+            remote.into_os_string(),
+            dir,
+        );
+        tool.save(&save_dir).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(save_dir.join("local/foo/bar.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(save_dir.join("remote/foo/bar.txt")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn temp_files_keep_their_names() {
+        let temp = TempDir::default();
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let change = Change {
+            filename: "src/new.rs".to_string(),
+            previous_filename: Some("src/old.rs".to_string()),
+            status: "renamed".to_string(),
+            ..Default::default()
+        };
+        let dir = diff.temp_dir.path();
+
+        assert_eq!(
+            diff.temp_original_path(&change).unwrap(),
+            dir.join("local/src/old.rs")
+        );
+        assert_eq!(
+            diff.temp_new_path(&change).unwrap(),
+            dir.join("remote/src/new.rs")
+        );
+    }
+
+    #[tokio::test]
+    async fn get_new_content() {
+        let temp = TempDir::default();
+        let contents = "line one\nline two";
+        let encoded = STANDARD.encode(contents.as_bytes());
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/one.c");
+            then.status(200)
+                .header("content-type", "text/html")
                 .body(format!(
                     "{{\"content\":\"{encoded}\", \"encoding\":\"base64\"}}"
                 ));
@@ -189,6 +1306,7 @@ mod tests {
             patch: Some("@@ -1,3 +1,3 @@\n doesn't matter".to_string()),
             status: "modified".to_string(),
             previous_filename: None,
+            ..Default::default()
         };
         let diff = Diff::new(difftool(&temp)).unwrap();
         let new_file = diff.new_file_contents(&change).await.unwrap();
@@ -200,6 +1318,781 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn binary_new_content() {
+        let temp = TempDir::default();
+        // The start of a PNG, which is not valid UTF-8
+        let contents = [
+            0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0x00, 0xff,
+        ];
+        let base_contents = [
+            0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0x00, 0xfe,
+        ];
+        let server = MockServer::start();
+        let mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/image.png?ref=head",
+            &contents,
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/image.png?ref=base",
+            &base_contents,
+        );
+        let change = Change {
+            filename: "image.png".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/image.png?ref=head"),
+            patch: None,
+            status: "modified".to_string(),
+            previous_filename: None,
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        mock.assert();
+        base_mock.assert();
+        assert_eq!(fs::read(&tool.remote).unwrap(), contents);
+        assert_eq!(fs::read(&tool.local).unwrap(), base_contents);
+    }
+
+    #[parameterized(
+    configured = { "images/logo.png", Viewer::OsDefault },
+    configured_with_dot = { "docs/spec.pdf", Viewer::OsDefault },
+    uppercase = { "images/LOGO.PNG", Viewer::OsDefault },
+    code = { "src/main.rs", Viewer::Difftool },
+    no_extension = { "png", Viewer::Difftool },
+    )]
+    fn viewer_for_extension(filename: &str, expected: Viewer) {
+        let temp = TempDir::default();
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_open_with_default(vec!["png".to_string(), ".PDF".to_string()]);
+        assert_eq!(diff.viewer(filename), expected);
+    }
+
+    #[parameterized(
+    configured = { "images/logo.png", Some("imgdiff") },
+    configured_with_dot = { "images/icon.SVG", Some("imgdiff") },
+    code = { "src/main.rs", None },
+    no_extension = { "png", None },
+    )]
+    fn tool_for_extension(filename: &str, expected: Option<&str>) {
+        let temp = TempDir::default();
+        let default_tool = difftool(&temp);
+        fs::write(
+            temp.join(".git").join("config"),
+            "[difftool.imgdiff]\n    cmd = imgdiff $LOCAL $REMOTE",
+        )
+        .unwrap();
+        let image_tool = || git_config::Difftool::new(&temp, Some("imgdiff"), false).unwrap();
+        let diff = Diff::new(default_tool)
+            .unwrap()
+            .with_extension_tools(HashMap::from([
+                ("png".to_string(), image_tool()),
+                (".svg".to_string(), image_tool()),
+            ]));
+        assert_eq!(
+            diff.extension_tool(filename)
+                .map(git_config::Difftool::name),
+            expected
+        );
+    }
+
+    #[test]
+    fn driver_args_of_added_file() {
+        let temp = TempDir::default();
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let change = Change {
+            filename: "src/new.rs".to_string(),
+            status: "added".to_string(),
+            sha: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        let tool = Difftool::new(
+            &diff.difftool,
+            change.filename.clone(),
+            "local/src/new.rs".into(),
+            "remote/src/new.rs".into(),
+            temp.as_ref(),
+        );
+        assert_eq!(
+            diff.driver_args(&change, &tool),
+            DriverArgs {
+                path: "src/new.rs".to_string(),
+                old_file: "/dev/null".into(),
+                old_hex: ".".to_string(),
+                old_mode: ".".to_string(),
+                new_file: "remote/src/new.rs".into(),
+                new_hex: "abc123".to_string(),
+                new_mode: "100644".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn driver_args_of_executable() {
+        let temp = TempDir::default();
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_file_modes(HashMap::from([(
+                "build.sh".to_string(),
+                "100755".to_string(),
+            )]));
+        let change = Change {
+            filename: "build.sh".to_string(),
+            status: "modified".to_string(),
+            sha: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        let tool = Difftool::new(
+            &diff.difftool,
+            change.filename.clone(),
+            "local/build.sh".into(),
+            "remote/build.sh".into(),
+            temp.as_ref(),
+        );
+        let args = diff.driver_args(&change, &tool);
+        assert_eq!(args.old_mode, "100644");
+        assert_eq!(args.new_mode, "100755");
+    }
+
+    #[tokio::test]
+    async fn large_file_tool_over_threshold() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        mock_contents(
+            &server,
+            "/repos/me/repo/contents/big.txt",
+            "more than ten bytes".as_bytes(),
+        );
+        let change = Change {
+            filename: "big.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/big.txt?ref=head"),
+            status: "added".to_string(),
+            ..Default::default()
+        };
+        let small_file_tool = difftool(&temp);
+        fs::write(
+            temp.join(".git").join("config"),
+            "[difftool.delta]\n    cmd = delta $LOCAL $REMOTE",
+        )
+        .unwrap();
+        let large_file_tool = git_config::Difftool::new(&temp, Some("delta"), false).unwrap();
+        let diff = Diff::new(small_file_tool)
+            .unwrap()
+            .with_large_file_tool(Some((10, large_file_tool)));
+
+        let tool = diff.difftool(change).await.unwrap();
+
+        assert_eq!(tool.tool_name(), "delta");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn executable_saved_with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        mock_contents(
+            &server,
+            "/repos/me/repo/contents/build.sh",
+            "#!/bin/sh\n".as_bytes(),
+        );
+        let change = Change {
+            filename: "build.sh".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/build.sh?ref=head"),
+            status: "added".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_file_modes(HashMap::from([(
+                "build.sh".to_string(),
+                "100755".to_string(),
+            )]));
+        let save_dir = temp.join("saved");
+
+        diff.difftool(change)
+            .await
+            .unwrap()
+            .save(&save_dir)
+            .unwrap();
+
+        let mode = fs::metadata(save_dir.join("remote").join("build.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+
+    #[tokio::test]
+    async fn failed_download_names_file() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/repos/me/repo/contents/src/foo.rs");
+            then.status(500);
+        });
+        let change = Change {
+            filename: "src/foo.rs".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/src/foo.rs?ref=head"),
+            patch: Some("@@ -1 +1 @@\n-a\n+b".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp)).unwrap().with_retries(0);
+        let error = diff.difftool(change).await.unwrap_err();
+
+        assert!(format!("{error:#}").starts_with("Failed to fetch contents for src/foo.rs: "));
+    }
+
+    #[tokio::test]
+    async fn submodule_is_not_downloaded() {
+        let temp = TempDir::default();
+        let change = Change {
+            filename: "vendor/lib".to_string(),
+            contents_url: "https://api.github.com/repos/me/repo/contents/vendor/lib".to_string(),
+            patch: Some("@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def".into()),
+            status: "modified".to_string(),
+            kind: ChangeKind::Submodule {
+                base: Some("abc".to_string()),
+                new: Some("def".to_string()),
+            },
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let tool = diff.difftool(change).await.unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&tool.local).unwrap(),
+            "Subproject commit abc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&tool.remote).unwrap(),
+            "Subproject commit def\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn renamed_without_patch_downloads_base() {
+        let temp = TempDir::default();
+        let new_contents = "line one\nline changed";
+        let base_contents = "line one\nline two";
+        let server = MockServer::start();
+        let new_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/new_name.txt?ref=head",
+            new_contents.as_bytes(),
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/old_name.txt?ref=base",
+            base_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "new_name.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/new_name.txt?ref=head"),
+            patch: None,
+            status: "renamed".to_string(),
+            previous_filename: Some("old_name.txt".to_string()),
+            changes: 2,
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        new_mock.assert();
+        base_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), base_contents);
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), new_contents);
+    }
+
+    #[tokio::test]
+    async fn modified_without_patch_downloads_base() {
+        let temp = TempDir::default();
+        let new_contents = "line one\nline changed";
+        let base_contents = "line one\nline two";
+        let server = MockServer::start();
+        let new_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/large.txt?ref=head",
+            new_contents.as_bytes(),
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/large.txt?ref=base",
+            base_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "large.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/large.txt?ref=head"),
+            patch: None,
+            status: "modified".to_string(),
+            changes: 40000,
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        new_mock.assert();
+        base_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), base_contents);
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), new_contents);
+    }
+
+    #[tokio::test]
+    async fn base_ref_contents_are_local() {
+        let temp = TempDir::default();
+        let new_contents = "line one\nline two";
+        let main_contents = "line one";
+        let server = MockServer::start();
+        let new_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=head",
+            new_contents.as_bytes(),
+        );
+        let main_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=main",
+            main_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1 +1,2 @@\n line one\n+line two".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_ref(Some("main".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        new_mock.assert();
+        main_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), main_contents);
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), new_contents);
+    }
+
+    #[test]
+    fn explain_modified_file() {
+        let temp = TempDir::default();
+        let change = Change {
+            filename: "src/main.rs".to_string(),
+            patch: Some("@@ -1 +1,2 @@\n line one\n+line two".into()),
+            status: "modified".to_string(),
+            changes: 1,
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let temp_dir = diff.temp_dir.path();
+
+        assert_eq!(
+            serde_json::to_value(diff.explain(&change)).unwrap(),
+            serde_json::json!({
+                "filename": "src/main.rs",
+                "previous_filename": null,
+                "status": "modified",
+                "has_patch": true,
+                "submodule": false,
+                "binary": false,
+                "rename": false,
+                "base": "reconstructed",
+                "reconstruction": "reverse-patch",
+                "local": temp_dir.join("local").join("src/main.rs"),
+                "remote": temp_dir.join("remote").join("src/main.rs"),
+            })
+        );
+    }
+
+    #[test]
+    fn explain_submodule() {
+        let temp = TempDir::default();
+        let change = Change {
+            filename: "vendor/lib".to_string(),
+            patch: Some("@@ -1 +1 @@\n-Subproject commit abc\n+Subproject commit def".into()),
+            status: "modified".to_string(),
+            changes: 2,
+            kind: ChangeKind::Submodule {
+                base: Some("abc".to_string()),
+                new: Some("def".to_string()),
+            },
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp)).unwrap();
+        let temp_dir = diff.temp_dir.path();
+
+        assert_eq!(
+            serde_json::to_value(diff.explain(&change)).unwrap(),
+            serde_json::json!({
+                "filename": "vendor/lib",
+                "previous_filename": null,
+                "status": "modified",
+                "has_patch": true,
+                "submodule": true,
+                "binary": false,
+                "rename": false,
+                "base": "reconstructed",
+                "reconstruction": "submodule",
+                "local": temp_dir.join("local").join("vendor/lib"),
+                "remote": temp_dir.join("remote").join("vendor/lib"),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_head_contents_fetched() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        let pinned_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=pinned",
+            "line one\nline two\n".as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1 +1,2 @@\n line one\n+line two".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_pinned_head(Some("pinned".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        pinned_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), "line one\n");
+        assert_eq!(
+            fs::read_to_string(&tool.remote).unwrap(),
+            "line one\nline two\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn reconstruction_differing_from_base() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=head",
+            "line one\nline two\n".as_bytes(),
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=base",
+            "line zero\n".as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1 +1,2 @@\n line one\n+line two".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()));
+
+        assert!(!diff.reconstruction_matches(&change).await.unwrap());
+        base_mock.assert();
+    }
+
+    #[parameterized(
+    whitespace_patch = { "modified", Some("\n") },
+    no_patch = { "renamed", None },
+    removed = { "removed", Some("@@ -1 +0,0 @@\n-line one") },
+    )]
+    #[test_macro(tokio::test)]
+    async fn reconstruction_not_reverse_patched_matches(status: &str, patch: Option<&str>) {
+        let temp = TempDir::default();
+        let change = Change {
+            filename: "file.txt".to_string(),
+            patch: patch.map(String::from),
+            status: status.to_string(),
+            ..Default::default()
+        };
+        // Without a base commit a reverse patched file would fail to audit
+        let diff = Diff::new(difftool(&temp)).unwrap();
+
+        assert!(diff.reconstruction_matches(&change).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn missing_at_base_ref_is_added() {
+        let temp = TempDir::default();
+        let new_contents = "line one";
+        let server = MockServer::start();
+        mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=head",
+            new_contents.as_bytes(),
+        );
+        let main_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/repos/me/repo/contents/file.txt")
+                .query_param("ref", "main");
+            then.status(404).body("{\"message\":\"Not Found\"}");
+        });
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1 +1 @@\n-line 1\n+line one".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_ref(Some("main".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        main_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), "");
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), new_contents);
+    }
+
+    #[tokio::test]
+    async fn suggestions_applied_to_head() {
+        let temp = TempDir::default();
+        let head_contents = "line one\nline two\nline three\n";
+        let server = MockServer::start();
+        let head_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=head",
+            head_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1,2 +1,3 @@\n line one\n+line two\n line three".into()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let suggestion = Suggestion {
+            path: "file.txt".to_string(),
+            start_line: 2,
+            end_line: 2,
+            replacement: "line 2".to_string(),
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_suggestions(Some(HashMap::from([(
+                "file.txt".to_string(),
+                vec![suggestion],
+            )])));
+        let tool = diff.difftool(change).await.unwrap();
+
+        head_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), head_contents);
+        assert_eq!(
+            fs::read_to_string(&tool.remote).unwrap(),
+            "line one\nline 2\nline three\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_renames_downloads_base() {
+        let temp = TempDir::default();
+        let new_contents = "line one\nline two";
+        let base_contents = "line one\nline elided";
+        let server = MockServer::start();
+        let new_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/new_name.txt?ref=head",
+            new_contents.as_bytes(),
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/old_name.txt?ref=base",
+            base_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "new_name.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/new_name.txt?ref=head"),
+            patch: None,
+            status: "renamed".to_string(),
+            previous_filename: Some("old_name.txt".to_string()),
+            changes: 0,
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()))
+            .with_verify_renames(true);
+        let tool = diff.difftool(change).await.unwrap();
+
+        new_mock.assert();
+        base_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), base_contents);
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), new_contents);
+    }
+
+    #[tokio::test]
+    async fn difftools_keep_change_order() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        let filenames = ["slow.txt", "fast.txt", "faster.txt"];
+        let delays = [300, 100, 0];
+        let mut changes = vec![];
+        for (filename, delay) in filenames.iter().zip(delays) {
+            let path = format!("/repos/me/repo/contents/{filename}");
+            server.mock(|when, then| {
+                when.method(GET).path(&path);
+                then.status(200)
+                    .delay(std::time::Duration::from_millis(delay))
+                    .body(format!(
+                        "{{\"content\":\"{}\", \"encoding\":\"base64\"}}",
+                        STANDARD.encode(filename.as_bytes())
+                    ));
+            });
+            changes.push(Change {
+                filename: filename.to_string(),
+                contents_url: server.url(format!("{path}?ref=head")),
+                patch: None,
+                status: "renamed".to_string(),
+                previous_filename: Some(format!("old_{filename}")),
+                changes: 0,
+                ..Default::default()
+            });
+        }
+        let diff = Diff::new(difftool(&temp)).unwrap();
+
+        let tools = diff
+            .difftools(changes, 2)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        let names = tools.iter().map(Difftool::filename).collect::<Vec<_>>();
+        assert_eq!(names, filenames);
+    }
+
+    #[tokio::test]
+    async fn merged_preview_fetches_at_merge_ref() {
+        let temp = TempDir::default();
+        let merged_contents = "line one\nbase change\npr change";
+        let base_contents = "line one\nbase change";
+        let server = MockServer::start();
+        let merge_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=merge",
+            merged_contents.as_bytes(),
+        );
+        let base_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt?ref=base",
+            base_contents.as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1,3 +1,3 @@\n doesn't matter".to_string()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_sha(Some("base".to_string()))
+            .with_merge_sha(Some("merge".to_string()));
+        let tool = diff.difftool(change).await.unwrap();
+
+        merge_mock.assert();
+        base_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), base_contents);
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), merged_contents);
+    }
+
+    #[tokio::test]
+    async fn working_tree_is_local() {
+        let temp = TempDir::default();
+        let root = temp.join("repo");
+        fs::create_dir_all(root.join("foo")).unwrap();
+        fs::write(root.join("foo/local.txt"), "working tree").unwrap();
+        let server = MockServer::start();
+        let mock = mock_contents(&server, "/local.txt", "pull request".as_bytes());
+        let change = Change {
+            filename: "foo/local.txt".to_string(),
+            contents_url: server.url("/local.txt"),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_working_tree(Some(root));
+        let tool = diff.difftool(change).await.unwrap();
+
+        mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), "working tree");
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), "pull request");
+    }
+
+    #[tokio::test]
+    async fn working_tree_missing_file_is_empty() {
+        let temp = TempDir::default();
+        let root = temp.join("repo");
+        fs::create_dir_all(&root).unwrap();
+        let server = MockServer::start();
+        let mock = mock_contents(&server, "/added.txt", "new file".as_bytes());
+        let change = Change {
+            filename: "added.txt".to_string(),
+            contents_url: server.url("/added.txt"),
+            status: "added".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_working_tree(Some(root));
+        let tool = diff.difftool(change).await.unwrap();
+
+        mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), "");
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), "new file");
+    }
+
+    #[tokio::test]
+    async fn base_blob_fetched_for_matching_file() {
+        let temp = TempDir::default();
+        let server = MockServer::start();
+        let new_mock = mock_contents(
+            &server,
+            "/repos/me/repo/contents/file.txt",
+            "new contents".as_bytes(),
+        );
+        let blob_mock = mock_contents(
+            &server,
+            "/repos/me/repo/git/blobs/abc123",
+            "blob contents".as_bytes(),
+        );
+        let change = Change {
+            filename: "file.txt".to_string(),
+            contents_url: server.url("/repos/me/repo/contents/file.txt?ref=head"),
+            patch: Some("@@ -1,3 +1,3 @@\n doesn't matter".to_string()),
+            status: "modified".to_string(),
+            ..Default::default()
+        };
+        let diff = Diff::new(difftool(&temp))
+            .unwrap()
+            .with_base_blobs(HashMap::from([(
+                "file.txt".to_string(),
+                "abc123".to_string(),
+            )]));
+        let tool = diff.difftool(change).await.unwrap();
+
+        new_mock.assert();
+        blob_mock.assert();
+        assert_eq!(fs::read_to_string(&tool.local).unwrap(), "blob contents");
+        assert_eq!(fs::read_to_string(&tool.remote).unwrap(), "new contents");
+    }
+
     #[tokio::test]
     async fn getting_a_second_set_of_new_content() {
         let temp = TempDir::default();
@@ -221,6 +2114,7 @@ mod tests {
             patch: Some("@@ -1,3 +1,3 @@\n doesn't matter".to_string()),
             status: "modified".to_string(),
             previous_filename: None,
+            ..Default::default()
         };
         let diff = Diff::new(difftool(&temp)).unwrap();
         let new_file = diff.new_file_contents(&change).await.unwrap();
@@ -0,0 +1,167 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! A summary of the files which failed during a review, grouped by what went wrong
+
+use std::fmt::Write;
+
+const RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// What went wrong with a file
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Cause {
+    /// Downloading the file from GitHub failed
+    Network,
+    /// Reverse applying the patch to get the base version failed
+    PatchApply,
+    /// A program, the difftool or `patch`, couldn't be run
+    Spawn,
+    /// Anything else
+    Other,
+}
+
+impl Cause {
+    /// The cause of `error`, from the context added along the way
+    ///
+    /// The download context wraps the reverse applying of the patch, so the more specific
+    /// causes are looked for first.
+    pub fn of(error: &anyhow::Error) -> Self {
+        let has = |prefix: &str| {
+            error
+                .chain()
+                .any(|cause| cause.to_string().starts_with(prefix))
+        };
+        if has("Failed to spawn") || has("Failed to launch") {
+            Cause::Spawn
+        } else if has("Failed to patch") {
+            Cause::PatchApply
+        } else if has("Failed to fetch contents") {
+            Cause::Network
+        } else {
+            Cause::Other
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Cause::Network => "network",
+            Cause::PatchApply => "patch apply",
+            Cause::Spawn => "spawn",
+            Cause::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Failure {
+    cause: Cause,
+    filename: String,
+    message: String,
+}
+
+/// The files which failed during a review
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Failures {
+    failures: Vec<Failure>,
+}
+
+impl Failures {
+    /// Record that `filename` failed with `error`
+    pub fn record(&mut self, filename: impl Into<String>, error: &anyhow::Error) {
+        self.failures.push(Failure {
+            cause: Cause::of(error),
+            filename: filename.into(),
+            message: error.root_cause().to_string(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The failures grouped by cause, each group with its count and the files in it
+    ///
+    /// # Arguments
+    /// * `color` - Whether to highlight the group headings with ANSI colors
+    pub fn summary(&self, color: bool) -> String {
+        let mut failures = self.failures.iter().collect::<Vec<_>>();
+        // Stable, so the files of a cause stay in the order they failed in
+        failures.sort_by_key(|failure| failure.cause);
+        let (start, end) = if color { (RED, RESET) } else { ("", "") };
+
+        let files = match failures.len() {
+            1 => "1 file".to_string(),
+            count => format!("{count} files"),
+        };
+        let mut summary = format!("{files} failed:\n");
+        for group in failures.chunk_by(|a, b| a.cause == b.cause) {
+            writeln!(
+                summary,
+                "{start}{} ({}){end}",
+                group[0].cause.as_str(),
+                group.len()
+            )
+            .expect("Writing to a String can't fail");
+            for failure in group {
+                writeln!(summary, "  {}: {}", failure.filename, failure.message)
+                    .expect("Writing to a String can't fail");
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn grouped_by_cause() {
+        let mut failures = Failures::default();
+        failures.record(
+            "a.rs",
+            &anyhow!("gh: Not Found (HTTP 404)").context("Failed to fetch contents for a.rs"),
+        );
+        failures.record(
+            "b.rs",
+            &anyhow!("No such file or directory").context("Failed to launch the difftool for b.rs"),
+        );
+        failures.record(
+            "c.rs",
+            &anyhow!("Failed to patch \"c.rs\": malformed patch")
+                .context("Failed to fetch contents for c.rs"),
+        );
+        failures.record(
+            "d.rs",
+            &anyhow!("gh: Server Error (HTTP 502)").context("Failed to fetch contents for d.rs"),
+        );
+
+        assert_eq!(
+            failures.summary(false),
+            concat!(
+                "4 files failed:\n",
+                "network (2)\n",
+                "  a.rs: gh: Not Found (HTTP 404)\n",
+                "  d.rs: gh: Server Error (HTTP 502)\n",
+                "patch apply (1)\n",
+                "  c.rs: Failed to patch \"c.rs\": malformed patch\n",
+                "spawn (1)\n",
+                "  b.rs: No such file or directory\n",
+            )
+        );
+    }
+
+    #[test]
+    fn colored_headings() {
+        let mut failures = Failures::default();
+        failures.record("a.rs", &anyhow!("oops"));
+        assert_eq!(
+            failures.summary(true),
+            "1 file failed:\n\x1b[1;31mother (1)\x1b[0m\n  a.rs: oops\n"
+        );
+    }
+}
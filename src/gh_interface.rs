@@ -5,17 +5,23 @@
 
 //! Module for interacting with the github command line
 
+use crate::change_set::ChangeKind;
 use crate::change_set::ChangeSet;
 use crate::cmd::Cmd;
+use crate::git_config;
+use crate::since::{self, Since};
+use crate::suggestions::{suggestion_blocks, Suggestion};
 use crate::Change;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Display, Formatter};
-use std::io::{Error, ErrorKind};
-use std::process::Stdio;
-use tokio::process::Command;
+use std::io::Error;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct PullRequest {
@@ -28,12 +34,51 @@ pub struct PullRequest {
 }
 
 impl PullRequest {
-    pub fn new_from_cwd() -> Result<Self> {
-        let mut gh = GhCli::new(std::process::Command::new("gh"));
+    /// The pull request for the current branch
+    ///
+    /// # Arguments
+    /// * `hostname` - The GitHub host to use instead of the default of `gh`
+    pub fn new_from_cwd(hostname: Option<String>) -> Result<Self> {
+        let mut gh = GhCli::new(Command::new("gh")).with_hostname(hostname);
         let repo = gh.current_repo()?;
         let number = gh.current_pr()?;
         Ok(Self { repo, number })
     }
+
+    /// The pull request from a GitHub Actions event payload, like the one at `GITHUB_EVENT_PATH`
+    ///
+    /// # Arguments
+    /// * `path` - The path to the JSON event payload
+    ///
+    /// # Returns
+    /// `None` when the event isn't for a pull request, like a push.
+    pub fn new_from_event(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read GitHub event {path:?}"))?;
+        Self::from_event_json(&contents)
+    }
+
+    fn from_event_json(json: &str) -> Result<Option<Self>> {
+        let event: Event = serde_json::from_str(json).context("Malformed GitHub event")?;
+        Ok(event.pull_request.map(|pull_request| Self {
+            repo: event.repository.full_name,
+            number: pull_request.number,
+        }))
+    }
+}
+
+/// The parts of a GitHub Actions pull request event payload needed to find the pull request
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Event {
+    /// Only present for pull request events
+    pull_request: Option<PrNumber>,
+    repository: EventRepo,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct EventRepo {
+    full_name: String,
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -41,6 +86,148 @@ struct PrNumber {
     number: usize,
 }
 
+/// A short description of a pull request
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PrSummary {
+    pub number: usize,
+    pub title: String,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct GitRef {
+    sha: String,
+    /// The name of the branch
+    #[serde(rename = "ref", default)]
+    name: String,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct PullRequestRefs {
+    base: GitRef,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct PullRequestHead {
+    head: GitRef,
+}
+
+/// The head branch of a pull request, `repo` is missing when the fork was deleted
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct HeadBranch {
+    sha: String,
+    #[serde(rename = "ref")]
+    name: String,
+    repo: Option<RepoName>,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct PullRequestHeadBranch {
+    head: HeadBranch,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct RepoName {
+    full_name: String,
+}
+
+/// An event of a repo, only push events have the fields of the payload
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct RepoEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    payload: PushPayload,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct PushPayload {
+    #[serde(rename = "ref", default)]
+    name: Option<String>,
+    #[serde(default)]
+    head: Option<String>,
+    #[serde(default)]
+    before: Option<String>,
+}
+
+/// A commit of a pull request
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct PrCommit {
+    sha: String,
+    commit: CommitDetails,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct CommitDetails {
+    committer: Signature,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Signature {
+    date: String,
+}
+
+/// The files changed by a commit, from `/repos/{repo}/commits/{sha}`
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct CommitFiles {
+    files: Vec<CommitFile>,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct CommitFile {
+    filename: String,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct User {
+    login: String,
+}
+
+/// A review of a pull request, `user` is missing for deleted accounts
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Review {
+    user: Option<User>,
+    commit_id: Option<String>,
+}
+
+/// A git tree, listed recursively
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Tree {
+    tree: Vec<TreeEntry>,
+    /// GitHub cuts the listing short for large trees
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct TreeEntry {
+    path: String,
+    mode: String,
+}
+
+/// The result of comparing two commits
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Comparison {
+    #[serde(default)]
+    files: Vec<Change>,
+}
+
+/// A review comment on a pull request
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct ReviewComment {
+    path: String,
+    body: String,
+    /// The last line commented on, missing when the comment is outdated
+    line: Option<usize>,
+    /// The first line of a multi-line comment
+    start_line: Option<usize>,
+    /// "LEFT" for comments on the base version of the file, "RIGHT" for the head version
+    side: Option<String>,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Reference {
+    object: GitRef,
+}
+
 #[derive(Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
 struct Owner {
     login: String,
@@ -65,41 +252,339 @@ struct Content {
     encoding: String,
 }
 
-fn output_to_string(output: std::process::Output) -> Result<String> {
+/// The value of the `Link` header in the `output` of `gh api --include`
+///
+/// Header names are case insensitive, HTTP/2 responses give them in lowercase.
+fn link_header(output: &str) -> Option<&str> {
+    // The headers end at the first blank line, the body comes after it
+    let mut headers = output.lines().take_while(|line| !line.trim().is_empty());
+    headers.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("link").then_some(value)
+    })
+}
+
+/// What the link header of a page of changes says about the pages after it
+#[derive(Debug, Eq, PartialEq)]
+enum NextPages {
+    /// The number of the last page, the pages up to it can be fetched all at once
+    Last(usize),
+    /// Only that there's a next page, GitHub may leave out the `last` link
+    Next,
+    /// There are no more pages
+    Done,
+}
+
+impl NextPages {
+    fn from_link_header(link_header: &str) -> Result<Self> {
+        let header = parse_link_header::parse_with_rel(link_header)?;
+        if let Some(entry) = header.get("last") {
+            let page = entry
+                .queries
+                .get("page")
+                .ok_or_else(|| anyhow!("No page in the last link of {link_header}"))?;
+            Ok(NextPages::Last(page.parse().with_context(|| {
+                format!("Invalid last page in the link header {link_header}")
+            })?))
+        } else if header.contains_key("next") {
+            Ok(NextPages::Next)
+        } else {
+            Ok(NextPages::Done)
+        }
+    }
+
+    /// Whether there's a page after `page`
+    fn has_page_after(&self, page: usize) -> bool {
+        match self {
+            NextPages::Last(last) => *last > page,
+            NextPages::Next => true,
+            NextPages::Done => false,
+        }
+    }
+}
+
+/// What to do when `gh` isn't authenticated
+const AUTH_HELP: &str = "Run `gh auth login`, or set the GH_TOKEN environment variable to a \
+GitHub token, e.g. GH_TOKEN=$GITHUB_TOKEN in GitHub Actions";
+
+fn output_to_string(output: Output) -> Result<String> {
     if output.status.success() {
         Ok(String::from_utf8(output.stdout)?)
     } else {
-        Err(Error::new(
-            ErrorKind::Other,
-            String::from_utf8(output.stderr)?,
-        ))?
+        let mut stderr = String::from_utf8(output.stderr)?;
+        if is_auth_error(&stderr) {
+            stderr = format!("{}\n{AUTH_HELP}", stderr.trim_end());
+        }
+        Err(Error::other(stderr))?
+    }
+}
+
+/// Whether `gh` failed, with `stderr`, for lack of credentials
+///
+/// Either `gh` has no credentials at all and asks to `gh auth login`, or the API rejected the
+/// credentials it has with a 401. Other errors can mention `gh auth login` too, like for a remote
+/// that isn't a known host, so only the prompt to get started counts.
+fn is_auth_error(stderr: &str) -> bool {
+    stderr.contains("To get started with GitHub CLI") || stderr.contains("(HTTP 401)")
+}
+
+/// The number of changes GitHub returns per page by default
+pub const DEFAULT_PAGE_SIZE: usize = 30;
+
+/// How many pages of changes are fetched at once, more risks tripping GitHub's secondary rate limits
+const MAX_CONCURRENT_PAGES: usize = 4;
+
+/// The environment variable `gh` uses to pick the GitHub host
+const GH_HOST: &str = "GH_HOST";
+
+/// How many times a `gh` command that failed with a transient error is retried by default
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// The wait before the first retry, it doubles for each retry after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest wait for a rate limit to reset, a command hitting a longer rate limit fails
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// How long to wait before retrying a `gh` command which produced `output`
+///
+/// Only rate limits and server errors are retried, other failures, like a file not found or bad
+/// credentials, will fail the same way again. A rate limit waits for the `Retry-After` or
+/// `X-Ratelimit-Reset` header when the command was run with `--include`, otherwise the same
+/// backoff as a server error is used.
+///
+/// # Arguments
+/// * `output` - The output of the command
+/// * `attempt` - The number of times the command has been retried
+///
+/// # Returns
+/// `None` when the command shouldn't be retried
+fn retry_delay(output: &Output, attempt: u32) -> Option<Duration> {
+    if output.status.success() {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = http_status(&stderr)?;
+    let backoff = INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempt));
+    let rate_limited =
+        status == 429 || (status == 403 && stderr.to_lowercase().contains("rate limit"));
+    if rate_limited {
+        let headers = String::from_utf8_lossy(&output.stdout);
+        let wait = rate_limit_wait(&headers).unwrap_or(backoff);
+        return (wait <= MAX_RATE_LIMIT_WAIT).then_some(wait);
+    }
+    (500..600).contains(&status).then_some(backoff)
+}
+
+/// The HTTP status `gh api` reports in its error message, like "gh: Not Found (HTTP 404)"
+fn http_status(stderr: &str) -> Option<u16> {
+    let (_, status) = stderr.rsplit_once("(HTTP ")?;
+    status.split(')').next()?.parse().ok()
+}
+
+/// How long until a rate limit resets, from the `Retry-After` or `X-Ratelimit-Reset` headers
+fn rate_limit_wait(headers: &str) -> Option<Duration> {
+    let header = |name: &str| {
+        headers.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().parse::<u64>().ok())?
+        })
+    };
+    if let Some(seconds) = header("Retry-After") {
+        return Some(Duration::from_secs(seconds));
+    }
+    let reset = header("X-Ratelimit-Reset")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(reset.saturating_sub(now)))
+}
+
+/// Log the `gh` command line about to be run with `args`
+fn log_gh_args(args: &[OsString]) {
+    log::debug!(
+        "Running {}",
+        git_config::shell_words(
+            std::iter::once(OsStr::new("gh")).chain(args.iter().map(OsString::as_os_str))
+        )
+    );
+}
+
+/// How long to wait before retrying the `attempt` which finished with `output`
+///
+/// `None` when the attempt is done with, either it succeeded, failed for good, or `retries` are
+/// used up.
+fn next_retry(output: &Output, attempt: u32, retries: u32) -> Option<Duration> {
+    log::trace!(
+        "gh exited with {}, {} bytes of output",
+        output.status,
+        output.stdout.len()
+    );
+    let delay = retry_delay(output, attempt).filter(|_| attempt < retries)?;
+    log::debug!("Retrying in {delay:?}");
+    Some(delay)
+}
+
+/// Run `gh` with `args`, retrying transient failures, and get its output
+///
+/// [GhCli] runs `gh` through here, the async downloads of file contents go through
+/// [run_async_command] which shares the same retry logic.
+///
+/// # Arguments
+/// * `template` - The command to create each attempt from
+/// * `args` - The arguments to `gh`
+/// * `hostname` - The GitHub host to authenticate with instead of the default of `gh`
+/// * `retries` - How many times to retry a transient failure
+fn run_gh<C: Cmd>(
+    template: &C,
+    args: &[OsString],
+    hostname: Option<&str>,
+    retries: u32,
+) -> Result<String> {
+    log_gh_args(args);
+    let mut attempt = 0;
+    loop {
+        let mut command = template.new_from_self();
+        for arg in args {
+            command.arg(arg.clone());
+        }
+        if let Some(hostname) = hostname {
+            command.env(OsString::from(GH_HOST), OsString::from(hostname));
+        }
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+        let output = command.output()?;
+        match next_retry(&output, attempt, retries) {
+            Some(delay) => {
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            None => return output_to_string(output),
+        }
     }
 }
 
-async fn run_async_command<I, T>(args: I) -> Result<String>
+/// Collect `args` for [run_gh]
+fn os_args<I, T>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    args.into_iter()
+        .map(|arg| OsString::from(arg.as_ref()))
+        .collect()
+}
+
+/// The async version of [run_gh], so many files can be downloaded at once without holding up the
+/// runtime, even while waiting to retry
+async fn run_async_command<I, T>(args: I, hostname: Option<&str>, retries: u32) -> Result<String>
 where
     I: IntoIterator<Item = T>,
     T: AsRef<OsStr>,
 {
-    let mut command = Command::new("gh");
-    for arg in args {
-        command.arg(OsString::from(arg.as_ref()));
-    }
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
-    let output = command.output().await?;
-    output_to_string(output)
-}
-
-pub async fn file_contents(change: &Change) -> Result<String> {
-    let output = run_async_command([
-        "api",
-        "--method",
-        "GET",
-        "-H",
-        "Accept: application/vnd.github+json",
-        &change.contents_url,
-    ])
+    let args = os_args(args);
+    log_gh_args(&args);
+    let mut attempt = 0;
+    loop {
+        let mut command = tokio::process::Command::new("gh");
+        command.args(&args);
+        if let Some(hostname) = hostname {
+            command.env(GH_HOST, hostname);
+        }
+        let output = command.output().await?;
+        match next_retry(&output, attempt, retries) {
+            Some(delay) => {
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return output_to_string(output),
+        }
+    }
+}
+
+/// Get the decoded contents of the new version of the file of `change`
+///
+/// # Arguments
+/// * `change` - The change to get the new version of the file for
+/// * `head` - The commit to get the file at, instead of the one of the `contents_url`
+/// * `hostname` - The GitHub host to authenticate with instead of the default of `gh`
+/// * `retries` - How many times to retry a transient failure
+/// * `strict` - Whether contents which don't match the blob sha of `change` are an error instead
+///   of a warning
+pub async fn file_contents(
+    change: &Change,
+    head: Option<&str>,
+    hostname: Option<&str>,
+    retries: u32,
+    strict: bool,
+) -> Result<Vec<u8>> {
+    let contents = match head {
+        Some(head) => {
+            let url = change.contents_url_at(&change.filename, head)?;
+            url_contents(&url, hostname, retries).await?
+        }
+        None => url_contents(&change.contents_url, hostname, retries).await?,
+    };
+    verify_contents(change, &contents, strict)?;
+    Ok(contents)
+}
+
+/// The git blob sha of `contents`, the sha git, and GitHub, identify a version of a file by
+pub fn blob_sha(contents: &[u8]) -> String {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(format!("blob {}\0", contents.len()).as_bytes());
+    hasher.update(contents);
+    hasher.digest().to_string()
+}
+
+/// Check the downloaded `contents` of the new version of `change` against its blob sha
+///
+/// Catches a truncated or corrupt response. Submodules are skipped, their sha is the commit the
+/// submodule points to.
+fn verify_contents(change: &Change, contents: &[u8], strict: bool) -> Result<()> {
+    let Some(expected) = change.sha.as_deref() else {
+        return Ok(());
+    };
+    if matches!(change.kind, ChangeKind::Submodule { .. }) {
+        return Ok(());
+    }
+    let actual = blob_sha(contents);
+    if actual == expected {
+        return Ok(());
+    }
+    let message = format!(
+        "The contents of {} have the blob sha {actual} instead of {expected}, the download may be \
+        truncated or corrupt",
+        change.filename
+    );
+    if strict {
+        bail!(message);
+    }
+    log::warn!("{message}");
+    Ok(())
+}
+
+/// Get the decoded contents of a file from a GitHub contents api `url`
+///
+/// The contents are the raw bytes of the file, as binary files may not be valid UTF-8.
+///
+/// # Arguments
+/// * `url` - The contents api url of the file
+/// * `hostname` - The GitHub host to authenticate with instead of the default of `gh`
+/// * `retries` - How many times to retry a transient failure
+pub async fn url_contents(url: &str, hostname: Option<&str>, retries: u32) -> Result<Vec<u8>> {
+    let output = run_async_command(
+        [
+            "api",
+            "--method",
+            "GET",
+            "-H",
+            "Accept: application/vnd.github+json",
+            url,
+        ],
+        hostname,
+        retries,
+    )
     .await?;
 
     let content: Content = serde_json::from_str(output.as_str())?;
@@ -108,36 +593,87 @@ pub async fn file_contents(change: &Change) -> Result<String> {
     // in it, removing these newlines still leaves the newlines that are encoded
     // into the base64 string so the diff will still be good.
     let cleaned = content.content.replace('\n', "");
-    let bytes = STANDARD.decode(cleaned)?;
-    Ok(String::from_utf8(bytes)?)
+    Ok(STANDARD.decode(cleaned)?)
+}
+
+/// Get the decoded contents of a file from a GitHub contents api `url`, if the file exists
+///
+/// # Arguments
+/// * `url` - The contents api url of the file
+/// * `hostname` - The GitHub host to authenticate with instead of the default of `gh`
+/// * `retries` - How many times to retry a transient failure
+///
+/// # Returns
+/// `None` when GitHub responds that the file wasn't found.
+pub async fn url_contents_if_exists(
+    url: &str,
+    hostname: Option<&str>,
+    retries: u32,
+) -> Result<Option<Vec<u8>>> {
+    match url_contents(url, hostname, retries).await {
+        Ok(contents) => Ok(Some(contents)),
+        // `gh api` reports the HTTP status of a failed request in its error message
+        Err(error) if error.to_string().contains("HTTP 404") => Ok(None),
+        Err(error) => Err(error),
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct GhCli<C> {
     command: C,
+    hostname: Option<String>,
+    page_size: usize,
+    retries: u32,
 }
 
 impl<C: Cmd> GhCli<C> {
     pub fn new(command: C) -> Self {
-        Self { command }
+        Self {
+            command,
+            hostname: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Request `page_size` changes per page from GitHub
+    ///
+    /// GitHub may omit the `patch` of the last few changes on a page when the page is larger than
+    /// the default of [DEFAULT_PAGE_SIZE].
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Run `gh` against `hostname`, e.g. a GitHub Enterprise server, instead of its default host
+    pub fn with_hostname(mut self, hostname: Option<String>) -> Self {
+        self.hostname = hostname;
+        self
     }
 
-    fn run_command<I, T>(&mut self, args: I) -> Result<String>
+    /// Retry a command that fails with a rate limit or server error up to `retries` times
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    fn run_command<I, T>(&self, args: I) -> Result<String>
     where
         I: IntoIterator<Item = T>,
         T: AsRef<OsStr>,
     {
-        let mut command = self.command.new_from_self();
-        for arg in args {
-            command.arg(OsString::from(arg.as_ref()));
-        }
-        command.stdout(Stdio::piped());
-        command.stderr(Stdio::piped());
-        let output = command.output()?;
-        output_to_string(output)
+        run_gh(
+            &self.command,
+            &os_args(args),
+            self.hostname.as_deref(),
+            self.retries,
+        )
     }
 
-    pub fn change_set(&mut self, pr: &PullRequest) -> Result<ChangeSet> {
+    pub fn change_set(&mut self, pr: &PullRequest) -> Result<ChangeSet>
+    where
+        C: Sync,
+    {
         let repo = &pr.repo;
         let number = pr.number;
         let pr_path = format!("/repos/{repo}/pulls/{number}/files");
@@ -145,103 +681,444 @@ impl<C: Cmd> GhCli<C> {
         // The `gh` command line supports a `--paginate` flag which could potentially do this all
         // for us. When using paginate `gh` increases the items per page to the max of 100.
         // Unfortunately this results in the `patch` property being omitted on the last couple of
-        // entries. By doing it manually we keep the page size at 30 entries and are able to
-        // maintain the `patch` property on the files.
-        let (pages, mut changes) = self.changes_first_page(&pr_path)?;
-        for page in 2..=pages {
-            changes.extend(self.changes_subsequent_page(page, &pr_path)?);
+        // entries. By doing it manually we keep the page size at 30 entries, unless asked
+        // otherwise, and are able to maintain the `patch` property on the files.
+        let (next, mut changes) = self.changes_page_with_links(1, &pr_path)?;
+        let pages = match next {
+            NextPages::Last(pages) => pages,
+            NextPages::Next => {
+                log::debug!("Following the next links to the changes of {repo}#{number}");
+                self.follow_next_pages(&pr_path, &mut changes)?;
+                return Ok(ChangeSet::new(changes));
+            }
+            NextPages::Done => 1,
+        };
+        log::debug!("{pages} pages of changes for {repo}#{number}");
+
+        // The first page gives the page count, the rest are fetched a few at a time. Joining the
+        // threads in the order they were spawned keeps the changes in the order of the pages.
+        let pages = (2..=pages).collect::<Vec<_>>();
+        for batch in pages.chunks(MAX_CONCURRENT_PAGES) {
+            let gh = &*self;
+            let pr_path = &pr_path;
+            let batch = std::thread::scope(|scope| {
+                let fetches = batch
+                    .iter()
+                    .map(|&page| scope.spawn(move || gh.changes_subsequent_page(page, pr_path)))
+                    .collect::<Vec<_>>();
+                fetches
+                    .into_iter()
+                    .map(|fetch| fetch.join().expect("Fetching a page of changes panicked"))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            changes.extend(batch.into_iter().flatten());
         }
-        Ok(ChangeSet { changes })
+        Ok(ChangeSet::new(changes))
     }
 
     /// Get a page changes that is after the first page.
     ///
     /// Simplified logic that doesn't look at the link header
-    fn changes_subsequent_page(&mut self, page: usize, pr_path: &str) -> Result<Vec<Change>> {
+    fn changes_subsequent_page(&self, page: usize, pr_path: &str) -> Result<Vec<Change>> {
+        let mut args = vec!["api".to_string(), "--method".into(), "GET".into()];
+        args.extend(self.page_args(page));
+        args.push(pr_path.to_string());
+        let output = self.run_command(args)?;
+        Ok(serde_json::from_str(output.as_str())?)
+    }
+
+    /// The `gh api` arguments to request `page` of the changes
+    ///
+    /// The page size is only provided when it differs from GitHub's default.
+    fn page_args(&self, page: usize) -> Vec<String> {
+        let mut args = vec!["-F".to_string(), format!("page={page}")];
+        if self.page_size != DEFAULT_PAGE_SIZE {
+            args.extend(["-F".to_string(), format!("per_page={}", self.page_size)]);
+        }
+        args
+    }
+
+    /// Get the pages of changes after the first one, one at a time, for as long as each page links
+    /// to a next one
+    fn follow_next_pages(&self, pr_path: &str, changes: &mut Vec<Change>) -> Result<()> {
+        let mut page = 1;
+        let mut next = NextPages::Next;
+        while next.has_page_after(page) {
+            page += 1;
+            let (page_next, page_changes) = self.changes_page_with_links(page, pr_path)?;
+            changes.extend(page_changes);
+            next = page_next;
+        }
+        Ok(())
+    }
+
+    /// Get a page of changes along with what its link header says about the pages after it
+    ///
+    /// When no link header is present then there are no more pages of changes.
+    fn changes_page_with_links(
+        &self,
+        page: usize,
+        pr_path: &str,
+    ) -> Result<(NextPages, Vec<Change>)> {
+        let mut args = vec![
+            "api".to_string(),
+            "--method".into(),
+            "GET".into(),
+            "--include".into(),
+        ];
+        args.extend(self.page_args(page));
+        args.push(pr_path.to_string());
+        let output = self.run_command(args)?;
+        let next = match link_header(&output) {
+            Some(link) => NextPages::from_link_header(link)?,
+            None => NextPages::Done,
+        };
+        Ok((
+            next,
+            serde_json::from_str(output.as_str().lines().last().ok_or_else(|| {
+                Error::other(format!("Should have had multiple lines in {output}"))
+            })?)?,
+        ))
+    }
+
+    /// The commit sha of the base branch for `pr`
+    pub fn base_sha(&mut self, pr: &PullRequest) -> Result<String> {
+        Ok(self.base(pr)?.sha)
+    }
+
+    /// The name of the base branch for `pr`, e.g. "main"
+    pub fn base_branch(&mut self, pr: &PullRequest) -> Result<String> {
+        Ok(self.base(pr)?.name)
+    }
+
+    fn base(&mut self, pr: &PullRequest) -> Result<GitRef> {
+        let repo = &pr.repo;
+        let number = pr.number;
         let output = self.run_command([
             "api",
             "--method",
             "GET",
-            "-F",
-            &format!("page={page}"),
-            pr_path,
+            &format!("/repos/{repo}/pulls/{number}"),
         ])?;
-        Ok(serde_json::from_str(output.as_str())?)
+        let refs: PullRequestRefs = serde_json::from_str(output.as_str())?;
+        Ok(refs.base)
+    }
+
+    /// The commit sha of the head branch for `pr`
+    pub fn head_sha(&mut self, pr: &PullRequest) -> Result<String> {
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            &format!("/repos/{repo}/pulls/{number}"),
+        ])?;
+        let refs: PullRequestHead = serde_json::from_str(output.as_str())?;
+        Ok(refs.head.sha)
     }
 
-    /// Get the first page of changes
+    /// The changes from the merge base of `base` and `head` to `head` in `repo`
     ///
-    /// Will parse the link header, if present to provide the total number of pages available
-    /// When no link header is present then only one page worth of changes exists
-    fn changes_first_page(&mut self, pr_path: &str) -> Result<(usize, Vec<Change>)> {
+    /// GitHub limits a comparison to the first 300 files.
+    pub fn compare(&mut self, repo: &str, base: &str, head: &str) -> Result<ChangeSet> {
         let output = self.run_command([
             "api",
             "--method",
             "GET",
-            "--include",
-            "-F",
-            "page=1",
-            pr_path,
+            &format!("/repos/{repo}/compare/{base}...{head}"),
         ])?;
-        let pages = if let Some(link) = output.lines().find(|l| l.starts_with("Link:")) {
-            Self::changes_page_count(
-                link.strip_prefix("Link:")
-                    .expect("Prefix should have existed due to find call"),
-            )?
-        } else {
-            1
-        };
-        Ok((
-            pages,
-            serde_json::from_str(output.as_str().lines().last().ok_or_else(|| {
-                Error::new(
-                    ErrorKind::Other,
-                    format!("Should have had multiple lines in {output}"),
-                )
-            })?)?,
-        ))
+        let comparison: Comparison = serde_json::from_str(output.as_str())?;
+        Ok(ChangeSet::new(comparison.files))
     }
 
-    /// Number of pages that make up all of the changes in a pr.
-    fn changes_page_count(link_header: &str) -> Result<usize> {
-        let header = parse_link_header::parse_with_rel(link_header)?;
-        if let Some(entry) = header.get("last") {
-            let page = entry.queries.get("page").expect("Malformed link header");
-            Ok(page.parse().expect("Page is not a valid integer"))
-        } else {
-            panic!("Expected a total page count in the link header")
+    /// The git file mode, e.g. "100755" for an executable, of each path in the tree of commit `sha`
+    ///
+    /// GitHub truncates the listing of a large tree, the paths left out of it have no mode.
+    pub fn file_modes(&mut self, repo: &str, sha: &str) -> Result<HashMap<String, String>> {
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            &format!("/repos/{repo}/git/trees/{sha}?recursive=1"),
+        ])?;
+        let tree: Tree = serde_json::from_str(output.as_str())?;
+        if tree.truncated {
+            log::warn!(
+                "The tree of {sha} is too large to list fully, some files may not keep their \
+                executable bit"
+            );
         }
+        Ok(tree
+            .tree
+            .into_iter()
+            .map(|entry| (entry.path, entry.mode))
+            .collect())
     }
 
-    pub fn current_pr(&mut self) -> Result<usize> {
-        let output = self.run_command(["pr", "view", "--json", "number"])?;
-        let pr: PrNumber = serde_json::from_str(output.as_str())?;
-        Ok(pr.number)
+    /// The previous head commit of `pr` to review from for `--since`
+    ///
+    /// # Arguments
+    /// * `pr` - The pull request to review
+    /// * `since` - Where to review from
+    /// * `now` - The time a duration of `since` is before
+    pub fn since_commit(
+        &mut self,
+        pr: &PullRequest,
+        since: &Since,
+        now: SystemTime,
+    ) -> Result<String> {
+        match since {
+            Since::Ago(duration) => {
+                let now = now.duration_since(UNIX_EPOCH)?.as_secs();
+                self.commit_before(pr, now.saturating_sub(duration.as_secs()))
+            }
+            Since::LastReview => self.last_review_commit(pr),
+            Since::LastPush => self.last_push_before(pr),
+        }
     }
 
-    pub fn current_repo(&mut self) -> Result<String> {
-        let output = self.run_command(["repo", "view", "--json", "owner,name"])?;
-        let repo: Repo = serde_json::from_str(output.as_str())?;
-        Ok(repo.to_string())
+    /// The last commit of `pr` made at or before `cutoff`, seconds since the Unix epoch
+    ///
+    /// When all of the commits are newer, the base commit is used so everything is reviewed.
+    fn commit_before(&mut self, pr: &PullRequest, cutoff: u64) -> Result<String> {
+        let before = self
+            .commit_times(pr)?
+            .into_iter()
+            .rfind(|(_, time)| *time <= cutoff);
+        match before {
+            Some((sha, _)) => Ok(sha),
+            None => self.base_sha(pr),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::change_set::{Change, ChangeSet};
-    use httpmock::Method::GET;
-    use httpmock::MockServer;
-    use mockall::mock;
-    use mockall::predicate::eq;
-    use std::ffi::OsString;
-    use std::io;
-    #[cfg(unix)]
-    use std::os::unix::prelude::ExitStatusExt;
-    #[cfg(windows)]
-    use std::os::windows::process::ExitStatusExt;
-    use std::process::Stdio;
-    use std::process::{ExitStatus, Output};
+    /// The files changed by the commits of `pr` made after `cutoff`, seconds since the Unix
+    /// epoch, for `--touched-since`
+    pub fn files_touched_since(
+        &mut self,
+        pr: &PullRequest,
+        cutoff: u64,
+    ) -> Result<BTreeSet<String>> {
+        let repo = &pr.repo;
+        let mut files = BTreeSet::new();
+        for (sha, time) in self.commit_times(pr)? {
+            if time <= cutoff {
+                continue;
+            }
+            let output = self.run_command([
+                "api",
+                "--method",
+                "GET",
+                &format!("/repos/{repo}/commits/{sha}"),
+            ])?;
+            let commit: CommitFiles = serde_json::from_str(output.as_str())?;
+            files.extend(commit.files.into_iter().map(|file| file.filename));
+        }
+        Ok(files)
+    }
+
+    /// The commits of `pr`, oldest first, with the time each was made in seconds since the Unix
+    /// epoch
+    fn commit_times(&mut self, pr: &PullRequest) -> Result<Vec<(String, u64)>> {
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            "--paginate",
+            &format!("/repos/{repo}/pulls/{number}/commits"),
+        ])?;
+
+        let mut commits = vec![];
+        for page in serde_json::Deserializer::from_str(&output).into_iter::<Vec<PrCommit>>() {
+            for commit in page? {
+                let date = &commit.commit.committer.date;
+                let time = since::timestamp(date)
+                    .ok_or_else(|| anyhow!("Unexpected date {date} of commit {}", commit.sha))?;
+                commits.push((commit.sha, time));
+            }
+        }
+        Ok(commits)
+    }
+
+    /// The commit the current user last reviewed `pr` at
+    fn last_review_commit(&mut self, pr: &PullRequest) -> Result<String> {
+        let output = self.run_command(["api", "user"])?;
+        let user: User = serde_json::from_str(output.as_str())?;
+
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            "--paginate",
+            &format!("/repos/{repo}/pulls/{number}/reviews"),
+        ])?;
+        let mut last = None;
+        for page in serde_json::Deserializer::from_str(&output).into_iter::<Vec<Review>>() {
+            for review in page? {
+                if review.user.as_ref() == Some(&user) && review.commit_id.is_some() {
+                    last = review.commit_id;
+                }
+            }
+        }
+        last.ok_or_else(|| anyhow!("{} hasn't reviewed pull request {number}", user.login))
+    }
+
+    /// The head of `pr` before the last push to its branch
+    ///
+    /// Pushes are found in the events of the head repo, which GitHub only keeps for 90 days.
+    fn last_push_before(&mut self, pr: &PullRequest) -> Result<String> {
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            &format!("/repos/{repo}/pulls/{number}"),
+        ])?;
+        let head = serde_json::from_str::<PullRequestHeadBranch>(output.as_str())?.head;
+        let head_repo = head
+            .repo
+            .ok_or_else(|| anyhow!("The head repo of pull request {number} no longer exists"))?;
+
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            "--paginate",
+            &format!("/repos/{}/events", head_repo.full_name),
+        ])?;
+        let branch = format!("refs/heads/{}", head.name);
+        for page in serde_json::Deserializer::from_str(&output).into_iter::<Vec<RepoEvent>>() {
+            for event in page? {
+                let payload = event.payload;
+                if event.kind == "PushEvent"
+                    && payload.name.as_ref() == Some(&branch)
+                    && payload.head.as_ref() == Some(&head.sha)
+                {
+                    if let Some(before) = payload.before {
+                        return Ok(before);
+                    }
+                }
+            }
+        }
+        Err(anyhow!(
+            "No push of {} found for pull request {number}",
+            head.sha
+        ))
+    }
+
+    /// The changes suggested in the review comments of `pr`
+    ///
+    /// Outdated comments and comments on the base version of a file are skipped, their lines
+    /// don't refer to the head version of the file.
+    pub fn suggestions(&mut self, pr: &PullRequest) -> Result<Vec<Suggestion>> {
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            "--paginate",
+            &format!("/repos/{repo}/pulls/{number}/comments"),
+        ])?;
+
+        // `--paginate` writes each page as its own JSON array, one after the other
+        let mut suggestions = vec![];
+        for page in serde_json::Deserializer::from_str(&output).into_iter::<Vec<ReviewComment>>() {
+            for comment in page? {
+                let Some(end_line) = comment.line else {
+                    continue;
+                };
+                if comment.side.as_deref() == Some("LEFT") {
+                    continue;
+                }
+                let start_line = comment.start_line.unwrap_or(end_line);
+                suggestions.extend(suggestion_blocks(&comment.body).into_iter().map(
+                    |replacement| Suggestion {
+                        path: comment.path.clone(),
+                        start_line,
+                        end_line,
+                        replacement,
+                    },
+                ));
+            }
+        }
+        Ok(suggestions)
+    }
+
+    /// The commit sha of the test merge commit GitHub creates for `pr`
+    ///
+    /// # Errors
+    /// When GitHub hasn't created a merge commit, usually because the pull request has conflicts.
+    pub fn merge_sha(&mut self, pr: &PullRequest) -> Result<String> {
+        let repo = &pr.repo;
+        let number = pr.number;
+        let output = self.run_command([
+            "api",
+            "--method",
+            "GET",
+            &format!("/repos/{repo}/git/ref/pull/{number}/merge"),
+        ])?;
+        let reference: Reference = serde_json::from_str(output.as_str())?;
+        Ok(reference.object.sha)
+    }
+
+    pub fn current_pr(&mut self) -> Result<usize> {
+        let output = self.run_command(["pr", "view", "--json", "number"])?;
+        let pr: PrNumber = serde_json::from_str(output.as_str())?;
+        Ok(pr.number)
+    }
+
+    /// The number of the pull request whose head is `branch` in `repo`
+    pub fn branch_pr(&mut self, repo: &str, branch: &str) -> Result<usize> {
+        let output =
+            self.run_command(["pr", "view", branch, "--repo", repo, "--json", "number"])?;
+        let pr: PrNumber = serde_json::from_str(output.as_str())?;
+        Ok(pr.number)
+    }
+
+    /// Open `pr` in the browser, with `gh pr view --web`
+    pub fn open_in_browser(&mut self, pr: &PullRequest) -> Result<()> {
+        let number = pr.number.to_string();
+        self.run_command(["pr", "view", &number, "--repo", &pr.repo, "--web"])?;
+        Ok(())
+    }
+
+    /// The open pull requests for the current repo
+    pub fn open_prs(&mut self) -> Result<Vec<PrSummary>> {
+        let output = self.run_command(["pr", "list", "--json", "number,title"])?;
+        Ok(serde_json::from_str(output.as_str())?)
+    }
+
+    pub fn current_repo(&mut self) -> Result<String> {
+        let output = self.run_command(["repo", "view", "--json", "owner,name"])?;
+        let repo: Repo = serde_json::from_str(output.as_str())?;
+        Ok(repo.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change_set::{Change, ChangeKind, ChangeSet};
+    use httpmock::Method::GET;
+    use httpmock::MockServer;
+    use mockall::mock;
+    use mockall::predicate::eq;
+    use std::ffi::OsString;
+    use std::io;
+    #[cfg(unix)]
+    use std::os::unix::prelude::ExitStatusExt;
+    #[cfg(windows)]
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::process::{ExitStatus, Output};
+    use std::sync::{Arc, Mutex};
+    use yare::parameterized;
 
     mock! {
         C {}
@@ -249,6 +1126,7 @@ mod tests {
             fn arg(&mut self, arg: OsString) -> &mut Self;
             fn stdout(&mut self, cfg: Stdio) -> &mut Self;
             fn stderr(&mut self, cfg: Stdio) -> &mut Self;
+            fn env(&mut self, key: OsString, val: OsString) -> &mut Self;
             fn output(&mut self) -> io::Result<Output>;
             fn new_from_self(&self) -> Self;
         }
@@ -301,157 +1179,912 @@ mod tests {
         mock
     }
 
-    fn pr_number_mock(status: i32, stdout: impl AsRef<str>, stderr: impl AsRef<str>) -> MockC {
-        mocked_command(
-            &["pr", "view", "--json", "number"],
-            status,
-            stdout.as_ref(),
-            stderr.as_ref(),
-        )
+    fn pr_number_mock(status: i32, stdout: impl AsRef<str>, stderr: impl AsRef<str>) -> MockC {
+        mocked_command(
+            &["pr", "view", "--json", "number"],
+            status,
+            stdout.as_ref(),
+            stderr.as_ref(),
+        )
+    }
+
+    fn repo_mock(status: i32, stdout: impl AsRef<str>, stderr: impl AsRef<str>) -> MockC {
+        mocked_command(
+            &["repo", "view", "--json", "owner,name"],
+            status,
+            stdout.as_ref(),
+            stderr.as_ref(),
+        )
+    }
+
+    // The first file in the output from
+    // `gh api  -H "Accept: application/vnd.github+json"  /repos/speedyleion/gh-difftool/pulls/10/files`
+    const ONE_FILE: &str = r#"
+            [
+              {
+                "sha": "b0a3777df4afc764c34234524267970025d55467",
+                "filename": "Cargo.toml",
+                "status": "modified",
+                "additions": 4,
+                "deletions": 0,
+                "changes": 4,
+                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
+                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
+                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
+                "patch": "@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\""
+                }
+            ]
+        "#;
+
+    // The first 2 files in the output from
+    // `gh api  -H "Accept: application/vnd.github+json"  /repos/speedyleion/gh-difftool/pulls/10/files`
+    const TWO_FILES: &str = r#"
+            [
+              {
+                "sha": "b0a3777df4afc764c34234524267970025d55467",
+                "filename": "Cargo.toml",
+                "status": "modified",
+                "additions": 4,
+                "deletions": 0,
+                "changes": 4,
+                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
+                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
+                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
+                "patch": "@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\""
+                },
+                {
+                "sha": "cb71da67691cdf5f595b4e64d4feaf0bdd7798f6",
+                "filename": "src/main.rs",
+                "status": "modified",
+                "additions": 1,
+                "deletions": 0,
+                "changes": 1,
+                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/src%2Fmain.rs",
+                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/src%2Fmain.rs",
+                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/src%2Fmain.rs?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
+                "patch": "@@ -1,4 +1,5 @@\n mod gh_interface;\n+mod patch;\n \n fn main() {\n     println!(\"Hello, world!\");"
+                }
+            ]
+        "#;
+
+    #[test]
+    fn page_size_passed_to_gh() {
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "--include",
+                "-F",
+                "page=1",
+                "-F",
+                "per_page=100",
+                "/repos/speedyleion/gh-difftool/pulls/10/files",
+            ],
+            0,
+            &ONE_FILE.replace("\n", ""),
+            "",
+        );
+        let mut gh = GhCli::new(mock).with_page_size(100);
+        let change_set = gh
+            .change_set(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10,
+            })
+            .unwrap();
+        assert_eq!(change_set.changes.len(), 1);
+    }
+
+    /// A mock of `gh api` for the changes of a pull request with `pages` pages, each page has
+    /// one change named after the page
+    ///
+    /// With `only_next` each page links to the next one, in a lowercase header, instead of the
+    /// first page linking to the last.
+    fn paged_mock(pages: usize, only_next: bool) -> MockC {
+        let mut mock = MockC::new();
+        mock.expect_new_from_self().returning(move || {
+            let page = Arc::new(Mutex::new(0));
+            let mut command = MockC::new();
+            let arg_page = Arc::clone(&page);
+            command.expect_arg().returning(move |arg| {
+                if let Some(number) = arg.to_str().and_then(|arg| arg.strip_prefix("page=")) {
+                    *arg_page.lock().unwrap() = number.parse::<usize>().unwrap();
+                }
+                MockC::new()
+            });
+            command.expect_stdout().returning(|_| MockC::new());
+            command.expect_stderr().returning(|_| MockC::new());
+            command.expect_output().returning(move || {
+                let page = *page.lock().unwrap();
+                let changes = format!(
+                    r#"[{{"filename": "page{page}.rs", "contents_url": "url", "status": "added"}}]"#
+                );
+                let stdout = match page {
+                    _ if only_next && page < pages => format!(
+                        "HTTP/2.0 200 OK\nlink: <https://api.github.com/repositories/1/pulls/10/files?page={}>; rel=\"next\"\n\n{changes}",
+                        page + 1
+                    ),
+                    _ if only_next => format!("HTTP/2.0 200 OK\n\n{changes}"),
+                    1 => format!(
+                        "HTTP/2.0 200 OK\nLink: <https://api.github.com/repositories/1/pulls/10/files?page=2>; rel=\"next\", <https://api.github.com/repositories/1/pulls/10/files?page={pages}>; rel=\"last\"\n\n{changes}"
+                    ),
+                    _ => changes,
+                };
+                Ok(output(0, &stdout, ""))
+            });
+            command
+        });
+        mock
+    }
+
+    #[parameterized(
+    last_link = { false },
+    only_next_links = { true },
+    )]
+    fn pages_of_changes_in_order(only_next: bool) {
+        let mut gh = GhCli::new(paged_mock(6, only_next));
+        let change_set = gh
+            .change_set(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10,
+            })
+            .unwrap();
+
+        let filenames = change_set
+            .changes
+            .iter()
+            .map(|change| change.filename.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            ["page1.rs", "page2.rs", "page3.rs", "page4.rs", "page5.rs", "page6.rs"]
+        );
+    }
+
+    #[parameterized(
+    capitalized = { "HTTP/2.0 200 OK\nLink: <url>; rel=\"next\"\n\n[]", Some(" <url>; rel=\"next\"") },
+    lowercase = { "HTTP/2.0 200 OK\r\nlink: <url>; rel=\"next\"\r\n\r\n[]", Some(" <url>; rel=\"next\"") },
+    missing = { "HTTP/2.0 200 OK\n\n[]", None },
+    only_in_body = { "HTTP/2.0 200 OK\n\nlink: <url>", None },
+    )]
+    fn link_header_found(output: &str, expected: Option<&str>) {
+        assert_eq!(link_header(output), expected);
+    }
+
+    #[parameterized(
+    last = { "<https://api.github.com/x?page=2>; rel=\"next\", <https://api.github.com/x?page=5>; rel=\"last\"", NextPages::Last(5) },
+    only_next = { "<https://api.github.com/x?page=2>; rel=\"next\"", NextPages::Next },
+    only_previous = { "<https://api.github.com/x?page=1>; rel=\"prev\"", NextPages::Done },
+    )]
+    fn next_pages_from_link_header(link: &str, expected: NextPages) {
+        assert_eq!(NextPages::from_link_header(link).unwrap(), expected);
+    }
+
+    #[parameterized(
+    non_numeric_page = { "<https://api.github.com/x?page=two>; rel=\"last\"" },
+    no_page = { "<https://api.github.com/x?per_page=30>; rel=\"last\"" },
+    not_a_link = { "page=5; rel=last" },
+    )]
+    fn malformed_link_header_errors(link: &str) {
+        assert!(NextPages::from_link_header(link).is_err());
+    }
+
+    #[test]
+    fn single_change_available() {
+        let mock = change_set_mock(0, &ONE_FILE.replace("\n", ""), "");
+        let mut gh = GhCli::new(mock);
+        assert_eq!(gh.change_set(&PullRequest{ repo: "speedyleion/gh-difftool".to_string(), number: 10}).unwrap(),
+            ChangeSet {
+                changes: vec![Change {
+                    filename: String::from("Cargo.toml"),
+                    contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
+                    patch: Some("@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\"".into()),
+                    status: String::from("modified"),
+                    previous_filename: None,
+                    changes: 4,
+                    additions: 4,
+                    deletions: 0,
+                    sha: Some(String::from("b0a3777df4afc764c34234524267970025d55467")),
+                    kind: ChangeKind::File,
+                }]
+            }
+        );
+    }
+
+    #[test]
+    fn change_set_available() {
+        let mock = change_set_mock(0, &TWO_FILES.replace("\n", ""), "");
+        let mut gh = GhCli::new(mock);
+        assert_eq!(gh.change_set(&PullRequest{ repo: "speedyleion/gh-difftool".to_string(), number: 10}).unwrap(),
+            ChangeSet {
+                changes: vec![
+                    Change {
+                        filename: String::from("Cargo.toml"),
+                        contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
+                        patch: Some("@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\"".into()),
+                        status: String::from("modified"),
+                        previous_filename: None,
+                        changes: 4,
+                        additions: 4,
+                        deletions: 0,
+                        sha: Some(String::from("b0a3777df4afc764c34234524267970025d55467")),
+                        kind: ChangeKind::File,
+                    },
+                    Change {
+                        filename: String::from("src/main.rs"),
+                        contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/src%2Fmain.rs?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
+                        patch: Some("@@ -1,4 +1,5 @@\n mod gh_interface;\n+mod patch;\n \n fn main() {\n     println!(\"Hello, world!\");".into()),
+                        status: String::from("modified"),
+                        previous_filename: None,
+                        changes: 1,
+                        additions: 1,
+                        deletions: 0,
+                        sha: Some(String::from("cb71da67691cdf5f595b4e64d4feaf0bdd7798f6")),
+                        kind: ChangeKind::File,
+                    },
+                ]
+            }
+        );
+    }
+    #[test]
+    fn no_pr_change_set_available() {
+        // The output from a non existent pr
+        let expected = r#"
+            {
+              "message": "Not Found",
+              "documentation_url": "https://docs.github.com/rest/reference/pulls#list-pull-requests-files"
+            }
+        "#;
+        let mock = change_set_mock(1, &expected.replace("\n", ""), "gh: Not Found (HTTP 404)");
+        let mut gh = GhCli::new(mock);
+        let error = gh
+            .change_set(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10,
+            })
+            .unwrap_err();
+        let root_cause = error.root_cause();
+        assert_eq!(format!("{}", root_cause), "gh: Not Found (HTTP 404)");
+    }
+
+    #[test]
+    fn bad_json() {
+        let bad_json = r#"
+            [
+        "#;
+        let mock = change_set_mock(0, &bad_json.replace("\n", ""), "");
+        let mut gh = GhCli::new(mock);
+        let error = gh
+            .change_set(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10,
+            })
+            .unwrap_err();
+        let root_cause = error.root_cause();
+        assert_eq!(
+            format!("{}", root_cause),
+            "EOF while parsing a list at line 1 column 21"
+        );
+    }
+
+    const BASE_PR_JSON: &str = r#"
+        {
+            "number": 10,
+            "base": {
+                "ref": "main",
+                "sha": "0f3e6a2d1c6b1e0b9f6c2a1e8d7f6e5d4c3b2a19"
+            }
+        }
+    "#;
+
+    #[test]
+    fn base_branch_of_pr() {
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/pulls/10",
+            ],
+            0,
+            BASE_PR_JSON,
+            "",
+        );
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.base_branch(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10
+            })
+            .unwrap(),
+            "main"
+        );
+    }
+
+    #[test]
+    fn base_sha_of_pr() {
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/pulls/10",
+            ],
+            0,
+            BASE_PR_JSON,
+            "",
+        );
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.base_sha(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10
+            })
+            .unwrap(),
+            "0f3e6a2d1c6b1e0b9f6c2a1e8d7f6e5d4c3b2a19"
+        );
+    }
+
+    #[test]
+    fn head_sha_of_pr() {
+        let pr_json = r#"
+            {
+                "number": 10,
+                "head": {
+                    "ref": "feature",
+                    "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+                }
+            }
+        "#;
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/pulls/10",
+            ],
+            0,
+            pr_json,
+            "",
+        );
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.head_sha(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10
+            })
+            .unwrap(),
+            "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+        );
+    }
+
+    #[test]
+    fn suggestions_from_review_comments() {
+        let comments_json = r#"
+            [
+                {
+                    "path": "src/main.rs",
+                    "body": "Rename?\n```suggestion\nlet count = 3;\n```",
+                    "line": 12,
+                    "start_line": null,
+                    "side": "RIGHT"
+                },
+                {
+                    "path": "src/main.rs",
+                    "body": "```suggestion\nold\n```",
+                    "line": null,
+                    "start_line": null,
+                    "side": "RIGHT"
+                }
+            ]
+            [
+                {
+                    "path": "README.md",
+                    "body": "```suggestion\nFirst\nSecond\n```",
+                    "line": 4,
+                    "start_line": 2,
+                    "side": "RIGHT"
+                },
+                {
+                    "path": "README.md",
+                    "body": "```suggestion\nBase\n```",
+                    "line": 1,
+                    "start_line": null,
+                    "side": "LEFT"
+                },
+                {
+                    "path": "README.md",
+                    "body": "Just a comment",
+                    "line": 1,
+                    "start_line": null,
+                    "side": "RIGHT"
+                }
+            ]
+        "#;
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "--paginate",
+                "/repos/speedyleion/gh-difftool/pulls/10/comments",
+            ],
+            0,
+            comments_json,
+            "",
+        );
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.suggestions(&PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 10
+            })
+            .unwrap(),
+            [
+                Suggestion {
+                    path: "src/main.rs".to_string(),
+                    start_line: 12,
+                    end_line: 12,
+                    replacement: "let count = 3;".to_string(),
+                },
+                Suggestion {
+                    path: "README.md".to_string(),
+                    start_line: 2,
+                    end_line: 4,
+                    replacement: "First\nSecond".to_string(),
+                },
+            ]
+        );
+    }
+
+    fn output(status: i32, stdout: &str, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(status.try_into().unwrap()),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    /// A mock whose commands produce each of the `outputs` in turn, regardless of the arguments
+    fn sequenced_mock(outputs: Vec<Output>) -> MockC {
+        let mut mock = MockC::new();
+        let times = outputs.len();
+        let mut outputs = outputs.into_iter();
+        mock.expect_new_from_self().times(times).returning(move || {
+            let output = outputs.next().unwrap();
+            let mut mock = MockC::new();
+            mock.expect_arg().returning(|_| MockC::new());
+            mock.expect_stdout().times(1).returning(|_| MockC::new());
+            mock.expect_stderr().times(1).returning(|_| MockC::new());
+            mock.expect_output()
+                .times(1)
+                .return_once(move || Ok(output));
+            mock
+        });
+        mock
+    }
+
+    fn pr() -> PullRequest {
+        PullRequest {
+            repo: "me/repo".to_string(),
+            number: 3,
+        }
+    }
+
+    // Two pages of `gh api --paginate /repos/me/repo/pulls/3/commits`, trimmed
+    const PR_COMMITS: &str = r#"
+        [
+            {"sha": "first", "commit": {"committer": {"date": "2024-03-01T10:00:00Z"}}},
+            {"sha": "second", "commit": {"committer": {"date": "2024-03-04T10:00:00Z"}}}
+        ]
+        [
+            {"sha": "third", "commit": {"committer": {"date": "2024-03-06T10:00:00Z"}}}
+        ]
+    "#;
+
+    #[parameterized(
+    between_commits = { "2024-03-07T00:00:00Z", 2, "second" },
+    after_all_commits = { "2024-03-07T00:00:00Z", 0, "third" },
+    )]
+    fn since_duration_ago(now: &str, days: u64, expected: &str) {
+        let mock = sequenced_mock(vec![output(0, PR_COMMITS, "")]);
+        let mut gh = GhCli::new(mock);
+        let now = UNIX_EPOCH + Duration::from_secs(since::timestamp(now).unwrap());
+        let since = Since::Ago(Duration::from_secs(days * 24 * 60 * 60));
+        assert_eq!(gh.since_commit(&pr(), &since, now).unwrap(), expected);
+    }
+
+    #[test]
+    fn files_touched_after_date() {
+        let mock = sequenced_mock(vec![
+            output(0, PR_COMMITS, ""),
+            output(
+                0,
+                r#"{"sha": "second", "files": [{"filename": "a.rs"}, {"filename": "b.rs"}]}"#,
+                "",
+            ),
+            output(
+                0,
+                r#"{"sha": "third", "files": [{"filename": "b.rs"}, {"filename": "c.rs"}]}"#,
+                "",
+            ),
+        ]);
+        let mut gh = GhCli::new(mock);
+        let cutoff = since::timestamp("2024-03-02T00:00:00Z").unwrap();
+        assert_eq!(
+            gh.files_touched_since(&pr(), cutoff).unwrap(),
+            BTreeSet::from(["a.rs".to_string(), "b.rs".to_string(), "c.rs".to_string()])
+        );
+    }
+
+    #[test]
+    fn since_duration_before_all_commits_is_base() {
+        let mock = sequenced_mock(vec![
+            output(0, PR_COMMITS, ""),
+            output(0, r#"{"base": {"sha": "base"}}"#, ""),
+        ]);
+        let mut gh = GhCli::new(mock);
+        let now =
+            UNIX_EPOCH + Duration::from_secs(since::timestamp("2024-03-07T00:00:00Z").unwrap());
+        let since = Since::Ago(Duration::from_secs(30 * 24 * 60 * 60));
+        assert_eq!(gh.since_commit(&pr(), &since, now).unwrap(), "base");
+    }
+
+    #[test]
+    fn since_last_review() {
+        let reviews = r#"
+            [
+                {"user": {"login": "me"}, "commit_id": "reviewed"},
+                {"user": {"login": "someone"}, "commit_id": "theirs"},
+                {"user": null, "commit_id": "ghost"}
+            ]
+        "#;
+        let mock = sequenced_mock(vec![
+            output(0, r#"{"login": "me"}"#, ""),
+            output(0, reviews, ""),
+        ]);
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.since_commit(&pr(), &Since::LastReview, SystemTime::now())
+                .unwrap(),
+            "reviewed"
+        );
+    }
+
+    #[test]
+    fn since_last_review_without_review() {
+        let mock = sequenced_mock(vec![
+            output(0, r#"{"login": "me"}"#, ""),
+            output(0, "[]", ""),
+        ]);
+        let mut gh = GhCli::new(mock);
+        let error = gh
+            .since_commit(&pr(), &Since::LastReview, SystemTime::now())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "me hasn't reviewed pull request 3");
+    }
+
+    #[test]
+    fn since_last_push() {
+        let pull = r#"
+            {"head": {"sha": "new", "ref": "feature", "repo": {"full_name": "fork/repo"}}}
+        "#;
+        let events = r#"
+            [
+                {"type": "IssueCommentEvent", "payload": {"action": "created"}},
+                {"type": "PushEvent", "payload": {"ref": "refs/heads/other", "head": "new", "before": "wrong"}},
+                {"type": "PushEvent", "payload": {"ref": "refs/heads/feature", "head": "new", "before": "old"}}
+            ]
+        "#;
+        let mock = sequenced_mock(vec![output(0, pull, ""), output(0, events, "")]);
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.since_commit(&pr(), &Since::LastPush, SystemTime::now())
+                .unwrap(),
+            "old"
+        );
+    }
+
+    #[parameterized(
+    bad_credentials = { "gh: Bad credentials (HTTP 401)\n" },
+    not_logged_in = { "To get started with GitHub CLI, please run:  gh auth login\n" },
+    )]
+    fn auth_error_has_guidance(stderr: &str) {
+        let error = output_to_string(output(1, "", stderr)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("{}\n{AUTH_HELP}", stderr.trim_end())
+        );
+    }
+
+    #[test]
+    fn other_errors_unchanged() {
+        let error = output_to_string(output(1, "", "gh: Not Found (HTTP 404)")).unwrap_err();
+        assert_eq!(error.to_string(), "gh: Not Found (HTTP 404)");
     }
 
-    fn repo_mock(status: i32, stdout: impl AsRef<str>, stderr: impl AsRef<str>) -> MockC {
-        mocked_command(
-            &["repo", "view", "--json", "owner,name"],
-            status,
-            stdout.as_ref(),
-            stderr.as_ref(),
-        )
+    const RATE_LIMITED: &str = "HTTP/2.0 429 Too Many Requests\nRetry-After: 0\n\n{}";
+
+    #[parameterized(
+    success = { 0, 0, "{}", "", None },
+    server_error = { 1, 0, "", "gh: Bad Gateway (HTTP 502)", Some(1) },
+    server_error_backs_off = { 1, 2, "", "gh: Service Unavailable (HTTP 503)", Some(4) },
+    not_found = { 1, 0, "", "gh: Not Found (HTTP 404)", None },
+    bad_credentials = { 1, 0, "", "gh: Bad credentials (HTTP 401)", None },
+    forbidden = { 1, 0, "", "gh: Resource not accessible by integration (HTTP 403)", None },
+    rate_limit = { 1, 1, "", "gh: API rate limit exceeded for user ID 1. (HTTP 403)", Some(2) },
+    retry_after = { 1, 0, "HTTP/2.0 429 Too Many Requests\nRetry-After: 5\n\n{}", "gh: HTTP 429 (HTTP 429)", Some(5) },
+    retry_after_too_long = { 1, 0, "HTTP/2.0 429 Too Many Requests\nretry-after: 3600\n\n{}", "gh: HTTP 429 (HTTP 429)", None },
+    no_http_status = { 1, 0, "", "error connecting to api.github.com", None },
+    )]
+    fn delay_before_retry(
+        status: i32,
+        attempt: u32,
+        stdout: &str,
+        stderr: &str,
+        expected: Option<u64>,
+    ) {
+        assert_eq!(
+            retry_delay(&output(status, stdout, stderr), attempt),
+            expected.map(Duration::from_secs)
+        );
     }
 
-    // The first file in the output from
-    // `gh api  -H "Accept: application/vnd.github+json"  /repos/speedyleion/gh-difftool/pulls/10/files`
-    const ONE_FILE: &str = r#"
-            [
-              {
-                "sha": "b0a3777df4afc764c34234524267970025d55467",
-                "filename": "Cargo.toml",
-                "status": "modified",
-                "additions": 4,
-                "deletions": 0,
-                "changes": 4,
-                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
-                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
-                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
-                "patch": "@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\""
-                }
-            ]
-        "#;
+    #[test]
+    fn rate_limited_command_retried() {
+        let mock = sequenced_mock(vec![
+            output(1, RATE_LIMITED, "gh: HTTP 429 (HTTP 429)"),
+            output(0, r#"{"number": 5}"#, ""),
+        ]);
+        let mut gh = GhCli::new(mock);
+        assert_eq!(gh.current_pr().unwrap(), 5);
+    }
 
-    // The first 2 files in the output from
-    // `gh api  -H "Accept: application/vnd.github+json"  /repos/speedyleion/gh-difftool/pulls/10/files`
-    const TWO_FILES: &str = r#"
-            [
-              {
-                "sha": "b0a3777df4afc764c34234524267970025d55467",
-                "filename": "Cargo.toml",
-                "status": "modified",
-                "additions": 4,
-                "deletions": 0,
-                "changes": 4,
-                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
-                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/Cargo.toml",
-                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
-                "patch": "@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\""
-                },
-                {
-                "sha": "cb71da67691cdf5f595b4e64d4feaf0bdd7798f6",
-                "filename": "src/main.rs",
-                "status": "modified",
-                "additions": 1,
-                "deletions": 0,
-                "changes": 1,
-                "blob_url": "https://github.com/speedyleion/gh-difftool/blob/befb7bf69c3c8ba97c714d57c8dadd9621021c84/src%2Fmain.rs",
-                "raw_url": "https://github.com/speedyleion/gh-difftool/raw/befb7bf69c3c8ba97c714d57c8dadd9621021c84/src%2Fmain.rs",
-                "contents_url": "https://api.github.com/repos/speedyleion/gh-difftool/contents/src%2Fmain.rs?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84",
-                "patch": "@@ -1,4 +1,5 @@\n mod gh_interface;\n+mod patch;\n \n fn main() {\n     println!(\"Hello, world!\");"
-                }
-            ]
-        "#;
+    #[test]
+    fn retries_exhausted() {
+        let mock = sequenced_mock(vec![
+            output(1, RATE_LIMITED, "gh: HTTP 429 (HTTP 429)"),
+            output(1, RATE_LIMITED, "gh: HTTP 429 (HTTP 429)"),
+        ]);
+        let mut gh = GhCli::new(mock).with_retries(1);
+        assert!(gh.current_pr().is_err());
+    }
 
     #[test]
-    fn single_change_available() {
-        let mock = change_set_mock(0, &ONE_FILE.replace("\n", ""), "");
+    fn not_found_fails_without_retry() {
+        let mock = sequenced_mock(vec![output(1, "", "gh: Not Found (HTTP 404)")]);
         let mut gh = GhCli::new(mock);
-        assert_eq!(gh.change_set(&PullRequest{ repo: "speedyleion/gh-difftool".to_string(), number: 10}).unwrap(),
-            ChangeSet {
-                changes: vec![Change {
-                    filename: String::from("Cargo.toml"),
-                    contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
-                    patch: Some("@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\"".into()),
-                    status: String::from("modified"),
-                    previous_filename: None,
-                }]
+        assert!(gh.current_pr().is_err());
+    }
+
+    #[test]
+    fn modes_of_tree() {
+        // Trimmed output of `gh api /repos/OWNER/REPO/git/trees/SHA?recursive=1`
+        let tree_json = r#"
+            {
+                "sha": "head",
+                "tree": [
+                    {"path": "build.sh", "mode": "100755", "type": "blob", "sha": "1"},
+                    {"path": "src", "mode": "040000", "type": "tree", "sha": "2"},
+                    {"path": "src/main.rs", "mode": "100644", "type": "blob", "sha": "3"}
+                ],
+                "truncated": false
             }
+        "#;
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/me/repo/git/trees/head?recursive=1",
+            ],
+            0,
+            tree_json,
+            "",
         );
+        let mut gh = GhCli::new(mock);
+        let modes = gh.file_modes("me/repo", "head").unwrap();
+        assert_eq!(modes["build.sh"], "100755");
+        assert_eq!(modes["src/main.rs"], "100644");
     }
 
     #[test]
-    fn change_set_available() {
-        let mock = change_set_mock(0, &TWO_FILES.replace("\n", ""), "");
+    fn modes_of_truncated_tree() {
+        let tree_json = r#"
+            {
+                "sha": "head",
+                "tree": [
+                    {"path": "build.sh", "mode": "100755", "type": "blob", "sha": "1"}
+                ],
+                "truncated": true
+            }
+        "#;
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/me/repo/git/trees/head?recursive=1",
+            ],
+            0,
+            tree_json,
+            "",
+        );
         let mut gh = GhCli::new(mock);
-        assert_eq!(gh.change_set(&PullRequest{ repo: "speedyleion/gh-difftool".to_string(), number: 10}).unwrap(),
-            ChangeSet {
-                changes: vec![
-                    Change {
-                        filename: String::from("Cargo.toml"),
-                        contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/Cargo.toml?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
-                        patch: Some("@@ -6,3 +6,7 @@ edition = \"2021\"\n [dev-dependencies]\n assert_cmd = \"2.0.4\"\n mockall = \"0.11.2\"\n+textwrap = \"0.15.1\"\n+\n+[dependencies]\n+patch = \"0.6.0\"".into()),
-                        status: String::from("modified"),
-                        previous_filename: None,
-                    },
-                    Change {
-                        filename: String::from("src/main.rs"),
-                        contents_url: String::from("https://api.github.com/repos/speedyleion/gh-difftool/contents/src%2Fmain.rs?ref=befb7bf69c3c8ba97c714d57c8dadd9621021c84"),
-                        patch: Some("@@ -1,4 +1,5 @@\n mod gh_interface;\n+mod patch;\n \n fn main() {\n     println!(\"Hello, world!\");".into()),
-                        status: String::from("modified"),
-                        previous_filename: None,
-                    },
+        let modes = gh.file_modes("me/repo", "head").unwrap();
+        assert_eq!(
+            modes,
+            HashMap::from([("build.sh".to_string(), "100755".to_string())])
+        );
+    }
+
+    #[test]
+    fn compare_commits() {
+        let compare_json = r#"
+            {
+                "status": "ahead",
+                "files": [
+                    {
+                        "sha": "b0a3777df4afc764c34234524267970025d55467",
+                        "filename": "Cargo.toml",
+                        "status": "modified",
+                        "changes": 1,
+                        "contents_url": "stuff",
+                        "patch": "@@ -1 +1 @@"
+                    }
                 ]
             }
+        "#;
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/compare/base...head",
+            ],
+            0,
+            compare_json,
+            "",
         );
+        let mut gh = GhCli::new(mock);
+        let change_set = gh
+            .compare("speedyleion/gh-difftool", "base", "head")
+            .unwrap();
+        assert_eq!(change_set.changes.len(), 1);
+        assert_eq!(change_set.changes[0].filename, "Cargo.toml");
     }
+
     #[test]
-    fn no_pr_change_set_available() {
-        // The output from a non existent pr
-        let expected = r#"
+    fn merge_sha_of_pr() {
+        let ref_json = r#"
             {
-              "message": "Not Found",
-              "documentation_url": "https://docs.github.com/rest/reference/pulls#list-pull-requests-files"
+                "ref": "refs/pull/10/merge",
+                "object": {
+                    "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                    "type": "commit"
+                }
             }
         "#;
-        let mock = change_set_mock(1, &expected.replace("\n", ""), "gh: Not Found (HTTP 404)");
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/git/ref/pull/10/merge",
+            ],
+            0,
+            ref_json,
+            "",
+        );
         let mut gh = GhCli::new(mock);
-        let error = gh
-            .change_set(&PullRequest {
+        assert_eq!(
+            gh.merge_sha(&PullRequest {
                 repo: "speedyleion/gh-difftool".to_string(),
-                number: 10,
+                number: 10
             })
-            .unwrap_err();
-        let root_cause = error.root_cause();
-        assert_eq!(format!("{}", root_cause), "gh: Not Found (HTTP 404)");
+            .unwrap(),
+            "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+        );
     }
 
     #[test]
-    fn bad_json() {
-        let bad_json = r#"
-            [
-        "#;
-        let mock = change_set_mock(0, &bad_json.replace("\n", ""), "");
+    fn no_merge_ref_is_an_error() {
+        let mock = mocked_command(
+            &[
+                "api",
+                "--method",
+                "GET",
+                "/repos/speedyleion/gh-difftool/git/ref/pull/10/merge",
+            ],
+            1,
+            "",
+            "gh: Not Found (HTTP 404)",
+        );
         let mut gh = GhCli::new(mock);
         let error = gh
-            .change_set(&PullRequest {
+            .merge_sha(&PullRequest {
                 repo: "speedyleion/gh-difftool".to_string(),
                 number: 10,
             })
             .unwrap_err();
-        let root_cause = error.root_cause();
         assert_eq!(
-            format!("{}", root_cause),
-            "EOF while parsing a list at line 1 column 21"
+            format!("{}", error.root_cause()),
+            "gh: Not Found (HTTP 404)"
+        );
+    }
+
+    #[test]
+    fn pull_request_from_event() {
+        let event = r#"
+            {
+              "action": "synchronize",
+              "number": 42,
+              "pull_request": {
+                "number": 42,
+                "title": "Add a feature",
+                "head": {"ref": "feature", "sha": "abc123"}
+              },
+              "repository": {
+                "name": "gh-difftool",
+                "full_name": "speedyleion/gh-difftool",
+                "owner": {"login": "speedyleion"}
+              }
+            }
+        "#;
+        assert_eq!(
+            PullRequest::from_event_json(event).unwrap(),
+            Some(PullRequest {
+                repo: "speedyleion/gh-difftool".to_string(),
+                number: 42,
+            })
         );
     }
 
+    #[test]
+    fn push_event_is_not_a_pull_request() {
+        let event = r#"
+            {
+              "ref": "refs/heads/main",
+              "repository": {"full_name": "speedyleion/gh-difftool"}
+            }
+        "#;
+        assert_eq!(PullRequest::from_event_json(event).unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_event() {
+        let event = r#"{"pull_request": {"number": 42}}"#;
+        assert!(PullRequest::from_event_json(event).is_err());
+    }
+
+    #[test]
+    fn hostname_sets_gh_host() {
+        let mut mock = MockC::new();
+        mock.expect_new_from_self().returning(|| {
+            let mut mock = MockC::new();
+            mock.expect_arg().times(4).returning(|_| MockC::new());
+            mock.expect_env()
+                .with(
+                    eq(OsString::from("GH_HOST")),
+                    eq(OsString::from("ghe.example.com")),
+                )
+                .times(1)
+                .returning(|_, _| MockC::new());
+            mock.expect_stdout().times(1).returning(|_| MockC::new());
+            mock.expect_stderr().times(1).returning(|_| MockC::new());
+            mock.expect_output().times(1).returning(|| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: br#"{"number": 3}"#.to_vec(),
+                    stderr: vec![],
+                })
+            });
+            mock
+        });
+        let mut gh = GhCli::new(mock).with_hostname(Some("ghe.example.com".to_string()));
+        assert_eq!(gh.current_pr().unwrap(), 3);
+    }
+
     #[test]
     fn current_pr_number_is_10() {
         let pr_json = r#"
@@ -503,6 +2136,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pr_from_branch() {
+        let mock = mocked_command(
+            &[
+                "pr",
+                "view",
+                "my-branch",
+                "--repo",
+                "me/repo",
+                "--json",
+                "number",
+            ],
+            0,
+            r#"{"number": 12}"#,
+            "",
+        );
+        let mut gh = GhCli::new(mock);
+        assert_eq!(gh.branch_pr("me/repo", "my-branch").unwrap(), 12);
+    }
+
+    #[test]
+    fn pr_opened_in_browser() {
+        let mock = mocked_command(
+            &["pr", "view", "12", "--repo", "me/repo", "--web"],
+            0,
+            "",
+            "Opening github.com/me/repo/pull/12 in your browser.\n",
+        );
+        let mut gh = GhCli::new(mock);
+        let pr = PullRequest {
+            repo: "me/repo".to_string(),
+            number: 12,
+        };
+        assert!(gh.open_in_browser(&pr).is_ok());
+    }
+
+    #[test]
+    fn no_pr_for_branch() {
+        let mock = mocked_command(
+            &[
+                "pr", "view", "what", "--repo", "me/repo", "--json", "number",
+            ],
+            1,
+            "",
+            "no pull requests found for branch \"what\"",
+        );
+        let mut gh = GhCli::new(mock);
+        let error = gh.branch_pr("me/repo", "what").unwrap_err();
+        assert_eq!(
+            format!("{}", error.root_cause()),
+            "no pull requests found for branch \"what\""
+        );
+    }
+
+    #[test]
+    fn open_prs_listed() {
+        // Output of `gh pr list --json number,title`
+        let prs_json = r#"
+            [
+                {
+                    "number": 12,
+                    "title": "Add a feature"
+                },
+                {
+                    "number": 9,
+                    "title": "Fix a bug"
+                }
+            ]
+        "#;
+        let mock = mocked_command(&["pr", "list", "--json", "number,title"], 0, prs_json, "");
+        let mut gh = GhCli::new(mock);
+        assert_eq!(
+            gh.open_prs().unwrap(),
+            vec![
+                PrSummary {
+                    number: 12,
+                    title: "Add a feature".to_string()
+                },
+                PrSummary {
+                    number: 9,
+                    title: "Fix a bug".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_open_prs() {
+        let mock = mocked_command(&["pr", "list", "--json", "number,title"], 0, "[]", "");
+        let mut gh = GhCli::new(mock);
+        assert_eq!(gh.open_prs().unwrap(), vec![]);
+    }
+
     #[test]
     fn repo_name_is_this_repo() {
         // Output of `gh repo view --json owner,name` on this repo
@@ -639,8 +2365,8 @@ mod tests {
             patch = "0.6.0"
         "#;
         assert_eq!(
-            file_contents(&change).await.unwrap(),
-            textwrap::dedent(expected).trim_start()
+            file_contents(&change, None, None, 0, true).await.unwrap(),
+            textwrap::dedent(expected).trim_start().as_bytes()
         );
         mock.assert();
     }
@@ -656,6 +2382,7 @@ mod tests {
         });
         let change = Change {
             contents_url: server.url("/main/contents"),
+            sha: Some("cb71da67691cdf5f595b4e64d4feaf0bdd7798f6".to_string()),
             ..Default::default()
         };
         let expected = r#"
@@ -667,9 +2394,34 @@ mod tests {
             }
         "#;
         assert_eq!(
-            file_contents(&change).await.unwrap(),
-            textwrap::dedent(expected).trim_start()
+            file_contents(&change, None, None, 0, true).await.unwrap(),
+            textwrap::dedent(expected).trim_start().as_bytes()
         );
         mock.assert();
     }
+
+    #[parameterized(
+    empty = { b"", "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391" },
+    hello = { b"hello\n", "ce013625030ba8dba906f756967f9e9ca394464a" },
+    )]
+    fn blob_sha_of_contents(contents: &[u8], expected: &str) {
+        assert_eq!(blob_sha(contents), expected);
+    }
+
+    #[parameterized(
+    matching = { "ce013625030ba8dba906f756967f9e9ca394464a", ChangeKind::File, true },
+    no_sha = { "", ChangeKind::File, true },
+    mismatched = { "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391", ChangeKind::File, false },
+    submodule = { "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391", ChangeKind::Submodule { base: None, new: None }, true },
+    )]
+    fn contents_verified_when_strict(sha: &str, kind: ChangeKind, ok: bool) {
+        let change = Change {
+            filename: "hello.txt".to_string(),
+            sha: (!sha.is_empty()).then(|| sha.to_string()),
+            kind,
+            ..Default::default()
+        };
+        assert_eq!(verify_contents(&change, b"hello\n", true).is_ok(), ok);
+        assert!(verify_contents(&change, b"hello\n", false).is_ok());
+    }
 }
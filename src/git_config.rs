@@ -7,26 +7,76 @@ use anyhow::Result;
 use gix_config::File;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
 use tokio::process::Command;
 
 // Looking at the Git source code the main entry point is
 // https://github.com/git/git/blob/master/git-mergetool--lib.sh
 // This will call into the various files in https://github.com/git/git/tree/master/mergetools
 // to build up the command and arguments.
-// We're going to *start* with just a few tool options
-static DIFFTOOLS: Lazy<HashMap<&str, Vec<&str>>> = Lazy::new(|| {
+static DIFFTOOLS: Lazy<HashMap<&str, KnownTool>> = Lazy::new(|| {
+    const LOCAL_REMOTE: &[&str] = &["$LOCAL", "$REMOTE"];
     let mut m = HashMap::new();
-    m.insert("bc", vec!["bcomp", "bcompare"]);
-    m.insert("bc3", vec!["bcomp", "bcompare"]);
-    m.insert("bc4", vec!["bcomp", "bcompare"]);
-    m.insert("meld", vec!["meld"]);
-    m.insert("vimdiff", vec!["vimdiff"]);
-    m.insert("gvimdiff", vec!["gvimdiff"]);
+    m.insert(
+        "araxis",
+        KnownTool::new(&["compare"], &["-wait", "-2", "$LOCAL", "$REMOTE"]),
+    );
+    m.insert("bc", KnownTool::new(&["bcomp", "bcompare"], LOCAL_REMOTE));
+    m.insert("bc3", KnownTool::new(&["bcomp", "bcompare"], LOCAL_REMOTE));
+    m.insert("bc4", KnownTool::new(&["bcomp", "bcompare"], LOCAL_REMOTE));
+    m.insert(
+        "diffmerge",
+        KnownTool::new(&["diffmerge", "sgdm"], LOCAL_REMOTE),
+    );
+    m.insert("gvimdiff", KnownTool::new(&["gvimdiff"], LOCAL_REMOTE));
+    m.insert(
+        "kdiff3",
+        KnownTool::new(
+            &["kdiff3"],
+            &[
+                "--L1",
+                "$MERGED (A)",
+                "--L2",
+                "$MERGED (B)",
+                "$LOCAL",
+                "$REMOTE",
+            ],
+        ),
+    );
+    m.insert("kompare", KnownTool::new(&["kompare"], LOCAL_REMOTE));
+    m.insert("meld", KnownTool::new(&["meld"], LOCAL_REMOTE));
+    m.insert(
+        "nvimdiff",
+        KnownTool::new(&["nvim"], &["-R", "-f", "-d", "$LOCAL", "$REMOTE"]),
+    );
+    m.insert("opendiff", KnownTool::new(&["opendiff"], LOCAL_REMOTE));
+    m.insert("p4merge", KnownTool::new(&["p4merge"], LOCAL_REMOTE));
+    m.insert("tkdiff", KnownTool::new(&["tkdiff"], LOCAL_REMOTE));
+    m.insert("vimdiff", KnownTool::new(&["vimdiff"], LOCAL_REMOTE));
+    m.insert(
+        "vscode",
+        KnownTool::new(&["code"], &["--wait", "--diff", "$LOCAL", "$REMOTE"]),
+    );
     m
 });
 
+/// A difftool `gh-difftool` knows how to launch without any git configuration
+struct KnownTool {
+    /// The programs that may provide the tool, the first one found is used
+    programs: &'static [&'static str],
+    /// The arguments to the program, following git's `diff_cmd` for the tool
+    args: &'static [&'static str],
+}
+
+impl KnownTool {
+    const fn new(programs: &'static [&'static str], args: &'static [&'static str]) -> Self {
+        Self { programs, args }
+    }
+}
+
 #[derive(Debug, displaydoc::Display, Eq, PartialEq)]
 pub enum Error {
     /// "{0}" is not a git repository
@@ -35,6 +85,12 @@ pub enum Error {
     NoDifftoolConfigured,
     /// Unknown difftool {0}
     UnknownDifftool(String),
+    /// Difftool {0} failed with {1}
+    DifftoolFailed(String, ExitStatus),
+    /// No external diff driver configured with `diff.external`
+    NoExternalDriver,
+    /// Tool command must be a program and its arguments: {0}
+    ToolCommand(String),
 }
 
 impl std::error::Error for Error {}
@@ -44,36 +100,236 @@ impl std::error::Error for Error {}
 pub struct Difftool {
     tool: String,
     command_args: Vec<String>,
+    env: Vec<(String, String)>,
+    /// Passed to the difftool after the configured arguments
+    extra_args: Vec<String>,
+    trust_exit_code: bool,
+    timeout: Option<Duration>,
+    /// Launched like a `diff.external` driver, with git's 7 diff driver arguments
+    external: bool,
+}
+
+/// `arg` with each `$MERGED` in it replaced by `merged`
+fn replace_merged(arg: &str, merged: &OsStr) -> OsString {
+    let mut replaced = OsString::new();
+    for (index, part) in arg.split("$MERGED").enumerate() {
+        if index > 0 {
+            replaced.push(merged);
+        }
+        replaced.push(part);
+    }
+    replaced
+}
+
+/// The arguments git passes to a `diff.external` driver for a file
+///
+/// A side which doesn't exist, like the old version of an added file, has a file of `/dev/null`
+/// and a hex and mode of `.`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DriverArgs {
+    pub path: String,
+    pub old_file: OsString,
+    pub old_hex: String,
+    pub old_mode: String,
+    pub new_file: OsString,
+    pub new_hex: String,
+    pub new_mode: String,
+}
+
+impl DriverArgs {
+    /// The arguments in the order git passes them to the driver
+    fn args(&self) -> [&OsStr; 7] {
+        [
+            OsStr::new(&self.path),
+            &self.old_file,
+            OsStr::new(&self.old_hex),
+            OsStr::new(&self.old_mode),
+            &self.new_file,
+            OsStr::new(&self.new_hex),
+            OsStr::new(&self.new_mode),
+        ]
+    }
 }
 
 impl Difftool {
-    pub fn new(git_dir: impl AsRef<Path>, tool: Option<impl AsRef<str>>) -> Result<Self> {
+    /// Create the difftool `tool`, or the one configured in git when `tool` is `None`
+    ///
+    /// # Arguments
+    /// * `git_dir` - The directory or sub-directory to a git repo
+    /// * `tool` - The name of the difftool to use
+    /// * `gui` - Prefer the configured GUI difftool, `diff.guitool`, when `tool` is `None`
+    pub fn new(
+        git_dir: impl AsRef<Path>,
+        tool: Option<impl AsRef<str>>,
+        gui: bool,
+    ) -> Result<Self> {
         let tool = match tool {
             Some(tool) => tool.as_ref().to_string(),
-            None => get_config_difftool(&git_dir)?,
+            None => get_config_difftool(&git_dir, gui)?,
         };
 
         let command_args = get_command_args(&git_dir, &tool)?;
+        let trust_exit_code = get_trust_exit_code(&git_dir, &tool)?;
+
+        Ok(Self {
+            tool,
+            command_args,
+            trust_exit_code,
+            ..Default::default()
+        })
+    }
+
+    /// Create a difftool from a `command` line, like a `difftool.<tool>.cmd`, without looking at
+    /// the git config
+    ///
+    /// # Errors
+    /// When `command` is empty or its quotes aren't balanced
+    pub fn from_command(command: impl AsRef<str>) -> Result<Self> {
+        let command = command.as_ref();
+        let command_args = shlex::split(command)
+            .filter(|args| !args.is_empty())
+            .ok_or_else(|| Error::ToolCommand(command.to_string()))?;
+        Ok(Self {
+            tool: command.to_string(),
+            command_args,
+            ..Default::default()
+        })
+    }
+
+    /// Use the external diff driver configured with `diff.external` instead of a difftool
+    ///
+    /// # Arguments
+    /// * `git_dir` - The directory or sub-directory to a git repo
+    ///
+    /// # Errors
+    /// When `diff.external` isn't set
+    pub fn external(git_dir: impl AsRef<Path>) -> Result<Self> {
+        let config = git_config(&git_dir)?;
+        let driver = config
+            .string_by("diff", None, "external")
+            .ok_or(Error::NoExternalDriver)?
+            .to_string();
+        let command_args = shlex::split(&driver)
+            .ok_or_else(|| anyhow::anyhow!("Failed to parse diff.external {driver}"))?;
+        Ok(Self {
+            tool: driver,
+            command_args,
+            external: true,
+            ..Default::default()
+        })
+    }
+
+    /// Whether this is a `diff.external` driver, which needs to be launched with
+    /// [`Difftool::launch_external()`]
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
+    /// The name of the difftool
+    pub fn name(&self) -> &str {
+        &self.tool
+    }
 
-        Ok(Self { tool, command_args })
+    /// Extra environment variables to set when launching the difftool
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Extra arguments to pass to the difftool, after `$LOCAL` and `$REMOTE`
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
+    }
+
+    /// Kill the difftool if it is still running after `timeout`
+    ///
+    /// A difftool which is killed is treated the same as one the user closed.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
     }
 
     pub async fn launch(&self, local: impl AsRef<OsStr>, remote: impl AsRef<OsStr>) -> Result<()> {
+        self.run(self.command(local, remote)).await
+    }
+
+    /// The command line [`Difftool::launch`] would run for `local` and `remote`
+    pub fn command_line(&self, local: impl AsRef<OsStr>, remote: impl AsRef<OsStr>) -> String {
+        command_line(&self.command(local, remote))
+    }
+
+    /// The command line [`Difftool::launch_external`] would run for a file
+    pub fn external_command_line(&self, args: &DriverArgs) -> String {
+        command_line(&self.external_command(args))
+    }
+
+    /// Launch the `diff.external` driver for a file
+    pub async fn launch_external(&self, args: &DriverArgs) -> Result<()> {
+        self.run(self.external_command(args)).await
+    }
+
+    /// Run the difftool `command`, waiting for it to finish or time out
+    async fn run(&self, mut command: Command) -> Result<()> {
+        // In order to work with terminal diff tools like vimdiff we need to
+        // spawn the process instead of using Command::output
+        log::debug!("Launching {}", command_line(&command));
+        let mut child = command.spawn()?;
+        let status = match self.timeout {
+            Some(timeout) => {
+                let waited = tokio::time::timeout(timeout, child.wait()).await;
+                match waited {
+                    Ok(status) => status?,
+                    Err(_) => {
+                        child.kill().await?;
+                        return Ok(());
+                    }
+                }
+            }
+            None => child.wait().await?,
+        };
+
+        // Some difftools, like bcompare, will return non zero status when there is a diff and 0
+        // only when there are no changes.  This prevents us from trusting the status, unless
+        // the user has explicitly said to.
+        if self.trust_exit_code && !status.success() {
+            return Err(Error::DifftoolFailed(self.tool.clone(), status).into());
+        }
+        Ok(())
+    }
+
+    /// Build the command to launch the difftool for `local` and `remote`
+    fn command(&self, local: impl AsRef<OsStr>, remote: impl AsRef<OsStr>) -> Command {
         let (program, args) = self
             .command_args
             .split_first()
             .expect("No difftool command args set");
         let mut command = Command::new(program);
 
-        // We set the environment variables in case the preferred difftool uses them directly
-        command.envs([("LOCAL", local.as_ref()), ("REMOTE", remote.as_ref())]);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        // We set the environment variables in case the preferred difftool uses them directly. There
+        // is no merge when diffing, `$MERGED` is the new file, like the file being compared by
+        // `git difftool`, and `$BASE` is the old one, so a command copied from a merge tool
+        // config gets two real files for its three way view.
+        command.envs([
+            ("LOCAL", local.as_ref()),
+            ("REMOTE", remote.as_ref()),
+            ("MERGED", remote.as_ref()),
+            ("BASE", local.as_ref()),
+        ]);
 
         for arg in args {
             // We replace the environment variables with the local and remote
             // paths because Command is not a shell so will not expand them
             let arg = match arg.as_ref() {
-                "$LOCAL" => local.as_ref(),
-                "$REMOTE" => remote.as_ref(),
+                "$LOCAL" | "$BASE" => local.as_ref(),
+                "$REMOTE" | "$MERGED" => remote.as_ref(),
+                // Labels, like kdiff3's `--L1 "$MERGED (A)"`, name the file being compared
+                _ if arg.contains("$MERGED") => {
+                    command.arg(replace_merged(arg, remote.as_ref()));
+                    continue;
+                }
                 _ => {
                     command.arg(arg);
                     continue;
@@ -81,15 +337,21 @@ impl Difftool {
             };
             command.arg(arg);
         }
+        command.args(&self.extra_args);
+        command
+    }
 
-        // In order to work with terminal diff tools like vimdiff we need to
-        // spawn the process instead of using Command::output
-        let mut child = command.spawn()?;
-        let _ = child.wait().await?;
-
-        // Some difftools, like bcompare, will return non zero status when there is a diff and 0
-        // only when there are no changes.  This prevents us from trusting the status
-        Ok(())
+    /// Build the command to launch the `diff.external` driver with the arguments for a file
+    fn external_command(&self, args: &DriverArgs) -> Command {
+        let (program, driver_args) = self
+            .command_args
+            .split_first()
+            .expect("No difftool command args set");
+        let mut command = Command::new(program);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        command.args(driver_args);
+        command.args(args.args());
+        command
     }
 }
 
@@ -106,7 +368,79 @@ fn get_command_args(git_dir: &impl AsRef<Path>, name: impl AsRef<str>) -> Result
         };
     }
     let program = get_difftool_program(git_dir, name)?;
-    Ok(vec![program, "$LOCAL".into(), "$REMOTE".into()])
+    let args = DIFFTOOLS
+        .get(name)
+        .map_or(&["$LOCAL", "$REMOTE"][..], |known| known.args);
+    Ok(std::iter::once(program)
+        .chain(args.iter().map(|arg| arg.to_string()))
+        .collect())
+}
+
+/// A difftool that can be used with `--tool`
+#[derive(Debug, Eq, PartialEq)]
+pub struct Tool {
+    pub name: String,
+    /// Whether the program for the tool was found
+    pub available: bool,
+}
+
+/// The difftools known to `gh-difftool` along with any configured in git, sorted by name
+///
+/// A tool is configured in git with a `difftool.<tool>.cmd` or `difftool.<tool>.path` option.
+///
+/// # Arguments
+/// * `dir` - The directory or sub-directory to a git repo
+pub fn tools(dir: impl AsRef<Path>) -> Result<Vec<Tool>> {
+    let config = git_config(&dir)?;
+    let mut names = DIFFTOOLS
+        .keys()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let configured = config
+        .sections_by_name("difftool")
+        .into_iter()
+        .flatten()
+        .filter(|section| section.value("cmd").is_some() || section.value("path").is_some())
+        .filter_map(|section| section.header().subsection_name())
+        .map(|name| name.to_string());
+    names.extend(configured);
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let command_args = get_command_args(&dir, &name)?;
+            let available = command_args
+                .first()
+                .is_some_and(|program| which::which(program).is_ok());
+            Ok(Tool { name, available })
+        })
+        .collect()
+}
+
+/// Whether to prompt before launching the difftool for each file
+///
+/// Uses the `difftool.prompt` git config option. Defaults to not prompting when unset.
+///
+/// # Arguments
+/// * `dir` - The directory or sub-directory to a git repo
+pub fn prompt(dir: impl AsRef<Path>) -> Result<bool> {
+    let config = git_config(dir)?;
+    let prompt = config.boolean_by("difftool", None, "prompt").transpose()?;
+    Ok(prompt.unwrap_or(false))
+}
+
+/// Whether the exit code of the difftool `name` should be trusted
+///
+/// Similar to git, `difftool.<tool>.trustExitCode` takes precedence over `difftool.trustExitCode`.
+fn get_trust_exit_code(git_dir: &impl AsRef<Path>, name: impl AsRef<str>) -> Result<bool> {
+    let config = git_config(git_dir)?;
+    let trust = config
+        .boolean_by("difftool", Some(name.as_ref().into()), "trustExitCode")
+        .or_else(|| config.boolean_by("difftool", None, "trustExitCode"))
+        .transpose()?;
+    Ok(trust.unwrap_or(false))
 }
 
 fn get_difftool_program(git_dir: impl AsRef<Path>, name: impl AsRef<str>) -> Result<String> {
@@ -121,7 +455,8 @@ fn lookup_known_tool_program(tool: impl AsRef<str>) -> Result<String> {
     let tool = tool.as_ref();
     let programs = DIFFTOOLS
         .get(tool)
-        .ok_or_else(|| Error::UnknownDifftool(tool.to_string()))?;
+        .ok_or_else(|| Error::UnknownDifftool(tool.to_string()))?
+        .programs;
 
     let program = find_first_program(programs).unwrap_or_else(|| String::from(programs[0]));
     Ok(program)
@@ -133,8 +468,20 @@ fn find_first_program(programs: &[&str]) -> Option<String> {
     })
 }
 
-fn get_config_difftool(dir: impl AsRef<Path>) -> Result<String> {
+/// The difftool configured in git
+///
+/// Similar to git, when `gui` is set `diff.guitool` is preferred, falling back to `merge.guitool`
+/// and then the non GUI tools.
+fn get_config_difftool(dir: impl AsRef<Path>, gui: bool) -> Result<String> {
     let config = git_config(dir)?;
+    if gui {
+        let guitool = config
+            .string_by("diff", None, "guitool")
+            .or_else(|| config.string_by("merge", None, "guitool"));
+        if let Some(tool) = guitool {
+            return Ok(tool.to_string());
+        }
+    }
     match config.string_by("diff", None, "tool") {
         Some(tool) => Ok(tool.to_string()),
         // Note: due to the global git config being found and the users diff setting being taken
@@ -149,14 +496,25 @@ fn get_config_difftool(dir: impl AsRef<Path>) -> Result<String> {
     }
 }
 
-/// Find the git directory, `.git`, for the provided directory
+/// Find the git directory for the provided directory
 ///
-/// This will walk up from the provided `dir` looking for the `.git` directory.
-/// This does *not* properly handle `.git` files for worktrees and submodules
+/// This will walk up from the provided `dir` looking for `.git`. In linked worktrees and
+/// submodules `.git` is a file with a `gitdir: <path>` line pointing to the git directory, which
+/// is followed.
 ///
 /// # Returns:
-/// The full path to the `.git` directory if found. None if not found.
+/// The full path to the git directory if found. None if not found.
 fn find_git_dir(dir: impl AsRef<Path>) -> Option<PathBuf> {
+    let dot_git = find_dot_git(dir)?;
+    if dot_git.is_file() {
+        read_gitdir_file(&dot_git)
+    } else {
+        Some(dot_git)
+    }
+}
+
+/// Find the `.git` directory, or file, walking up from `dir`
+fn find_dot_git(dir: impl AsRef<Path>) -> Option<PathBuf> {
     let dir = dir.as_ref();
     for path in dir.ancestors() {
         let git = path.join(".git");
@@ -167,20 +525,111 @@ fn find_git_dir(dir: impl AsRef<Path>) -> Option<PathBuf> {
     None
 }
 
-/// Get the git config for the repo at `dir`
+/// The git directory the `.git` `file` points to
+///
+/// A relative path is relative to the directory containing `file`.
+fn read_gitdir_file(file: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(file).ok()?;
+    let gitdir = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))?
+        .trim();
+    Some(file.parent()?.join(gitdir))
+}
+
+/// The git directory with the config of the repo for `git_dir`
+///
+/// The git directory of a linked worktree has a `commondir` file with the path, relative to it,
+/// to the git directory of the main worktree. The config is shared from there.
+fn common_dir(git_dir: PathBuf) -> PathBuf {
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir,
+    }
+}
+
+/// Find the root of the working tree for the repo at `dir`
 ///
 /// # Arguments
 /// * `dir` - The directory or sub-directory to a git repo
 ///
+/// # Error
+/// If `dir` is not for a git repository
+pub fn repo_root(dir: impl AsRef<Path>) -> Result<PathBuf> {
+    // The working tree is where `.git` is, a `.git` file may point to a git directory elsewhere
+    let dot_git =
+        find_dot_git(&dir).ok_or_else(|| Error::NotAGitRepository(PathBuf::from(dir.as_ref())))?;
+    Ok(dot_git
+        .parent()
+        .expect("The `.git` directory should always have a parent")
+        .to_path_buf())
+}
+
+/// The program and arguments of `command` as a line for a shell, quoting where needed
+///
+/// The environment of `command` isn't included.
+pub fn command_line(command: &Command) -> String {
+    let command = command.as_std();
+    shell_words(std::iter::once(command.get_program()).chain(command.get_args()))
+}
+
+/// The `words` as a line for a shell, quoting where needed
+pub fn shell_words<'a>(words: impl IntoIterator<Item = &'a OsStr>) -> String {
+    words
+        .into_iter()
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            // Only an argument with a nul can't be quoted, it couldn't be passed to a program either
+            shlex::try_quote(&arg).map_or_else(|_| arg.to_string(), |quoted| quoted.to_string())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The name of the ignore file, in the root of the working tree
+const IGNORE_FILE: &str = ".gh-difftoolignore";
+
+/// The patterns of the `.gh-difftoolignore` file for the repo at `dir`
+///
+/// The file lists the paths, or glob patterns, to leave out of a review, one per line. Empty
+/// lines and lines starting with `#` are skipped.
+///
 /// # Returns
-/// The config `File` for the repo at `dir`.
+/// The patterns, empty when `dir` isn't in a git repository or the repository has no ignore file.
 ///
 /// # Error
-/// If `dir` is not for a git repository
+/// If the ignore file exists but can't be read
+pub fn ignore_patterns(dir: impl AsRef<Path>) -> Result<Vec<String>> {
+    let Ok(root) = repo_root(dir) else {
+        return Ok(vec![]);
+    };
+    let path = root.join(IGNORE_FILE);
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Get the git config for the repo at `dir`
+///
+/// # Arguments
+/// * `dir` - The directory or sub-directory to a git repo
+///
+/// # Returns
+/// The config `File` for the repo at `dir`. Outside of a git repo this is only the global and
+/// system config, so a difftool configured for the user still works.
 pub fn git_config(dir: impl AsRef<Path>) -> Result<File<'static>> {
-    let git_dir =
-        find_git_dir(&dir).ok_or_else(|| Error::NotAGitRepository(PathBuf::from(dir.as_ref())))?;
-    Ok(File::from_git_dir(git_dir)?)
+    match find_git_dir(&dir) {
+        Some(git_dir) => Ok(File::from_git_dir(common_dir(git_dir))?),
+        None => Ok(File::from_globals()?),
+    }
 }
 
 #[cfg(test)]
@@ -215,6 +664,87 @@ mod tests {
         assert_eq!(find_git_dir(nested_dir), Some(expected));
     }
 
+    #[test]
+    fn git_dir_from_submodule_git_file() {
+        let temp = TempDir::default();
+        let submodule = temp.join("sub");
+        let git_dir = temp.join(".git").join("modules").join("sub");
+        fs::create_dir_all(&submodule).unwrap();
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(submodule.join(".git"), "gitdir: ../.git/modules/sub\n").unwrap();
+
+        assert_eq!(
+            find_git_dir(submodule.join("src")),
+            Some(submodule.join("../.git/modules/sub"))
+        );
+        assert_eq!(repo_root(&submodule).unwrap(), submodule);
+    }
+
+    /// A linked worktree, like `git worktree add`, of a repo in `dir` with `config`
+    ///
+    /// # Returns
+    /// The root of the linked worktree
+    fn linked_worktree(dir: &Path, config: &str) -> PathBuf {
+        let main_git_dir = dir.join("main").join(".git");
+        let worktree_git_dir = main_git_dir.join("worktrees").join("feature");
+        let worktree = dir.join("feature");
+        fs::create_dir_all(&worktree_git_dir).unwrap();
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(main_git_dir.join("config"), config).unwrap();
+        fs::write(worktree_git_dir.join("commondir"), "../..\n").unwrap();
+        fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", worktree_git_dir.display()),
+        )
+        .unwrap();
+        worktree
+    }
+
+    #[test]
+    fn config_of_linked_worktree() {
+        let temp = TempDir::default();
+        let worktree = linked_worktree(&temp, "[user]\n    name = Me\n");
+        let config = git_config(&worktree).unwrap();
+
+        assert_eq!(
+            config.string_by("user", None, "name").unwrap().to_string(),
+            "Me".to_string()
+        );
+    }
+
+    #[test]
+    fn repo_root_from_nested_dir() {
+        let root_dir = current_dir().unwrap();
+        let nested_dir = root_dir.join("src");
+
+        assert_eq!(repo_root(nested_dir).unwrap(), root_dir);
+    }
+
+    #[test]
+    fn ignore_patterns_from_repo_root() {
+        let temp = TempDir::default().permanent();
+        fs::create_dir_all(temp.join(".git")).unwrap();
+        fs::create_dir_all(temp.join("src")).unwrap();
+        fs::write(
+            temp.join(".gh-difftoolignore"),
+            "# Generated files\nCargo.lock\n\n  **/*.snap  \n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            ignore_patterns(temp.join("src")).unwrap(),
+            ["Cargo.lock", "**/*.snap"]
+        );
+    }
+
+    #[test]
+    fn no_ignore_file() {
+        let temp = TempDir::default().permanent();
+        fs::create_dir_all(temp.join(".git")).unwrap();
+
+        assert!(ignore_patterns(&temp).unwrap().is_empty());
+    }
+
     #[test]
     fn getting_git_config() {
         let temp = TempDir::default().permanent();
@@ -230,6 +760,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn global_config_outside_of_git_repo() {
+        let temp = TempDir::default().permanent();
+        assert_eq!(find_git_dir(&temp), None);
+
+        assert!(git_config(&temp).is_ok());
+        assert!(get_trust_exit_code(&temp, "meld").is_ok());
+        assert!(prompt(&temp).is_ok());
+    }
+
     #[test]
     fn found_difftool_in_config() {
         let temp = TempDir::default().permanent();
@@ -238,7 +778,43 @@ mod tests {
         fs::create_dir_all(&git_dir).unwrap();
         fs::write(&config_file, "[diff]\n    tool = meld\n").unwrap();
 
-        assert_eq!(get_config_difftool(&temp).unwrap(), "meld".to_string());
+        assert_eq!(
+            get_config_difftool(&temp, false).unwrap(),
+            "meld".to_string()
+        );
+    }
+
+    #[parameterized(
+    gui = { true, "meld" },
+    not_gui = { false, "vimdiff" },
+    )]
+    fn guitool_in_config_only_for_gui(gui: bool, expected: &str) {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[diff]\n    guitool = meld\n    tool = vimdiff\n",
+        )
+        .unwrap();
+
+        assert_eq!(get_config_difftool(&temp, gui).unwrap(), expected);
+    }
+
+    #[test]
+    fn merge_guitool_used_for_gui() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[diff]\n    tool = vimdiff\n[merge]\n    guitool = kdiff3\n",
+        )
+        .unwrap();
+
+        assert_eq!(get_config_difftool(&temp, true).unwrap(), "kdiff3");
     }
 
     #[test]
@@ -276,6 +852,15 @@ mod tests {
     #[parameterized(
     meld = { "meld", "meld" },
     gvimdiff = { "gvimdiff", "gvimdiff" },
+    araxis = { "araxis", "compare" },
+    diffmerge = { "diffmerge", "diffmerge" },
+    kdiff3 = { "kdiff3", "kdiff3" },
+    kompare = { "kompare", "kompare" },
+    nvimdiff = { "nvimdiff", "nvim" },
+    opendiff = { "opendiff", "opendiff" },
+    p4merge = { "p4merge", "p4merge" },
+    tkdiff = { "tkdiff", "tkdiff" },
+    vscode = { "vscode", "code" },
     )]
     fn lookup_known_tool(tool: &str, program: &str) {
         assert_eq!(
@@ -326,7 +911,7 @@ mod tests {
         fs::write(&config_file, &contents).unwrap();
 
         assert_eq!(
-            Difftool::new(&temp, Some(tool)).unwrap(),
+            Difftool::new(&temp, Some(tool), false).unwrap(),
             Difftool {
                 tool: tool.to_string(),
                 command_args: vec![
@@ -334,10 +919,313 @@ mod tests {
                     "$LOCAL".to_string(),
                     "$REMOTE".to_string()
                 ],
+                ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn extra_env_set_on_command() {
+        let difftool = Difftool {
+            tool: "meld".to_string(),
+            command_args: vec!["meld".into(), "$LOCAL".into(), "$REMOTE".into()],
+            ..Default::default()
+        }
+        .with_env(vec![
+            ("THEME".to_string(), "dark".to_string()),
+            ("CONFIG".to_string(), "/some/config".to_string()),
+        ]);
+
+        let command = difftool.command("local_file", "remote_file");
+        let envs = command.as_std().get_envs().collect::<HashMap<_, _>>();
+
+        assert_eq!(envs[OsStr::new("THEME")], Some(OsStr::new("dark")));
+        assert_eq!(envs[OsStr::new("CONFIG")], Some(OsStr::new("/some/config")));
+        assert_eq!(envs[OsStr::new("LOCAL")], Some(OsStr::new("local_file")));
+        assert_eq!(envs[OsStr::new("REMOTE")], Some(OsStr::new("remote_file")));
+    }
+
+    #[test]
+    fn command_line_with_paths_substituted() {
+        let difftool = Difftool {
+            tool: "makebelieve".to_string(),
+            command_args: vec![
+                "some/cmd".into(),
+                "--left".into(),
+                "$LOCAL".into(),
+                "$REMOTE".into(),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            difftool.command_line("/tmp/local/a file.rs", "/tmp/remote/a file.rs"),
+            "some/cmd --left '/tmp/local/a file.rs' '/tmp/remote/a file.rs'"
+        );
+    }
+
+    #[test]
+    fn merged_and_base_substituted() {
+        let difftool = Difftool {
+            tool: "makebelieve".to_string(),
+            command_args: vec![
+                "some/cmd".into(),
+                "--base".into(),
+                "$BASE".into(),
+                "--output".into(),
+                "$MERGED".into(),
+                "$LOCAL".into(),
+                "$REMOTE".into(),
+            ],
+            ..Default::default()
+        };
+
+        let command = difftool.command("local_file", "remote_file");
+        let args = command.as_std().get_args().collect::<Vec<_>>();
+
+        assert_eq!(
+            args,
+            [
+                "--base",
+                "local_file",
+                "--output",
+                "remote_file",
+                "local_file",
+                "remote_file"
+            ]
+        );
+    }
+
+    #[test]
+    fn merged_substituted_in_label() {
+        let difftool = Difftool {
+            tool: "kdiff3".to_string(),
+            command_args: vec![
+                "kdiff3".into(),
+                "--L1".into(),
+                "$MERGED (A)".into(),
+                "$LOCAL".into(),
+                "$REMOTE".into(),
+            ],
+            ..Default::default()
+        };
+
+        let command = difftool.command("local_file", "remote_file");
+        let args = command.as_std().get_args().collect::<Vec<_>>();
+
+        assert_eq!(
+            args,
+            ["--L1", "remote_file (A)", "local_file", "remote_file"]
+        );
+    }
+
+    #[test]
+    fn tool_from_command() {
+        let difftool = Difftool::from_command("some/cmd --title 'a b' $LOCAL $REMOTE").unwrap();
+        assert_eq!(
+            difftool.command_line("local_file", "remote_file"),
+            "some/cmd --title 'a b' local_file remote_file"
+        );
+    }
+
+    #[parameterized(
+    empty = { "" },
+    unbalanced_quote = { "some/cmd 'a b $LOCAL $REMOTE" },
+    )]
+    fn tool_command_errors(command: &str) {
+        assert_eq!(
+            Difftool::from_command(command)
+                .unwrap_err()
+                .downcast_ref::<Error>(),
+            Some(&Error::ToolCommand(command.to_string()))
+        );
+    }
+
+    #[test]
+    fn extra_args_after_files() {
+        let difftool = Difftool {
+            tool: "meld".to_string(),
+            command_args: vec!["meld".into(), "$LOCAL".into(), "$REMOTE".into()],
+            ..Default::default()
+        }
+        .with_extra_args(vec!["--newtab".to_string(), "--label=pr".to_string()]);
+
+        let command = difftool.command("local_file", "remote_file");
+        let args = command.as_std().get_args().collect::<Vec<_>>();
+
+        assert_eq!(
+            args,
+            ["local_file", "remote_file", "--newtab", "--label=pr"]
+        );
+    }
+
+    #[test]
+    fn external_driver_gets_seven_args() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[diff]\n    external = difft --color always\n",
+        )
+        .unwrap();
+        let difftool = Difftool::external(&temp).unwrap();
+        let args = DriverArgs {
+            path: "src/main.rs".to_string(),
+            old_file: "local/src/main.rs".into(),
+            old_hex: "0000000000000000000000000000000000000000".to_string(),
+            old_mode: "100644".to_string(),
+            new_file: "remote/src/main.rs".into(),
+            new_hex: "abc123".to_string(),
+            new_mode: "100644".to_string(),
+        };
+
+        let command = difftool.external_command(&args);
+        let command = command.as_std();
+
+        assert!(difftool.is_external());
+        assert_eq!(command.get_program(), "difft");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--color",
+                "always",
+                "src/main.rs",
+                "local/src/main.rs",
+                "0000000000000000000000000000000000000000",
+                "100644",
+                "remote/src/main.rs",
+                "abc123",
+                "100644",
+            ]
+        );
+    }
+
+    #[test]
+    fn no_external_driver() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("config"), "[diff]\n    tool = meld\n").unwrap();
+        let error = Difftool::external(&temp).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Error>(),
+            Some(&Error::NoExternalDriver)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn difftool_killed_after_timeout() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[difftool]\n    trustExitCode = true\n[difftool.x]\n    cmd = sleep 30",
+        )
+        .unwrap();
+        let difftool = Difftool::new(&temp, Some("x"), false)
+            .unwrap()
+            .with_timeout(Some(Duration::from_millis(100)));
+
+        let start = std::time::Instant::now();
+        difftool.launch("local_file", "remote_file").await.unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn tools_include_known_and_configured() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[difftool.mine]\n    cmd = sh -c true\n[difftool.gone]\n    path = /no/such/program\n[difftool.settings]\n    trustExitCode = true",
+        )
+        .unwrap();
+
+        let tools = tools(&temp).unwrap();
+        let names = tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+
+        assert!(names.contains(&"meld"));
+        assert!(names.contains(&"vimdiff"));
+        assert!(!names.contains(&"settings"));
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+        let available = |name| tools.iter().find(|t| t.name == name).unwrap().available;
+        assert!(!available("gone"));
+        #[cfg(unix)]
+        assert!(available("mine"));
+    }
+
+    #[test]
+    fn trust_exit_code_from_config() {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            "[difftool.x]\n    path = some/path\n    trustExitCode = true",
+        )
+        .unwrap();
+
+        let difftool = Difftool::new(&temp, Some("x"), false).unwrap();
+        assert!(difftool.trust_exit_code);
+    }
+
+    #[parameterized(
+    tool_true = { "[difftool.x]\n    trustExitCode = true", true },
+    tool_false = { "[difftool.x]\n    trustExitCode = false", false },
+    global_true = { "[difftool]\n    trustExitCode = true", true },
+    tool_overrides_global = { "[difftool]\n    trustExitCode = true\n[difftool.x]\n    trustExitCode = false", false },
+    unset = { "[difftool.x]\n    path = some/path", false },
+    )]
+    fn trust_exit_code(contents: &str, expected: bool) {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(&config_file, contents).unwrap();
+
+        assert_eq!(get_trust_exit_code(&temp, "x").unwrap(), expected);
+    }
+
+    #[parameterized(
+    prompt_true = { "[difftool]\n    prompt = true", true },
+    prompt_false = { "[difftool]\n    prompt = false", false },
+    unset = { "[difftool.x]\n    path = some/path", false },
+    )]
+    fn prompt_from_config(contents: &str, expected: bool) {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(&config_file, contents).unwrap();
+
+        assert_eq!(prompt(&temp).unwrap(), expected);
+    }
+
+    #[test]
+    fn difftool_from_main_repo_in_linked_worktree() {
+        let temp = TempDir::default();
+        let worktree = linked_worktree(
+            &temp,
+            "[diff]\n    tool = makebelieve\n[difftool.makebelieve]\n    cmd = some/cmd $LOCAL $REMOTE\n",
+        );
+
+        let tool = get_config_difftool(&worktree, false).unwrap();
+        assert_eq!(tool, "makebelieve");
+        assert_eq!(
+            get_command_args(&worktree, &tool).unwrap(),
+            ["some/cmd", "$LOCAL", "$REMOTE"]
+        );
+    }
+
     #[test]
     fn difftool_cmd_from_config() {
         let temp = TempDir::default().permanent();
@@ -361,4 +1249,29 @@ mod tests {
             ]
         );
     }
+
+    #[parameterized(
+    meld = { "meld", &["$LOCAL", "$REMOTE"] },
+    bc = { "bc", &["$LOCAL", "$REMOTE"] },
+    araxis = { "araxis", &["-wait", "-2", "$LOCAL", "$REMOTE"] },
+    nvimdiff = { "nvimdiff", &["-R", "-f", "-d", "$LOCAL", "$REMOTE"] },
+    kdiff3 = { "kdiff3", &["--L1", "$MERGED (A)", "--L2", "$MERGED (B)", "$LOCAL", "$REMOTE"] },
+    vscode = { "vscode", &["--wait", "--diff", "$LOCAL", "$REMOTE"] },
+    unknown = { "makebelieve", &["$LOCAL", "$REMOTE"] },
+    )]
+    fn known_tool_args_follow_path(tool: &str, args: &[&str]) {
+        let temp = TempDir::default().permanent();
+        let git_dir = temp.join(".git");
+        let config_file = git_dir.join("config");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            &config_file,
+            format!("[difftool.{tool}]\n    path = some/path"),
+        )
+        .unwrap();
+
+        let mut expected = vec!["some/path".to_string()];
+        expected.extend(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(get_command_args(&temp, tool).unwrap(), expected);
+    }
 }
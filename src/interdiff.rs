@@ -0,0 +1,250 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! The difference between two revisions of a pull request, an interdiff
+//!
+//! When a pull request is force pushed, only the files whose changes differ between the previous
+//! head and the current head need to be reviewed again.
+
+use crate::change_set::{Change, ChangeSet};
+
+/// The changes to diff between the previous and current revisions of a pull request
+///
+/// The resultant changes describe going from the file at the previous head to the file at the
+/// current head:
+/// - "added" when the file doesn't exist at the previous head
+/// - "removed" when the file doesn't exist at the current head
+/// - "renamed" when the file is at a different path in each revision
+/// - "modified" otherwise
+///
+/// Files with the same changes in both revisions are left out. The changes are in the order of
+/// `new`, followed by the files that are only in `old`.
+///
+/// # Arguments
+/// * `old` - The changes of the pull request at the previous head
+/// * `new` - The changes of the pull request at the current head
+pub fn interdiff(old: ChangeSet, new: ChangeSet) -> ChangeSet {
+    let mut old = old.changes;
+    let mut changes = vec![];
+    for new_change in new.changes {
+        let old_change = old
+            .iter()
+            .position(|c| c.filename == new_change.filename)
+            .or_else(|| old.iter().position(|c| is_renamed_between(c, &new_change)))
+            .map(|index| old.remove(index));
+        if let Some(change) = between(old_change, Some(new_change)) {
+            changes.push(change);
+        }
+    }
+    changes.extend(
+        old.into_iter()
+            .filter_map(|old_change| between(Some(old_change), None)),
+    );
+    ChangeSet { changes }
+}
+
+/// Whether one revision renamed the file that the other revision changed in place
+fn is_renamed_between(old: &Change, new: &Change) -> bool {
+    new.previous_filename.as_ref() == Some(&old.filename)
+        || old.previous_filename.as_ref() == Some(&new.filename)
+}
+
+/// The change from the file in `old` to the file in `new`, if the file differs
+///
+/// A missing `old` or `new` means the pull request didn't change the file in that revision, so
+/// the file is the same as in the base branch.
+fn between(old: Option<Change>, new: Option<Change>) -> Option<Change> {
+    if let (Some(old), Some(new)) = (&old, &new) {
+        if same_change(old, new) {
+            return None;
+        }
+    }
+
+    let (original_path, original_exists) = match (&old, &new) {
+        (Some(old), _) => (old.filename.clone(), old.status != "removed"),
+        (None, Some(new)) => (
+            new.previous_filename
+                .clone()
+                .unwrap_or_else(|| new.filename.clone()),
+            new.status != "added",
+        ),
+        (None, None) => return None,
+    };
+    let (new_path, new_exists) = match (&new, &old) {
+        (Some(new), _) => (new.filename.clone(), new.status != "removed"),
+        (None, Some(old)) => (
+            old.previous_filename
+                .clone()
+                .unwrap_or_else(|| old.filename.clone()),
+            old.status != "added",
+        ),
+        (None, None) => return None,
+    };
+
+    let status = match (original_exists, new_exists) {
+        (false, false) => return None,
+        (false, true) => "added",
+        (true, false) => "removed",
+        (true, true) if original_path != new_path => "renamed",
+        (true, true) => "modified",
+    };
+    let contents_url = new
+        .as_ref()
+        .or(old.as_ref())
+        .map(|c| c.contents_url.clone())
+        .unwrap_or_default();
+    let (filename, previous_filename) = match status {
+        "removed" => (original_path, None),
+        "renamed" => (new_path, Some(original_path)),
+        _ => (new_path, None),
+    };
+    Some(Change {
+        filename,
+        previous_filename,
+        contents_url,
+        status: status.to_string(),
+        ..Default::default()
+    })
+}
+
+/// Whether `old` and `new` make the same change to the file
+fn same_change(old: &Change, new: &Change) -> bool {
+    old.status == new.status
+        && old.previous_filename == new.previous_filename
+        && match (&old.sha, &new.sha) {
+            (Some(old_sha), Some(new_sha)) => old_sha == new_sha,
+            _ => old.patch == new.patch,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(filename: &str, status: &str, sha: &str) -> Change {
+        Change {
+            filename: filename.to_string(),
+            contents_url: format!("https://api.github.com/repos/me/repo/contents/{filename}"),
+            status: status.to_string(),
+            sha: Some(sha.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn selected(changes: &ChangeSet) -> Vec<(&str, Option<&str>, &str)> {
+        changes
+            .changes
+            .iter()
+            .map(|c| {
+                (
+                    c.filename.as_str(),
+                    c.previous_filename.as_deref(),
+                    c.status.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_files_are_skipped() {
+        let old = ChangeSet {
+            changes: vec![
+                change("same.rs", "modified", "1"),
+                change("a.rs", "modified", "2"),
+            ],
+        };
+        let new = ChangeSet {
+            changes: vec![
+                change("same.rs", "modified", "1"),
+                change("a.rs", "modified", "3"),
+            ],
+        };
+        assert_eq!(selected(&interdiff(old, new)), [("a.rs", None, "modified")]);
+    }
+
+    #[test]
+    fn file_only_in_new_revision() {
+        let old = ChangeSet { changes: vec![] };
+        let new = ChangeSet {
+            changes: vec![
+                change("new.rs", "added", "1"),
+                change("existing.rs", "modified", "2"),
+            ],
+        };
+        assert_eq!(
+            selected(&interdiff(old, new)),
+            [("new.rs", None, "added"), ("existing.rs", None, "modified")]
+        );
+    }
+
+    #[test]
+    fn file_only_in_old_revision() {
+        let old = ChangeSet {
+            changes: vec![
+                change("dropped.rs", "added", "1"),
+                change("reverted.rs", "modified", "2"),
+                change("restored.rs", "removed", "3"),
+            ],
+        };
+        let new = ChangeSet { changes: vec![] };
+        assert_eq!(
+            selected(&interdiff(old, new)),
+            [
+                ("dropped.rs", None, "removed"),
+                ("reverted.rs", None, "modified"),
+                ("restored.rs", None, "added"),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_revision_renames_file() {
+        let old = ChangeSet {
+            changes: vec![change("old.rs", "modified", "1")],
+        };
+        let mut renamed = change("new.rs", "renamed", "2");
+        renamed.previous_filename = Some("old.rs".to_string());
+        let new = ChangeSet {
+            changes: vec![renamed],
+        };
+        assert_eq!(
+            selected(&interdiff(old, new)),
+            [("new.rs", Some("old.rs"), "renamed")]
+        );
+    }
+
+    #[test]
+    fn new_revision_undoes_rename() {
+        let mut renamed = change("new.rs", "renamed", "1");
+        renamed.previous_filename = Some("old.rs".to_string());
+        let old = ChangeSet {
+            changes: vec![renamed],
+        };
+        let new = ChangeSet {
+            changes: vec![change("old.rs", "modified", "2")],
+        };
+        assert_eq!(
+            selected(&interdiff(old, new)),
+            [("old.rs", Some("new.rs"), "renamed")]
+        );
+    }
+
+    #[test]
+    fn patches_compared_without_sha() {
+        let mut old_change = change("a.rs", "modified", "");
+        old_change.sha = None;
+        old_change.patch = Some("@@ -1 +1 @@\n-a\n+b".to_string());
+        let old = ChangeSet {
+            changes: vec![old_change],
+        };
+        let mut new_change = change("a.rs", "modified", "");
+        new_change.sha = None;
+        new_change.patch = Some("@@ -1 +1 @@\n-a\n+c".to_string());
+        let new = ChangeSet {
+            changes: vec![new_change],
+        };
+        assert_eq!(selected(&interdiff(old, new)), [("a.rs", None, "modified")]);
+    }
+}
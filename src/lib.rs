@@ -0,0 +1,38 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Fetching the changes of a GitHub pull request and launching a difftool on them
+//!
+//! The `gh-difftool` binary is a command line on top of this. Other tools can use
+//! [`gh_interface::GhCli::change_set`] to get the [`ChangeSet`] of a [`PullRequest`], then
+//! [`gh_interface::file_contents`] to download the new version of each [`Change`], or a
+//! [`diff::Diff`] to get both versions of each file ready for a [`git_config::Difftool`].
+
+pub mod change_set;
+pub mod cmd;
+pub mod diff;
+pub mod gh_interface;
+pub mod git_config;
+
+// Only public for the `gh-difftool` binary, these aren't part of the library's API
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod csv;
+#[doc(hidden)]
+pub mod failures;
+#[doc(hidden)]
+pub mod interdiff;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod since;
+#[doc(hidden)]
+pub mod stat;
+#[doc(hidden)]
+pub mod suggestions;
+
+pub use change_set::{Change, ChangeSet};
+pub use gh_interface::PullRequest;
@@ -3,21 +3,24 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
-mod change_set;
-mod cmd;
-mod diff;
-mod gh_interface;
-mod git_config;
-
-use crate::change_set::{Change, ChangeSet};
-use crate::diff::{Diff, Difftool};
-use crate::gh_interface::PullRequest;
-use anyhow::Result;
-use clap::{ArgAction, Parser};
-use futures::stream::FuturesOrdered;
+use anyhow::{Context, Result};
+use clap::{ArgAction, CommandFactory, Parser};
+use clap_complete::Shell;
 use futures::StreamExt;
-use std::collections::VecDeque;
+use gh_difftool::cache::ChangeSetCache;
+use gh_difftool::change_set::{Change, ChangeSet, SortOrder};
+use gh_difftool::diff::{Diff, Difftool};
+use gh_difftool::failures::Failures;
+use gh_difftool::gh_interface::{PrSummary, PullRequest};
+use gh_difftool::report::{Outcome, Report};
+use gh_difftool::since::Since;
+use gh_difftool::{csv, gh_interface, git_config, interdiff, since, stat, suggestions};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, SystemTime};
 use url::Url;
 
 #[derive(Parser)]
@@ -27,23 +30,98 @@ struct Cli {
     #[arg(short = 't', long = "tool", env = "GH_DIFFTOOL")]
     tool: Option<String>,
 
+    /// Launch COMMAND for each file instead of a configured difftool, e.g. "meld $LOCAL $REMOTE"
+    ///
+    /// Takes precedence over `--tool`, for trying out a tool without configuring it in git.
+    #[arg(long = "tool-command", value_name = "COMMAND")]
+    tool_command: Option<String>,
+
+    /// Use the GUI difftool from `diff.guitool` instead of `diff.tool`, when no tool is given
+    #[arg(short = 'g', long = "gui")]
+    gui: bool,
+
+    /// Run the `diff.external` driver from git config for each file instead of a difftool
+    ///
+    /// The driver gets the same 7 arguments git gives it: path, old-file, old-hex, old-mode,
+    /// new-file, new-hex, and new-mode.
+    #[arg(long = "use-external-driver", conflicts_with_all = ["tool", "gui", "tool_command"])]
+    use_external_driver: bool,
+
+    /// List the difftools that can be used with `--tool`
+    #[arg(long = "tool-help")]
+    tool_help: bool,
+
+    /// Print the completion script for `SHELL` and exit
+    #[arg(long = "completions", value_name = "SHELL", hide = true)]
+    completions: Option<Shell>,
+
     /// The GitHub repo to diff, defaults to the GitHub remote of the current git repo
     #[arg(short = 'R', long = "repo", requires = "pr", value_names = ["OWNER/REPO"])]
     repo: Option<String>,
 
+    /// Log the `gh` commands run and the difftools launched to stderr, repeat for more detail
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    /// The GitHub hostname to use, e.g. for GitHub Enterprise, defaults to the host `gh` uses
+    #[arg(long = "hostname", env = "GH_HOST", value_name = "HOST")]
+    hostname: Option<String>,
+
     /// The pull request to diff
     ///
     /// When omitted the pull request associated with the current branch will be used
     /// A pull request can be supplied as argument in any of the following formats:
     /// - by number, e.g. "123"
     /// - by URL, e.g. "https://github.com/OWNER/REPO/pull/123"
+    /// - by the name of its head branch, e.g. "my-feature-branch"
     #[arg(value_parser=parse_pr, verbatim_doc_comment)]
-    pr: Option<PullRequest>,
+    pr: Option<PrArg>,
 
     /// Show only the names of files that changed in a pull request
     #[arg(long = "name-only")]
     name_only: bool,
 
+    /// Show the names of files that changed in a pull request, each with its status like
+    /// `git diff --name-status`
+    #[arg(long = "name-status", conflicts_with = "name_only")]
+    name_status: bool,
+
+    /// Print the changes of the pull request as JSON instead of launching a difftool
+    #[arg(long = "json", conflicts_with_all = ["name_only", "name_status"])]
+    json: bool,
+
+    /// Show only the names of the binary files that changed in a pull request
+    ///
+    /// Binary files can't be diffed as text, these may need to be reviewed another way.
+    #[arg(long = "only-binary", conflicts_with_all = ["name_only", "name_status", "json"])]
+    only_binary: bool,
+
+    /// Show a summary of the lines changed for each file in a pull request, like `git diff --stat`
+    #[arg(long = "stat", conflicts_with_all = ["name_only", "name_status", "json", "only_binary"])]
+    stat: bool,
+
+    /// Show each directory with files changed in a pull request and how many, ordered by ORDER
+    #[arg(
+        long = "dirs",
+        value_name = "ORDER",
+        num_args = 0..=1,
+        default_missing_value = "count",
+        require_equals = true,
+        conflicts_with_all = ["name_only", "name_status", "json", "only_binary", "stat"]
+    )]
+    dirs: Option<stat::DirOrder>,
+
+    /// Write the changes of the pull request to PATH as CSV instead of launching a difftool
+    ///
+    /// One row per file with the filename, previous filename, status, additions, deletions and
+    /// blob sha. Useful for tracking a review in a spreadsheet.
+    #[arg(
+        long = "csv",
+        value_name = "PATH",
+        conflicts_with_all = ["name_only", "name_status", "json", "only_binary", "stat"]
+    )]
+    csv: Option<PathBuf>,
+
     /// Start showing the diff for the given file, the files before it will move to end.
     ///
     /// Applied before `--skip-to`. This behavior deviates from `git-difftool` which
@@ -51,12 +129,382 @@ struct Cli {
     #[arg(long = "rotate-to", verbatim_doc_comment)]
     rotate_to: Option<String>,
 
+    /// The order to show the files in
+    ///
+    /// Applied before `--rotate-to` and `--skip-to`.
+    #[arg(long = "sort", value_name = "ORDER", default_value = "api")]
+    sort: SortOrder,
+
+    /// Only diff files with the given statuses
+    ///
+    /// Any combination of the following letters:
+    /// - A, added
+    /// - C, copied
+    /// - D, deleted (removed)
+    /// - M, modified
+    /// - R, renamed
+    /// - T, changed (type changed)
+    #[arg(long = "diff-filter", value_parser = parse_diff_filter, verbatim_doc_comment)]
+    diff_filter: Option<String>,
+
+    /// Only diff files under the given directory, relative to the repository root
+    #[arg(long = "subdir", value_name = "PATH")]
+    subdir: Option<String>,
+
     /// Start showing the diff for the given file, skipping all the files before it.
     #[arg(long = "skip-to")]
     skip_to: Option<String>,
 
+    /// Pick the files to review from a numbered list of the changed files
+    ///
+    /// Only offered when run from a terminal. Files are picked by number, e.g. "2", by range,
+    /// e.g. "2-5", or from a file on, e.g. "4-", separated by commas. Nothing picks all of them.
+    #[arg(
+        short = 'i',
+        long = "interactive",
+        conflicts_with_all = ["skip_to", "rotate_to", "files", "files_from", "name_only", "name_status", "json", "only_binary", "stat", "dirs", "csv"]
+    )]
+    interactive: bool,
+
+    /// Show the files in reverse order.
+    ///
+    /// Applied after `--rotate-to` and `--skip-to`, so the given file is shown last.
+    #[arg(long = "reverse")]
+    reverse: bool,
+
+    /// Exit with 1 when the pull request has changes to show and 0 when it doesn't, like
+    /// `git diff --exit-code`
+    ///
+    /// Applied after the filtering options, e.g. `--diff-filter=A --exit-code` fails when files
+    /// were added.
+    #[arg(long = "exit-code")]
+    exit_code: bool,
+
+    /// Show only the first N files
+    ///
+    /// Applied after the files are ordered and filtered, so `--skip-to` skips then the next N
+    /// files are shown.
+    #[arg(long = "limit", value_name = "N")]
+    limit: Option<usize>,
+
+    /// Close the difftool after SECONDS, instead of waiting for it to be closed
+    #[arg(long = "tool-timeout", value_name = "SECONDS", value_parser = parse_tool_timeout)]
+    tool_timeout: Option<Duration>,
+
+    /// Extra environment variable to set for the difftool, may be repeated
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env, action = ArgAction::Append)]
+    env: Vec<(String, String)>,
+
+    /// Extra argument to pass to the difftool after the files, may be repeated
+    ///
+    /// For example `--tool-arg=--newtab` to open each file in a new tab of meld.
+    #[arg(long = "tool-arg", value_name = "ARG", allow_hyphen_values = true, action = ArgAction::Append)]
+    tool_args: Vec<String>,
+
+    /// Open both versions of files with the extension EXT in the default application of the OS
+    /// instead of the difftool, may be repeated or comma separated
+    ///
+    /// Useful for files like images or PDFs which the difftool can't show well.
+    #[arg(long = "open-with-default", value_name = "EXT", value_delimiter = ',', action = ArgAction::Append)]
+    open_with_default: Vec<String>,
+
+    /// Use the `--large-file-tool` for files whose new version is larger than BYTES
+    #[arg(
+        long = "large-file-threshold",
+        value_name = "BYTES",
+        requires = "large_file_tool"
+    )]
+    large_file_threshold: Option<u64>,
+
+    /// The tool to use for files larger than the `--large-file-threshold`, e.g. a terminal diff
+    /// which is faster than a GUI difftool for huge files
+    #[arg(
+        long = "large-file-tool",
+        value_name = "TOOL",
+        requires = "large_file_threshold"
+    )]
+    large_file_tool: Option<String>,
+
+    /// Use TOOL for files with the extension EXT instead of the difftool, may be repeated
+    ///
+    /// For example `--tool-for png=imgdiff` to diff images with an image diff tool.
+    #[arg(long = "tool-for", value_name = "EXT=TOOL", value_parser = parse_tool_for, action = ArgAction::Append)]
+    tool_for: Vec<(String, String)>,
+
+    /// Prompt before launching the difftool for each file
+    ///
+    /// Defaults to the `difftool.prompt` git config option.
+    #[arg(long = "prompt", overrides_with = "no_prompt")]
+    prompt: bool,
+
+    /// Do not prompt before launching the difftool for each file
+    #[arg(long = "no-prompt", overrides_with = "prompt")]
+    no_prompt: bool,
+
+    /// How many times to retry a `gh` request that fails with a rate limit or server error
+    ///
+    /// Each retry waits twice as long as the one before, starting at one second. Rate limits wait
+    /// for the limit to reset when GitHub says when that is.
+    #[arg(long = "retries", value_name = "N", default_value_t = gh_interface::DEFAULT_RETRIES)]
+    retries: u32,
+
+    /// Fail when a downloaded file doesn't match the blob sha GitHub gives for it, instead of
+    /// warning
+    ///
+    /// A mismatch means the download was truncated or corrupted.
+    #[arg(long = "strict")]
+    strict: bool,
+
+    /// The number of changed files to request from GitHub at a time
+    ///
+    /// Larger pages need fewer requests, but GitHub may omit the patch of the last few files on a
+    /// page when it is larger than the default.
+    #[arg(long = "page-size", value_name = "N", default_value_t = gh_interface::DEFAULT_PAGE_SIZE, value_parser = parse_page_size, hide_short_help = true)]
+    page_size: usize,
+
+    /// The number of files to download at once
+    ///
+    /// "auto" uses the available parallelism of the machine, capped at 4 to avoid tripping
+    /// GitHub's secondary rate limits.
+    #[arg(long = "jobs", value_name = "N|auto", default_value = "auto", value_parser = parse_jobs, verbatim_doc_comment)]
+    jobs: Jobs,
+
+    /// Write a markdown summary of the review to PATH once done, suitable for a pull request comment
+    ///
+    /// The summary has the status of each file and whether it was viewed or skipped.
+    #[arg(long = "report", value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Copy the base and new version of each file into this directory after it has been diffed
+    ///
+    /// On Unix the new version of an executable file keeps its executable bit.
+    #[arg(long = "save-dir", value_name = "DIR")]
+    save_dir: Option<PathBuf>,
+
+    /// Keep the temporary directory with the files after the review, instead of deleting it
+    ///
+    /// Its location is printed once the review is done, for opening the files in another tool.
+    #[arg(long = "keep")]
+    keep: bool,
+
+    /// Diff the base branch against what the pull request would look like once merged
+    ///
+    /// Uses the test merge commit GitHub creates for the pull request, so any changes made to
+    /// the base branch since the pull request was created will also be shown.
+    #[arg(long = "merged-preview", verbatim_doc_comment)]
+    merged_preview: bool,
+
+    /// Diff the files in the local working tree against the pull request
+    ///
+    /// Useful to check that a local checkout matches what is on GitHub.
+    #[arg(long = "working-tree", conflicts_with = "merged_preview")]
+    working_tree: bool,
+
+    /// Diff only what changed in the pull request since a previous head commit, an interdiff
+    ///
+    /// Useful to re-review a pull request after it was force pushed. Each file is diffed from
+    /// its version at `--prev-head` to its version at the current head.
+    #[arg(
+        long = "interdiff",
+        requires = "prev_head",
+        conflicts_with_all = ["merged_preview", "working_tree"]
+    )]
+    interdiff: bool,
+
+    /// The previous head commit of the pull request to use for `--interdiff`
+    #[arg(long = "prev-head", value_name = "SHA", requires = "interdiff")]
+    prev_head: Option<String>,
+
+    /// Diff only what changed in the pull request since WHEN, an interdiff
+    ///
+    /// WHEN is one of:
+    /// - a duration, e.g. "2d", to review the commits from the last two days; units are m, h, d,
+    ///   and w
+    /// - "last-review", to review what changed since your last review
+    /// - "last-push", to review what the last push changed
+    #[arg(
+        long = "since",
+        value_name = "WHEN",
+        value_parser = since::parse_since,
+        conflicts_with_all = ["merged_preview", "working_tree", "interdiff", "base", "base_branch", "preview_suggestions"],
+        verbatim_doc_comment
+    )]
+    since: Option<Since>,
+
+    /// Only diff the files changed by commits made after DATE, showing their whole change in the
+    /// pull request
+    ///
+    /// DATE is a day like "2024-03-01", or a time like "2024-03-01T12:00:00Z", in UTC. Useful
+    /// for periodically re-reviewing a long-lived pull request. All of the files are diffed when
+    /// the commits can't be fetched.
+    #[arg(long = "touched-since", value_name = "DATE", value_parser = since::parse_date)]
+    touched_since: Option<u64>,
+
+    /// Diff the files at REF, a commit, branch, or tag, against the pull request instead of the
+    /// base of the pull request
+    ///
+    /// Useful to compare the pull request against the current state of the base branch. Files
+    /// that don't exist at REF are shown as added.
+    #[arg(
+        long = "base",
+        value_name = "REF",
+        conflicts_with_all = ["merged_preview", "working_tree", "interdiff"]
+    )]
+    base: Option<String>,
+
+    /// Diff the files at the current tip of the pull request's base branch against the pull
+    /// request, like `--base` with the name of the base branch
+    ///
+    /// Shows what merging would do when the pull request is behind its base branch.
+    #[arg(
+        long = "base-branch",
+        conflicts_with_all = ["merged_preview", "working_tree", "interdiff", "base", "preview_suggestions", "commits"]
+    )]
+    base_branch: bool,
+
+    /// Diff the files with suggested changes in review comments against the suggestions applied
+    ///
+    /// Only the files with suggestions are diffed. Suggestions on outdated comments, and those
+    /// overlapping an earlier suggestion, are left out.
+    #[arg(
+        long = "preview-suggestions",
+        conflicts_with_all = ["merged_preview", "working_tree", "interdiff", "base"]
+    )]
+    preview_suggestions: bool,
+
+    /// Cache the list of changed files, reusing it on later runs until the pull request's head
+    /// changes
+    ///
+    /// Useful for rerunning a big pull request after some downloads failed.
+    #[arg(long = "reuse-changeset")]
+    reuse_changeset: bool,
+
+    /// Diff the pull request as of the head commit SHA instead of its current head
+    ///
+    /// Pins the files of a review when the pull request is updated in the middle of it. Combine
+    /// with `--base` for a fully pinned comparison. GitHub compares at most 300 files.
+    #[arg(
+        long = "head",
+        value_name = "SHA",
+        conflicts_with_all = ["merged_preview", "preview_suggestions", "reuse_changeset"]
+    )]
+    head: Option<String>,
+
+    /// Diff only the commits in RANGE, given as BASE..HEAD, instead of the whole pull request
+    ///
+    /// Useful for reviewing a large pull request commit by commit, or only the commits added
+    /// since a previous review. GitHub compares at most 300 files.
+    #[arg(
+        long = "commits",
+        value_name = "RANGE",
+        value_parser = parse_commits,
+        conflicts_with_all = ["merged_preview", "working_tree", "interdiff", "since", "base", "preview_suggestions", "reuse_changeset", "head"]
+    )]
+    commits: Option<(String, String)>,
+
+    /// Use the blob with SHA as the base version of FILE, may be repeated
+    #[arg(long = "base-blob", value_name = "FILE=SHA", value_parser = parse_base_blob, action = ArgAction::Append)]
+    base_blobs: Vec<(String, String)>,
+
+    /// Download the base version of renamed and copied files instead of assuming it is the same
+    /// as the new version when GitHub provides no patch
+    #[arg(long = "verify-renames")]
+    verify_renames: bool,
+
+    /// Pass `--binary` to the system `patch` so line endings are not translated
+    ///
+    /// Useful on Windows for patches with CRLF line endings.
+    #[arg(long = "binary-patch")]
+    binary_patch: bool,
+
+    /// Let `patch` translate the line endings of the base version on Windows
+    ///
+    /// By default both versions keep the line endings from GitHub, otherwise the base version
+    /// gets CRLF line endings and every line shows as changed.
+    #[arg(long = "no-crlf-normalize", conflicts_with = "binary_patch")]
+    no_crlf_normalize: bool,
+
+    /// Use the new version of each file as the base version, so the difftool shows no differences
+    ///
+    /// A debugging aid to tell whether a confusing diff comes from the download or from reverse
+    /// applying the patch.
+    #[arg(long = "no-reverse", hide = true)]
+    no_reverse: bool,
+
+    /// Check the base version reconstructed from each patch against the actual base
+    ///
+    /// Prints the files whose reconstructed base differs, failing when there are any, instead of
+    /// launching the difftool.
+    #[arg(long = "audit-reconstruction")]
+    audit_reconstruction: bool,
+
+    /// Print the command line the difftool would be launched with for each file, instead of
+    /// launching it
+    ///
+    /// Useful for checking a `difftool.<tool>.cmd` config. The temporary files are removed once
+    /// done, unless kept with `--temp-name`.
+    #[arg(long = "print-command")]
+    print_command: bool,
+
+    /// Print how FILE would be diffed as JSON instead of launching the difftool
+    ///
+    /// Describes the kind of change, where the base version comes from, how it's reconstructed
+    /// from the patch, and the temporary files. Nothing is downloaded.
+    #[arg(
+        long = "explain",
+        value_name = "FILE",
+        conflicts_with_all = ["name_only", "name_status", "json", "only_binary", "stat", "dirs", "csv", "audit_reconstruction"]
+    )]
+    explain: Option<String>,
+
+    /// Diff FILE against the base version from reverse applying the patch read from stdin
+    ///
+    /// Nothing is fetched from GitHub. Useful for diffing a local patch or checking how a patch
+    /// is reverse applied.
+    #[arg(
+        long = "stdin-patch",
+        value_name = "FILE",
+        conflicts_with_all = ["name_only", "name_status", "json", "only_binary", "stat", "dirs", "csv", "audit_reconstruction", "explain"]
+    )]
+    stdin_patch: Option<PathBuf>,
+
+    /// Open the pull request in the browser instead of launching a difftool
+    ///
+    /// For reviewing without a difftool configured.
+    #[arg(
+        long = "web",
+        conflicts_with_all = ["tool", "gui", "tool_command", "use_external_driver", "name_only", "name_status", "json", "only_binary", "stat", "dirs", "csv", "audit_reconstruction", "explain", "stdin_patch"]
+    )]
+    web: bool,
+
+    /// Create the temporary directory in PATH instead of the system temporary directory
+    ///
+    /// For when the system temporary directory is small or mounted `noexec`. The system temporary
+    /// directory already honors `TMPDIR` on Unix.
+    #[arg(long = "tmpdir", value_name = "PATH", value_parser = parse_tmpdir)]
+    tmpdir: Option<PathBuf>,
+
+    /// Use a fixed name for the temporary directory instead of a random one
+    #[arg(long = "temp-name", value_name = "NAME")]
+    temp_name: Option<String>,
+
+    /// Replace the temporary directory from `--temp-name` if it already exists
+    #[arg(long = "force", requires = "temp_name")]
+    force: bool,
+
+    /// Read more files to diff from PATH, one per line, or from stdin when PATH is "-"
+    ///
+    /// Empty lines are ignored. An empty list leaves nothing to diff.
+    #[arg(long = "files-from", value_name = "PATH")]
+    files_from: Option<PathBuf>,
+
+    /// Diff the files matched by the repository's `.gh-difftoolignore` too
+    #[arg(long = "include-ignored")]
+    include_ignored: bool,
+
     /// Specific files to diff.
     ///
+    /// Glob patterns, e.g. 'src/**/*.rs', are supported.
     /// When not provided all of the files that changed in the pull request
     /// will be diffed
     #[arg(last=true, action=ArgAction::Append)]
@@ -66,23 +514,227 @@ struct Cli {
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    init_logging(cli.verbose);
+
+    if cli.tool_help {
+        return tool_help();
+    }
+
+    if let Some(shell) = cli.completions {
+        clap_complete::generate(
+            shell,
+            &mut Cli::command(),
+            "gh-difftool",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
+    }
+
+    // Resolve the difftool before any requests to GitHub so a mistyped tool fails right away.
+    // Only listing the changes doesn't need a difftool.
+    let lists_changes = cli.name_only
+        || cli.name_status
+        || cli.only_binary
+        || cli.stat
+        || cli.dirs.is_some()
+        || cli.json
+        || cli.csv.is_some();
+    let difftool = if lists_changes || cli.audit_reconstruction || cli.explain.is_some() || cli.web
+    {
+        None
+    } else if let Some(command) = &cli.tool_command {
+        Some(git_config::Difftool::from_command(command)?)
+    } else if cli.use_external_driver {
+        Some(git_config::Difftool::external(std::env::current_dir()?)?)
+    } else {
+        Some(
+            git_config::Difftool::new(std::env::current_dir()?, cli.tool.as_deref(), cli.gui)
+                .map_err(suggest_web)?,
+        )
+    };
+    let large_file_tool = match (cli.large_file_threshold, &cli.large_file_tool) {
+        (Some(threshold), Some(tool)) if difftool.is_some() => {
+            let tool = git_config::Difftool::new(std::env::current_dir()?, Some(tool), false)?
+                .with_env(cli.env.clone())
+                .with_timeout(cli.tool_timeout);
+            Some((threshold, tool))
+        }
+        _ => None,
+    };
+    let mut extension_tools = HashMap::new();
+    if difftool.is_some() {
+        for (extension, tool) in &cli.tool_for {
+            let tool = git_config::Difftool::new(std::env::current_dir()?, Some(tool), false)?
+                .with_env(cli.env.clone())
+                .with_timeout(cli.tool_timeout);
+            extension_tools.insert(extension.clone(), tool);
+        }
+    }
+    let difftool = difftool.map(|difftool| {
+        difftool
+            .with_env(cli.env)
+            .with_extra_args(cli.tool_args)
+            .with_timeout(cli.tool_timeout)
+    });
+
+    if let Some(new) = cli.stdin_patch {
+        let mut patch = String::new();
+        std::io::stdin().read_to_string(&mut patch)?;
+        let difftool = difftool.expect("The difftool is resolved for a patch from stdin");
+        let diff = match cli.tmpdir {
+            Some(tmpdir) => Diff::new_in(difftool, tmpdir)?,
+            None => Diff::new(difftool)?,
+        };
+        let diff = diff
+            .with_binary_patch(cli.binary_patch)
+            .with_crlf_normalize(!cli.no_crlf_normalize);
+        return diff.local_patch(&new, patch)?.launch().await;
+    }
 
-    let mut gh = gh_interface::GhCli::new(Command::new("gh"));
-    let mut pr = match cli.pr {
-        Some(pr) => pr,
-        None => PullRequest::new_from_cwd()?,
+    let hostname = pr_hostname(cli.hostname, cli.pr.as_ref());
+
+    let mut gh = gh_interface::GhCli::new(Command::new("gh"))
+        .with_hostname(hostname.clone())
+        .with_page_size(cli.page_size)
+        .with_retries(cli.retries);
+    let event_path = std::env::var_os("GITHUB_EVENT_PATH");
+    let mut pr = match (cli.pr, event_path) {
+        (Some(PrArg::Url(pr, _)), _) => pr,
+        // Only look up the repo of the current directory when it's not given, so a pull request
+        // can be reviewed from outside of a checkout
+        (Some(PrArg::Number(number)), _) => {
+            let repo = match &cli.repo {
+                Some(repo) => repo.clone(),
+                None => gh.current_repo()?,
+            };
+            PullRequest { repo, number }
+        }
+        (Some(PrArg::Branch(branch)), _) => {
+            let repo = match &cli.repo {
+                Some(repo) => repo.clone(),
+                None => gh.current_repo()?,
+            };
+            let number = gh.branch_pr(&repo, &branch)?;
+            PullRequest { repo, number }
+        }
+        (None, event_path) => {
+            // In GitHub Actions the pull request comes from the event which triggered the
+            // workflow. Other events, like a push, use the pull request of the current branch.
+            let event_pr = event_path
+                .map(PullRequest::new_from_event)
+                .transpose()?
+                .flatten();
+            match event_pr {
+                Some(pr) => pr,
+                None => match PullRequest::new_from_cwd(hostname.clone()) {
+                    Ok(pr) => pr,
+                    // Only offer to pick a pull request when someone is around to pick one
+                    Err(_) if std::io::stdin().is_terminal() && std::io::stderr().is_terminal() => {
+                        pick_pr(&mut gh)?
+                    }
+                    Err(error) => return Err(error),
+                },
+            }
+        }
     };
 
     if let Some(repo) = cli.repo {
         pr.repo = repo;
     };
 
-    let mut change_set = gh.change_set(&pr)?;
+    if cli.web {
+        return gh.open_in_browser(&pr);
+    }
 
-    let files = cli.files;
-    if !files.is_empty() {
+    // A range of commits is a pinned head compared from the start of the range, instead of from
+    // the base of the pull request
+    let (range_base, head) = match cli.commits {
+        Some((base, head)) => (Some(base), Some(head)),
+        None => (None, cli.head),
+    };
+
+    let mut change_set = match &head {
+        // The files of the pull request are only available for the current head
+        Some(head) => {
+            let base_sha = match &range_base {
+                Some(base) => base.clone(),
+                None => gh.base_sha(&pr)?,
+            };
+            gh.compare(&pr.repo, &base_sha, head)?
+        }
+        None if cli.reuse_changeset => {
+            reused_change_set(&mut gh, &pr, &ChangeSetCache::in_user_cache())?
+        }
+        None => gh.change_set(&pr)?,
+    };
+    // Nothing else would be shown, so say why rather than finish silently
+    if change_set.changes.is_empty() {
+        eprintln!("No changed files in {}#{}", pr.repo, pr.number);
+    }
+
+    let prev_head = match &cli.since {
+        Some(since) => Some(gh.since_commit(&pr, since, SystemTime::now())?),
+        None => cli.prev_head,
+    };
+    let head_sha = match &prev_head {
+        Some(prev_head) => {
+            let base_sha = gh.base_sha(&pr)?;
+            let prev_change_set = gh.compare(&pr.repo, &base_sha, prev_head)?;
+            change_set = interdiff::interdiff(prev_change_set, change_set);
+            match &head {
+                Some(head) => Some(head.clone()),
+                None => Some(gh.head_sha(&pr)?),
+            }
+        }
+        None => None,
+    };
+
+    let suggestions = if cli.preview_suggestions {
+        let suggestions = suggestions::by_path(gh.suggestions(&pr)?);
+        change_set
+            .changes
+            .retain(|change| suggestions.contains_key(&change.filename));
+        Some(suggestions)
+    } else {
+        None
+    };
+
+    let mut files = cli.files;
+    if let Some(path) = &cli.files_from {
+        files.extend(read_files_from(path)?);
+    }
+    if !files.is_empty() || cli.files_from.is_some() {
         change_set.filter_files(&files);
     }
+    if let Some(date) = cli.touched_since {
+        // Periodically re-reviewing is better off seeing everything than nothing
+        match gh.files_touched_since(&pr, date) {
+            Ok(touched) => {
+                change_set.filter_paths(&touched);
+            }
+            Err(error) => eprintln!(
+                "Diffing all files, couldn't find the files touched since the date: {error:#}"
+            ),
+        }
+    }
+    if !cli.include_ignored {
+        let patterns = git_config::ignore_patterns(std::env::current_dir()?)?;
+        // The `.gh-difftoolignore` of the current checkout is only for its own pull requests, not
+        // those of another repo given by `--repo` or a URL
+        if !patterns.is_empty() && gh.current_repo().is_ok_and(|current| current == pr.repo) {
+            change_set.exclude_files(&patterns);
+        }
+    }
+
+    if let Some(filter) = cli.diff_filter {
+        change_set.filter_status(&diff_filter_statuses(&filter));
+    }
+
+    if let Some(subdir) = cli.subdir {
+        change_set.filter_subdir(subdir);
+    }
+
+    change_set.sort_by(cli.sort);
 
     // Rotation is intentionally before skipping. This allows one to look at only ['2', '3'] from
     // ['1', '2', '3', '4'], by rotating to '4' and skipping to '2'. If skip happened first then
@@ -95,91 +747,662 @@ async fn main() -> Result<()> {
     if let Some(filename) = cli.skip_to {
         change_set.skip_to(filename)?;
     }
+    if cli.reverse {
+        change_set.reverse();
+    }
+
+    if cli.only_binary {
+        change_set.filter_binary();
+    }
+    if let Some(count) = cli.limit {
+        change_set.limit(count);
+    }
+
+    if cli.interactive && std::io::stdin().is_terminal() && std::io::stderr().is_terminal() {
+        pick_files(&mut change_set)?;
+    }
+
+    // Checked before the changes are consumed, the exit happens once they've been shown
+    let exit_for_changes = cli.exit_code && !change_set.changes.is_empty();
+
+    if lists_changes {
+        if cli.name_only || cli.only_binary {
+            for change in change_set.changes {
+                let filename = change.filename;
+                println!("{filename}");
+            }
+        } else if cli.name_status {
+            for change in &change_set.changes {
+                println!("{}", name_status(change));
+            }
+        } else if cli.stat {
+            print!("{}", stat::diffstat(&change_set, stat::terminal_width()));
+        } else if let Some(order) = cli.dirs {
+            print!("{}", stat::dirstat(&change_set, order));
+        } else if cli.json {
+            println!("{}", change_set_json(&pr, &change_set)?);
+        } else if let Some(path) = cli.csv {
+            std::fs::write(&path, csv::change_set_csv(&change_set))
+                .with_context(|| format!("Failed to write the changes to {}", path.display()))?;
+        }
+        return exit_if_changed(exit_for_changes);
+    }
+
+    let merge_sha = if cli.merged_preview {
+        Some(gh.merge_sha(&pr).context(Error::NoMergeCommit(pr.number))?)
+    } else {
+        None
+    };
+
+    // Binary files, and modified and renamed files with large diffs, come without a patch, so the
+    // base version has to be downloaded. Only look up the base when needed to avoid an extra
+    // request.
+    let base_sha = if cli.merged_preview
+        || cli.audit_reconstruction
+        || change_set.changes.iter().any(Change::needs_base_contents)
+        || (cli.verify_renames && change_set.changes.iter().any(Change::is_rename))
+    {
+        match &range_base {
+            Some(base) => Some(base.clone()),
+            None => Some(gh.base_sha(&pr)?),
+        }
+    } else {
+        None
+    };
+
+    // Only the executable bit of the saved files is set from the mode, which Windows doesn't have.
+    // A `diff.external` driver is told the mode on any platform, like git does.
+    let file_modes = if (cfg!(unix) && cli.save_dir.is_some()) || cli.use_external_driver {
+        let head_sha = match (&head_sha, &head) {
+            (Some(head_sha), _) | (None, Some(head_sha)) => head_sha.clone(),
+            (None, None) => gh.head_sha(&pr)?,
+        };
+        gh.file_modes(&pr.repo, &head_sha)?
+    } else {
+        HashMap::new()
+    };
+
+    let base_ref = if cli.base_branch {
+        Some(gh.base_branch(&pr)?)
+    } else {
+        cli.base
+    };
+
+    let working_tree = if cli.working_tree {
+        Some(git_config::repo_root(std::env::current_dir()?)?)
+    } else {
+        None
+    };
+
+    // The audit and explain never launch the difftool, so they're the only ones to get here
+    // without one
+    let difftool = difftool.unwrap_or_default();
+    let diff_files = match (cli.temp_name, cli.tmpdir) {
+        (Some(name), tmpdir) => {
+            let root = tmpdir.unwrap_or_else(std::env::temp_dir);
+            Diff::new_with_temp_name(difftool, root, name, cli.force)?
+        }
+        (None, Some(tmpdir)) => Diff::new_in(difftool, tmpdir)?,
+        (None, None) => Diff::new(difftool)?,
+    };
+    let diff_files = diff_files
+        .with_base_sha(base_sha)
+        .with_merge_sha(merge_sha)
+        .with_working_tree(working_tree)
+        .with_base_blobs(cli.base_blobs.into_iter().collect())
+        .with_verify_renames(cli.verify_renames)
+        .with_binary_patch(cli.binary_patch)
+        .with_crlf_normalize(!cli.no_crlf_normalize)
+        .with_no_reverse(cli.no_reverse)
+        .with_hostname(hostname)
+        .with_retries(cli.retries)
+        .with_strict(cli.strict)
+        .with_head_sha(head_sha)
+        .with_pinned_head(head)
+        .with_prev_head(prev_head)
+        .with_base_ref(base_ref)
+        .with_open_with_default(cli.open_with_default)
+        .with_large_file_tool(large_file_tool)
+        .with_extension_tools(extension_tools)
+        .with_file_modes(file_modes)
+        .with_suggestions(suggestions);
+    if cli.audit_reconstruction {
+        return audit_reconstruction(&diff_files, change_set).await;
+    }
+    if let Some(file) = &cli.explain {
+        let change = change_set
+            .changes
+            .iter()
+            .find(|change| &change.filename == file)
+            .ok_or_else(|| Error::NoSuchPath(file.clone()))?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff_files.explain(change))?
+        );
+        return Ok(());
+    }
+    let prompt = match (cli.prompt, cli.no_prompt) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => git_config::prompt(std::env::current_dir()?)?,
+    };
+    let options = DiffOptions {
+        save_dir: cli.save_dir.as_deref(),
+        prompt,
+        jobs: cli.jobs.resolve(),
+        progress: std::io::stderr().is_terminal(),
+        print_command: cli.print_command,
+        keep: cli.keep,
+    };
+    let report = diff(diff_files, change_set, &options).await?;
+    if let Some(path) = cli.report {
+        std::fs::write(&path, report.markdown(&pr))
+            .with_context(|| format!("Failed to write the report to {}", path.display()))?;
+    }
+    exit_if_changed(exit_for_changes)
+}
+
+/// Logs this crate's warnings, and with `--verbose` more, to stderr
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        // Only this crate, the dependencies log more than is useful for seeing what's run
+        metadata.level() <= log::max_level() && metadata.target().starts_with("gh_difftool")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Log to stderr at the level for how many times `--verbose` was given, only warnings without it
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    log::set_logger(&LOGGER).expect("The logger is only set once");
+    log::set_max_level(level);
+}
+
+/// Exit with 1 when `changed`, like `git diff --exit-code`, otherwise carry on
+fn exit_if_changed(changed: bool) -> Result<()> {
+    if changed {
+        std::io::stdout().flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The change set of `pr` from `cache` when it's for the current head, otherwise fetched and
+/// cached for the next run
+fn reused_change_set(
+    gh: &mut gh_interface::GhCli<Command>,
+    pr: &PullRequest,
+    cache: &ChangeSetCache,
+) -> Result<ChangeSet> {
+    let head_sha = gh.head_sha(pr)?;
+    if let Some(change_set) = cache.load(pr, &head_sha) {
+        return Ok(change_set);
+    }
+    let change_set = gh.change_set(pr)?;
+    cache.store(pr, &head_sha, &change_set)?;
+    Ok(change_set)
+}
+
+/// The files listed in `path` for `--files-from`, stdin when `path` is "-"
+fn read_files_from(path: &Path) -> Result<Vec<String>> {
+    if path == Path::new("-") {
+        return files_from_lines(std::io::stdin().lock());
+    }
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to read the files to diff from {}", path.display()))?;
+    files_from_lines(std::io::BufReader::new(file))
+}
+
+/// Each non empty line of `reader` as a file
+fn files_from_lines(reader: impl BufRead) -> Result<Vec<String>> {
+    let mut files = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            files.push(line.to_string());
+        }
+    }
+    Ok(files)
+}
+
+/// Print the difftools which can be used with `--tool`, similar to `git difftool --tool-help`
+fn tool_help() -> Result<()> {
+    let (available, unavailable): (Vec<_>, Vec<_>) = git_config::tools(std::env::current_dir()?)?
+        .into_iter()
+        .partition(|tool| tool.available);
+    println!("'gh difftool --tool=<tool>' may be set to one of the following:");
+    for tool in available {
+        println!("\t\t{}", tool.name);
+    }
+    println!();
+    println!("The following tools are valid, but not currently available:");
+    for tool in unavailable {
+        println!("\t\t{}", tool.name);
+    }
+    Ok(())
+}
+
+/// The changes of a pull request as printed by `--json`
+#[derive(Debug, Serialize)]
+struct ChangeSetJson<'a> {
+    repo: &'a str,
+    number: usize,
+    changes: &'a [Change],
+}
+
+/// The changes of `change_set` for `pr` as pretty printed JSON
+fn change_set_json(pr: &PullRequest, change_set: &ChangeSet) -> Result<String> {
+    let json = ChangeSetJson {
+        repo: &pr.repo,
+        number: pr.number,
+        changes: &change_set.changes,
+    };
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+/// Options for how the difftool is launched for each change
+struct DiffOptions<'a> {
+    /// Directory to copy each diffed file pair into
+    save_dir: Option<&'a Path>,
+    /// Prompt before launching the difftool for each change
+    prompt: bool,
+    /// The number of changes to download at once
+    jobs: usize,
+    /// Show how many changes have been downloaded while waiting on them
+    progress: bool,
+    /// Print the command line of the difftool for each change instead of launching it
+    print_command: bool,
+    /// Keep the temporary directory after the review
+    keep: bool,
+}
+
+/// A `prefetched N/M files` line on stderr, rewritten in place as changes are downloaded
+struct Progress {
+    enabled: bool,
+    total: usize,
+    /// Whether the line has been written and not ended yet
+    shown: bool,
+}
+
+impl Progress {
+    fn new(enabled: bool, total: usize) -> Self {
+        Self {
+            enabled,
+            total,
+            shown: false,
+        }
+    }
 
-    if cli.name_only {
-        for change in change_set.changes {
-            let filename = change.filename;
-            println!("{filename}");
+    fn update(&mut self, prefetched: usize) {
+        if self.enabled {
+            eprint!("\r{}", progress_line(prefetched, self.total));
+            self.shown = true;
+        }
+    }
+
+    /// End the line, so other output doesn't get appended to it
+    fn finish(&mut self) {
+        if self.shown {
+            eprintln!();
+            self.shown = false;
+        }
+    }
+}
+
+fn progress_line(prefetched: usize, total: usize) -> String {
+    format!("prefetched {prefetched}/{total} files")
+}
+
+/// The upper limit for `--jobs auto`, downloads are network bound so more cores don't help much
+const MAX_AUTO_JOBS: usize = 4;
+
+/// How many changes to download at once
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Jobs {
+    /// Scale with the available parallelism, up to [MAX_AUTO_JOBS]
+    Auto,
+    Count(usize),
+}
+
+impl Jobs {
+    /// The number of jobs to use
+    fn resolve(self) -> usize {
+        match self {
+            Jobs::Auto => std::thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(1)
+                .min(MAX_AUTO_JOBS),
+            Jobs::Count(count) => count,
+        }
+    }
+}
+
+/// Which change to launch the difftool for next
+#[derive(Debug, Eq, PartialEq)]
+enum Flow {
+    Next,
+    Previous,
+    /// Jump to the 1 based position of a change
+    Jump(usize),
+    Quit,
+}
+
+/// The response to the prompt before launching the difftool
+#[derive(Debug, Eq, PartialEq)]
+enum Answer {
+    Launch,
+    Skip,
+    Previous,
+    /// Jump to the 1 based position of a change
+    Jump(usize),
+    /// Save the files of the change to a directory, then prompt again
+    Save,
+    Quit,
+}
+
+/// The index of the next change to launch the difftool for after `flow`
+///
+/// Returns `None` when there are no more changes to diff. Moving before the first change, or
+/// jumping to a position that doesn't exist, stays on the `current` change.
+///
+/// # Arguments
+/// * `flow` - Where to go from the `current` change
+/// * `current` - The 0 based index of the change that was just diffed
+/// * `total` - The total number of changes being diffed
+fn navigate(flow: Flow, current: usize, total: usize) -> Option<usize> {
+    match flow {
+        Flow::Next => Some(current + 1).filter(|next| *next < total),
+        Flow::Previous => Some(current.saturating_sub(1)),
+        Flow::Jump(position) if (1..=total).contains(&position) => Some(position - 1),
+        Flow::Jump(_) => Some(current),
+        Flow::Quit => None,
+    }
+}
+
+/// A thin wrapper around [Difftool::launch()]. It allows for a common future when there is nothing
+/// to diff
+///
+/// # Arguments
+/// * `difftool` - The difftool to launch, if any
+/// * `position` - The 1 based position of the change being diffed
+/// * `total` - The total number of changes being diffed
+/// * `options` - Options for how to launch the difftool
+///
+/// # Returns
+/// Where to go next and what happened to the change
+async fn launch_difftool(
+    difftool: Option<Difftool<'_>>,
+    position: usize,
+    total: usize,
+    options: &DiffOptions<'_>,
+) -> Result<(Flow, Outcome)> {
+    let Some(difftool) = difftool else {
+        return Ok((Flow::Next, Outcome::NotViewed));
+    };
+    if options.print_command {
+        println!("{}", difftool.command_line());
+        return Ok((Flow::Next, Outcome::NotViewed));
+    }
+    if options.prompt {
+        loop {
+            match prompt(&difftool, position, total).await? {
+                Answer::Launch => break,
+                Answer::Skip => return Ok((Flow::Next, Outcome::Skipped)),
+                Answer::Previous => return Ok((Flow::Previous, Outcome::Skipped)),
+                Answer::Jump(position) => return Ok((Flow::Jump(position), Outcome::Skipped)),
+                Answer::Save => {
+                    eprint!("Save to directory (empty to cancel): ");
+                    std::io::stderr().flush()?;
+                    match save_answer(&difftool, &read_line().await?) {
+                        Ok(Some(dir)) => eprintln!("Saved to {}", dir.display()),
+                        Ok(None) => {}
+                        Err(error) => eprintln!("{error:#}"),
+                    }
+                }
+                Answer::Quit => return Ok((Flow::Quit, Outcome::NotViewed)),
+            }
         }
-        return Ok(());
     }
+    difftool
+        .launch()
+        .await
+        .with_context(|| format!("Failed to launch the difftool for {}", difftool.filename()))?;
+    if let Some(dir) = options.save_dir {
+        difftool
+            .save(dir)
+            .with_context(|| format!("Failed to save {}", difftool.filename()))?;
+    }
+    Ok((Flow::Next, Outcome::Viewed))
+}
 
-    // Important, do this after the name only check as name only doesn't need a difftool
-    let difftool = git_config::Difftool::new(std::env::current_dir()?, cli.tool.as_deref())?;
-    diff(difftool, change_set).await?;
-    Ok(())
+/// Prompt the user before launching `difftool`, similar to `git-difftool`
+///
+/// The prompt goes to stderr so that stdout is left for output that may be scripted against.
+async fn prompt(difftool: &Difftool<'_>, position: usize, total: usize) -> Result<Answer> {
+    eprintln!("\nViewing ({position}/{total}): '{}'", difftool.filename());
+    eprint!(
+        "Launch '{}' [Y/n/q, < previous, > next, g N go to N, s save]? ",
+        difftool.tool_name()
+    );
+    std::io::stderr().flush()?;
+    Ok(parse_answer(&read_line().await?))
 }
 
-/// A thin wrapper around [Difftool::launch()]. It allows for a common future when there is nothing
-/// to diff
-async fn launch_difftool(difftool: Option<Difftool<'_>>) -> Result<()> {
-    if let Some(difftool) = difftool {
-        difftool.launch().await
-    } else {
-        Ok(())
+/// Read a line the user entered
+///
+/// Reads on a blocking thread so the downloading of the other changes can continue.
+async fn read_line() -> Result<String> {
+    let input = tokio::task::spawn_blocking(|| {
+        let mut input = String::new();
+        std::io::stdin().lock().read_line(&mut input).map(|_| input)
+    })
+    .await??;
+    Ok(input)
+}
+
+/// Save the files of `difftool` to the directory the user entered as `input`
+///
+/// # Returns
+/// The directory the files were saved to, `None` when no directory was entered
+fn save_answer(difftool: &Difftool<'_>, input: &str) -> Result<Option<PathBuf>> {
+    let dir = input.trim();
+    if dir.is_empty() {
+        return Ok(None);
+    }
+    difftool
+        .save(dir)
+        .with_context(|| format!("Failed to save {} to {dir}", difftool.filename()))?;
+    Ok(Some(PathBuf::from(dir)))
+}
+
+/// Parse the user's `input` to the launch prompt, anything unknown launches the difftool
+fn parse_answer(input: &str) -> Answer {
+    let input = input.trim().to_lowercase();
+    if let Some(position) = input
+        .strip_prefix('g')
+        .and_then(|position| position.trim().parse().ok())
+    {
+        return Answer::Jump(position);
+    }
+    match input.as_str() {
+        "n" | "no" | ">" => Answer::Skip,
+        "<" => Answer::Previous,
+        "q" | "quit" => Answer::Quit,
+        "s" | "save" => Answer::Save,
+        _ => Answer::Launch,
     }
 }
 
 /// Launches a difftool for each change in `change_set`.
 ///
 /// Similar to git-difftool only one change will be opened at a time in the difftool. The difftool
-/// of the changes will be executed in the same order as the changes.
+/// of the changes will be executed in the same order as the changes, unless the user navigates to
+/// another change from the prompt.
 ///
 /// # Arguments
-/// * `difftool` - The command name of the difftool to use
+/// * `diff` - Creates the temporary files and difftool for each change
 /// * `change_set` - The changes to run the difftool on
+/// * `options` - Options for how to launch the difftool for each change
+///
+/// # Returns
+/// Which of the changes were viewed or skipped
 ///
 /// # Implementation Details
 /// In an effort to speed up performance `async` behavior has been done. The logic uses 2 queues:
 ///
-/// 1. a queue to download and create the temporary diff files
-/// 2. a queue to launch the difftool on the next change ready for diffing
+/// 1. a queue to download and create the temporary diff files, at most `options.jobs` at a time
+/// 2. the difftools which are ready, indexed by the `current` change to launch the difftool on
 ///
 /// The reason for the 2 queues is to prevent launching multiple difftool instances. We only want
 /// one instance up at a time until the user dismisses it. While the difftool is up and has not
-/// been dismissed, the downloading and creation of temporary diff files will proceed.
-async fn diff(difftool: git_config::Difftool, change_set: ChangeSet) -> Result<()> {
-    let diff = Diff::new(difftool)?;
+/// been dismissed, the downloading and creation of temporary diff files will proceed. The ready
+/// difftools are kept so that the user can navigate back to a previous change.
+async fn diff(diff: Diff, change_set: ChangeSet, options: &DiffOptions<'_>) -> Result<Report> {
+    let mut report = Report::new(&change_set);
+    let mut failures = Failures::default();
+    let filenames = change_set
+        .changes
+        .iter()
+        .map(|change| change.filename.clone())
+        .collect::<Vec<_>>();
     {
-        let mut stream = FuturesOrdered::new();
-        for change in change_set.changes {
-            stream.push_back(diff.difftool(change));
-        }
+        let total = change_set.changes.len();
+        let mut current = 0;
+        let mut stream = diff.difftools(change_set.changes, options.jobs);
 
         // See https://tokio.rs/tokio/tutorial/select#resuming-an-async-operation on this pattern
-        // Initialize to done, because the `launch_difftool(None)` will return a consumed future.
-        let mut done = true;
-        let diff_future = launch_difftool(None);
+        // Initialize to not launching, because the `launch_difftool(None)` will return a consumed
+        // future.
+        let mut launching = false;
+        let diff_future = launch_difftool(None, current, total, options);
         tokio::pin!(diff_future);
 
-        let mut diffs = VecDeque::new();
+        // Changes which failed to download are `None`, they're skipped over
+        let mut diffs = Vec::with_capacity(total);
+        let mut progress = Progress::new(options.progress, total);
 
         loop {
+            if !launching {
+                if current >= total {
+                    break;
+                }
+                match diffs.get(current) {
+                    Some(Some(difftool)) => {
+                        progress.finish();
+                        let difftool = Difftool::clone(difftool);
+                        diff_future.set(launch_difftool(
+                            Some(difftool),
+                            current + 1,
+                            total,
+                            options,
+                        ));
+                        launching = true;
+                    }
+                    Some(None) => {
+                        current += 1;
+                        continue;
+                    }
+                    None => {}
+                }
+            }
+
             tokio::select! {
                 Some(new_diff) = stream.next() => {
-                    diffs.push_back(new_diff?);
-                    // Be sure and set this back to false since the other branch will set to true
-                    // if the `diffs` had happened to be empty last time through.
-                    done = false;
-                },
-                result = &mut diff_future, if !done => {
-                    //TODO need to make this error more useful. Getting errors
-                    // with no context isn't nice, but it's better than not
-                    // getting the errors.
-                    if let Err(error) = result {
-                        println!("{error:?}");
+                    // One file failing to download shouldn't stop the review of the others
+                    match new_diff {
+                        Ok(difftool) => diffs.push(Some(difftool)),
+                        Err(error) => {
+                            progress.finish();
+                            eprintln!("{error:#}");
+                            failures.record(&filenames[diffs.len()], &error);
+                            diffs.push(None);
+                        }
                     }
+                    // Only while waiting on a download, so an open prompt isn't written over
+                    if !launching {
+                        progress.update(diffs.len());
+                    }
+                },
+                result = &mut diff_future, if launching => {
+                    launching = false;
+                    let flow = match result {
+                        Ok((flow, outcome)) => {
+                            report.record(current, outcome);
+                            flow
+                        }
+                        Err(error) => {
+                            // When the exit code is trusted a failure means the user wants to stop
+                            if matches!(
+                                error.downcast_ref::<git_config::Error>(),
+                                Some(git_config::Error::DifftoolFailed(..))
+                            ) {
+                                return Err(error);
+                            }
+                            eprintln!("{error:#}");
+                            failures.record(&filenames[current], &error);
+                            Flow::Next
+                        }
+                    };
 
-                    if let Some(diffthing) = diffs.pop_front() {
-                        diff_future.set(launch_difftool(Some(diffthing)));
-                    } else {
-                        diff_future.set(launch_difftool(None));
-                        done = true;
+                    match navigate(flow, current, total) {
+                        Some(next) => current = next,
+                        None => break,
                     }
                 },
                 else => break,
             }
         }
+        progress.finish();
+    }
+    if !failures.is_empty() {
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        eprint!("\n{}", failures.summary(color));
+    }
+    if options.keep {
+        eprintln!("Kept the temporary files in {}", diff.keep().display());
+    }
+    Ok(report)
+}
+
+/// Print each change in `change_set` whose base reconstructed from the patch differs from the
+/// actual base
+///
+/// # Errors
+/// When any of the reconstructed bases differ, or fail to be checked
+async fn audit_reconstruction(diff: &Diff, change_set: ChangeSet) -> Result<()> {
+    let mut mismatches = 0;
+    for change in &change_set.changes {
+        let matches = diff
+            .reconstruction_matches(change)
+            .await
+            .with_context(|| format!("Failed to audit {}", change.filename))?;
+        if !matches {
+            println!("{}", change.filename);
+            mismatches += 1;
+        }
+    }
+    if mismatches > 0 {
+        return Err(Error::ReconstructionMismatch(mismatches).into());
     }
     Ok(())
 }
@@ -188,51 +1411,759 @@ async fn diff(difftool: git_config::Difftool, change_set: ChangeSet) -> Result<(
 pub enum Error {
     /// PR URL is not valid: {0}
     PrUrl(String),
+    /// Environment variable must be in the form KEY=VALUE: {0}
+    EnvVar(String),
+    /// Pull request {0} has no merge commit, it may have conflicts with the base branch
+    NoMergeCommit(usize),
+    /// No open pull requests for {0}
+    NoOpenPrs(String),
+    /// "{0}" is not one of the open pull requests
+    InvalidPrChoice(String),
+    /// "{0}" doesn't pick from the changed files, use numbers like 1,3-5 or 4-
+    InvalidFileChoice(String),
+    /// Base blob must be in the form FILE=SHA: {0}
+    BaseBlob(String),
+    /// Commits must be a range in the form BASE..HEAD: {0}
+    Commits(String),
+    /// Tool for an extension must be in the form EXT=TOOL: {0}
+    ToolFor(String),
+    /// Jobs must be a positive number or "auto": {0}
+    Jobs(String),
+    /// Page size must be a number from 1 to 100: {0}
+    PageSize(String),
+    /// Temporary directory must be an existing directory that can be written to: {0}
+    TmpDir(String),
+    /// Tool timeout must be a positive number of seconds: {0}
+    ToolTimeout(String),
+    /// Unknown diff filter status '{0}', expected one of A, C, D, M, R, T
+    DiffFilter(char),
+    /// {0} files reconstructed from their patch differ from their base
+    ReconstructionMismatch(usize),
+    /// No such path '{0}' in the diff.
+    NoSuchPath(String),
+    /// No difftool to review with, set one with `git config diff.tool` or review in the browser with --web
+    NoDifftool,
 }
 
 impl std::error::Error for Error {}
 
+/// Point out `--web` when the `error` resolving the difftool is that none is configured
+fn suggest_web(error: anyhow::Error) -> anyhow::Error {
+    match error.downcast_ref::<git_config::Error>() {
+        Some(git_config::Error::NoDifftoolConfigured) => error.context(Error::NoDifftool),
+        _ => error,
+    }
+}
+
+/// A pull request as given on the command line
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum PrArg {
+    /// Only the number, the repo comes from `--repo` or the current directory
+    Number(usize),
+    /// A url, which has the repo in it, and the host it's on
+    Url(PullRequest, String),
+    /// The head branch of the pull request, the repo comes from `--repo` or the current directory
+    Branch(String),
+}
+
 /// Parse a PR from the command line
 ///
 /// A pull request can be supplied as argument in any of the following formats:
 /// - by number, e.g. "123"
 /// - by URL, e.g. "https://github.com/OWNER/REPO/pull/123"
-fn parse_pr(pr: &str) -> Result<PullRequest> {
+/// - by the name of its head branch, e.g. "my-feature-branch"
+fn parse_pr(pr: &str) -> Result<PrArg> {
     if let Ok(number) = pr.parse() {
-        let mut gh = gh_interface::GhCli::new(Command::new("gh"));
-        let repo = gh.current_repo()?;
-        return Ok(PullRequest { repo, number });
+        return Ok(PrArg::Number(number));
     }
-    let url = Url::parse(pr)?;
+    let url = match Url::parse(pr) {
+        Ok(url) => url,
+        // Without a scheme it isn't a url, branch names can't have the `:` to make one
+        Err(url::ParseError::RelativeUrlWithoutBase) if !pr.is_empty() => {
+            return Ok(PrArg::Branch(pr.to_string()))
+        }
+        Err(error) => return Err(error.into()),
+    };
     let components = url
         .path_segments()
         .map(|c| c.collect::<Vec<_>>())
-        .expect("Should only fail for cannot-be-a-base urls");
+        .ok_or_else(|| Error::PrUrl(pr.to_string()))?;
+
+    // GitHub Enterprise hosts may have a path prefix before the owner and repo so search for the
+    // `pull` segment instead of relying on a fixed position.
+    let pull = components
+        .iter()
+        .position(|c| *c == "pull")
+        .filter(|pull| *pull >= 2)
+        .ok_or_else(|| Error::PrUrl(pr.to_string()))?;
     let number = components
-        .get(3)
+        .get(pull + 1)
         .ok_or_else(|| Error::PrUrl(pr.to_string()))?
         .parse()?;
 
-    // Note since the "3" up above will error out, we know for sure we have 2 components
-    let repo = components.into_iter().take(2).collect::<Vec<_>>().join("/");
+    let repo = components[pull - 2..pull].join("/");
+    let host = url.host_str().ok_or_else(|| Error::PrUrl(pr.to_string()))?;
+    Ok(PrArg::Url(PullRequest { repo, number }, host.to_string()))
+}
+
+/// The GitHub host to use, `hostname` when given, otherwise the host of a `pr` url
+///
+/// A url of GitHub Enterprise is on its own host, `None` leaves it to `gh` to pick the host.
+fn pr_hostname(hostname: Option<String>, pr: Option<&PrArg>) -> Option<String> {
+    match (hostname, pr) {
+        (Some(hostname), _) => Some(hostname),
+        (None, Some(PrArg::Url(_, host))) if host != "github.com" => Some(host.clone()),
+        (None, _) => None,
+    }
+}
+
+/// Interactively pick one of the open pull requests for the current repo
+fn pick_pr(gh: &mut gh_interface::GhCli<Command>) -> Result<PullRequest> {
+    let repo = gh.current_repo()?;
+    let prs = gh.open_prs()?;
+    if prs.is_empty() {
+        return Err(Error::NoOpenPrs(repo).into());
+    }
+
+    // The picker is interactive, so keep it out of stdout
+    let number = prompt_pr(
+        &prs,
+        &mut std::io::stderr().lock(),
+        &mut std::io::stdin().lock(),
+    )?;
     Ok(PullRequest { repo, number })
 }
 
+/// List the open `prs` to `out` and read the number of the one picked from `input`
+fn prompt_pr(prs: &[PrSummary], out: &mut impl Write, input: &mut impl BufRead) -> Result<usize> {
+    writeln!(
+        out,
+        "No pull request found for the current branch. Open pull requests:"
+    )?;
+    for pr in prs {
+        writeln!(out, "  #{} {}", pr.number, pr.title)?;
+    }
+    write!(out, "Pull request number: ")?;
+    out.flush()?;
+
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(select_pr(prs, &line).ok_or_else(|| Error::InvalidPrChoice(line.trim().into()))?)
+}
+
+/// The pull request number from `prs` that the user chose with `input`
+///
+/// The `input` may optionally be prefixed with `#`.
+fn select_pr(prs: &[PrSummary], input: &str) -> Option<usize> {
+    let input = input.trim();
+    let number = input.strip_prefix('#').unwrap_or(input).parse().ok()?;
+    prs.iter().any(|pr| pr.number == number).then_some(number)
+}
+
+/// Interactively pick the files of `change_set` to review, dropping the others
+fn pick_files(change_set: &mut ChangeSet) -> Result<()> {
+    let total = change_set.changes.len();
+    if total == 0 {
+        return Ok(());
+    }
+
+    // The picker is interactive, so keep it out of stdout
+    for (number, change) in change_set.changes.iter().enumerate() {
+        eprintln!("  {:>2}: {}", number + 1, change.filename);
+    }
+    eprint!("Files to review, e.g. 1,3-5 or 4- [all]: ");
+    std::io::stderr().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().lock().read_line(&mut input)?;
+    let picked =
+        select_files(total, &input).ok_or_else(|| Error::InvalidFileChoice(input.trim().into()))?;
+    change_set.changes = std::mem::take(&mut change_set.changes)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, change)| picked.contains(&index).then_some(change))
+        .collect();
+    Ok(())
+}
+
+/// The indices of the files, out of `total`, that the user chose with `input`
+///
+/// The `input` is a comma separated list of file numbers, starting at 1, ranges like "2-5", and
+/// open ended ranges like "4-". Empty `input` chooses all of the files.
+fn select_files(total: usize, input: &str) -> Option<BTreeSet<usize>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Some((0..total).collect());
+    }
+    let number = |number: &str| {
+        number
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=total).contains(number))
+    };
+    let mut picked = BTreeSet::new();
+    for choice in input.split(',') {
+        let (first, last) = match choice.split_once('-') {
+            Some((first, "")) => (number(first)?, total),
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(choice)?, number(choice)?),
+        };
+        if first > last {
+            return None;
+        }
+        picked.extend(first - 1..last);
+    }
+    Some(picked)
+}
+
+/// Parse a `KEY=VALUE` environment variable from the command line
+fn parse_env(env: &str) -> Result<(String, String)> {
+    match env.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(Error::EnvVar(env.to_string()).into()),
+    }
+}
+
+/// Parse the `--tool-timeout` from the command line as a number of seconds
+fn parse_tool_timeout(seconds: &str) -> Result<Duration> {
+    let seconds = seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .ok_or_else(|| Error::ToolTimeout(seconds.to_string()))?;
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Parse the `--tmpdir` from the command line, it must be a directory files can be created in
+fn parse_tmpdir(tmpdir: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(tmpdir);
+    let error = || Error::TmpDir(tmpdir.to_string());
+    if !path.is_dir() {
+        return Err(error().into());
+    }
+    tempfile::tempfile_in(&path).map_err(|_| error())?;
+    Ok(path)
+}
+
+/// Parse the `--page-size` from the command line, GitHub allows at most 100 per page
+fn parse_page_size(page_size: &str) -> Result<usize> {
+    match page_size.parse() {
+        Ok(size) if (1..=100).contains(&size) => Ok(size),
+        _ => Err(Error::PageSize(page_size.to_string()).into()),
+    }
+}
+
+/// Parse the `--jobs` from the command line, either "auto" or a positive number
+fn parse_jobs(jobs: &str) -> Result<Jobs> {
+    if jobs == "auto" {
+        return Ok(Jobs::Auto);
+    }
+    match jobs.parse() {
+        Ok(count) if count > 0 => Ok(Jobs::Count(count)),
+        _ => Err(Error::Jobs(jobs.to_string()).into()),
+    }
+}
+
+/// The GitHub file status for the git diff filter `letter`
+fn status_for_letter(letter: char) -> Option<&'static str> {
+    match letter {
+        'A' => Some("added"),
+        'C' => Some("copied"),
+        'D' => Some("removed"),
+        'M' => Some("modified"),
+        'R' => Some("renamed"),
+        'T' => Some("changed"),
+        _ => None,
+    }
+}
+
+/// The git diff filter letter for the GitHub file `status`, "X" like git for an unknown status
+fn letter_for_status(status: &str) -> char {
+    match status {
+        "added" => 'A',
+        "copied" => 'C',
+        "removed" => 'D',
+        "modified" => 'M',
+        "renamed" => 'R',
+        "changed" => 'T',
+        _ => 'X',
+    }
+}
+
+/// The line for `change` in the `--name-status` output, e.g. "M\tsrc/main.rs"
+///
+/// Renames and copies show the previous name as well, "R100\told -> new". GitHub doesn't give a
+/// similarity score, so the score is only shown, as 100, when the contents didn't change.
+fn name_status(change: &Change) -> String {
+    let letter = letter_for_status(&change.status);
+    match &change.previous_filename {
+        Some(previous) if matches!(letter, 'R' | 'C') => {
+            let score = if change.changes == 0 { "100" } else { "" };
+            format!("{letter}{score}\t{previous} -> {}", change.filename)
+        }
+        _ => format!("{letter}\t{}", change.filename),
+    }
+}
+
+/// Parse a git style `--diff-filter` from the command line, e.g. "AM"
+fn parse_diff_filter(filter: &str) -> Result<String> {
+    match filter.chars().find(|c| status_for_letter(*c).is_none()) {
+        Some(letter) => Err(Error::DiffFilter(letter).into()),
+        None => Ok(filter.to_string()),
+    }
+}
+
+/// The GitHub file statuses for a `filter` that has already been parsed by [parse_diff_filter()]
+fn diff_filter_statuses(filter: &str) -> Vec<&'static str> {
+    filter.chars().filter_map(status_for_letter).collect()
+}
+
+/// Parse the `BASE..HEAD` of `--commits` from the command line
+fn parse_commits(commits: &str) -> Result<(String, String)> {
+    match commits.split_once("..") {
+        Some((base, head))
+            if !base.is_empty()
+                && !head.is_empty()
+                && !head.starts_with('.')
+                && !head.contains("..") =>
+        {
+            Ok((base.to_string(), head.to_string()))
+        }
+        _ => Err(Error::Commits(commits.to_string()).into()),
+    }
+}
+
+/// Parse an `EXT=TOOL` from the command line for `--tool-for`
+fn parse_tool_for(tool_for: &str) -> Result<(String, String)> {
+    match tool_for.split_once('=') {
+        Some((extension, tool))
+            if !extension.trim_start_matches('.').is_empty() && !tool.is_empty() =>
+        {
+            Ok((extension.to_string(), tool.to_string()))
+        }
+        _ => Err(Error::ToolFor(tool_for.to_string()).into()),
+    }
+}
+
+/// Parse a `FILE=SHA` base blob from the command line
+fn parse_base_blob(base_blob: &str) -> Result<(String, String)> {
+    // Split on the last `=` since a sha will never contain one, but a filename could
+    match base_blob.rsplit_once('=') {
+        Some((file, sha)) if !file.is_empty() && !sha.is_empty() => {
+            Ok((file.to_string(), sha.to_string()))
+        }
+        _ => Err(Error::BaseBlob(base_blob.to_string()).into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::error::ErrorKind;
     use yare::parameterized;
 
+    #[parameterized(
+    modified = { "modified", None, 3, "M\tsrc/main.rs" },
+    added = { "added", None, 3, "A\tsrc/main.rs" },
+    removed = { "removed", None, 3, "D\tsrc/main.rs" },
+    renamed = { "renamed", Some("src/old.rs"), 0, "R100\tsrc/old.rs -> src/main.rs" },
+    renamed_and_modified = { "renamed", Some("src/old.rs"), 3, "R\tsrc/old.rs -> src/main.rs" },
+    unknown = { "unchanged", None, 0, "X\tsrc/main.rs" },
+    )]
+    fn name_status_line(status: &str, previous: Option<&str>, changes: usize, expected: &str) {
+        let change = Change {
+            filename: "src/main.rs".to_string(),
+            previous_filename: previous.map(String::from),
+            status: status.to_string(),
+            changes,
+            ..Default::default()
+        };
+        assert_eq!(name_status(&change), expected);
+    }
+
     #[parameterized(
     empty = {""},
-    not_a_url = {"nothing/to/it"},
+    not_a_url = {"nothing:to/it"},
     domain_only = {"https://github.com"},
     pr_location_is_not_a_number = {"https://github.com/repo/owner/pull/not_a_number"},
+    no_pull_segment = {"https://github.com/owner/repo/issues/10"},
+    no_number = {"https://github.com/owner/repo/pull"},
+    no_owner = {"https://github.com/repo/pull/10"},
     )]
     fn pr_url_parsing_errors(bad_url: &str) {
         assert!(parse_pr(bad_url).is_err())
     }
 
+    #[parameterized(
+    empty = {"\n", Answer::Launch},
+    yes = {"y\n", Answer::Launch},
+    no = {"n\n", Answer::Skip},
+    no_upper = {"N\n", Answer::Skip},
+    quit = {"q\n", Answer::Quit},
+    quit_word = {"quit\n", Answer::Quit},
+    next = {">\n", Answer::Skip},
+    previous = {"<\n", Answer::Previous},
+    jump = {"g 3\n", Answer::Jump(3)},
+    jump_without_space = {"g12\n", Answer::Jump(12)},
+    jump_without_position = {"g\n", Answer::Launch},
+    save = {"s\n", Answer::Save},
+    save_word = {"save\n", Answer::Save},
+    )]
+    fn parsing_prompt_answer(input: &str, expected: Answer) {
+        assert_eq!(parse_answer(input), expected);
+    }
+
+    #[test]
+    fn change_set_as_json() {
+        let pr = PullRequest {
+            repo: "owner/repo".to_string(),
+            number: 7,
+        };
+        let change_set = ChangeSet {
+            changes: vec![Change {
+                filename: "new.rs".to_string(),
+                previous_filename: Some("old.rs".to_string()),
+                status: "renamed".to_string(),
+                additions: 2,
+                deletions: 1,
+                changes: 3,
+                sha: Some("abc123".to_string()),
+                ..Default::default()
+            }],
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&change_set_json(&pr, &change_set).unwrap()).unwrap();
+
+        assert_eq!(json["repo"], "owner/repo");
+        assert_eq!(json["number"], 7);
+        let change = &json["changes"][0];
+        assert_eq!(change["filename"], "new.rs");
+        assert_eq!(change["previous_filename"], "old.rs");
+        assert_eq!(change["status"], "renamed");
+        assert_eq!(change["sha"], "abc123");
+        assert_eq!(change["additions"], 2);
+        assert_eq!(change["deletions"], 1);
+    }
+
+    #[parameterized(
+    next = {Flow::Next, 0, Some(1)},
+    next_at_end = {Flow::Next, 2, None},
+    previous = {Flow::Previous, 2, Some(1)},
+    previous_at_start = {Flow::Previous, 0, Some(0)},
+    jump = {Flow::Jump(1), 2, Some(0)},
+    jump_to_last = {Flow::Jump(3), 0, Some(2)},
+    jump_past_end = {Flow::Jump(4), 1, Some(1)},
+    jump_to_zero = {Flow::Jump(0), 1, Some(1)},
+    quit = {Flow::Quit, 1, None},
+    )]
+    fn navigating_changes(flow: Flow, current: usize, expected: Option<usize>) {
+        assert_eq!(navigate(flow, current, 3), expected);
+    }
+
+    #[parameterized(
+    number = {"12", Some(12)},
+    with_hash = {"#9", Some(9)},
+    with_whitespace = {" 12\n", Some(12)},
+    not_open = {"3", None},
+    not_a_number = {"foo", None},
+    )]
+    fn selecting_pr(input: &str, expected: Option<usize>) {
+        let prs = [
+            PrSummary {
+                number: 12,
+                title: "Add a feature".to_string(),
+            },
+            PrSummary {
+                number: 9,
+                title: "Fix a bug".to_string(),
+            },
+        ];
+        assert_eq!(select_pr(&prs, input), expected);
+    }
+
+    #[parameterized(
+    since_alone = { &["--since", "2d"], None },
+    since_and_base = { &["--since", "2d", "--base", "main"], Some(ErrorKind::ArgumentConflict) },
+    since_and_base_branch = { &["--since", "2d", "--base-branch"], Some(ErrorKind::ArgumentConflict) },
+    since_and_preview_suggestions = { &["--since", "last-push", "--preview-suggestions"], Some(ErrorKind::ArgumentConflict) },
+    )]
+    fn parsing_cli(args: &[&str], expected: Option<ErrorKind>) {
+        let args = std::iter::once("gh-difftool").chain(args.iter().copied());
+        let result = Cli::try_parse_from(args);
+        assert_eq!(result.err().map(|error| error.kind()), expected);
+    }
+
+    #[test]
+    fn prompting_pr() {
+        let prs = [
+            PrSummary {
+                number: 12,
+                title: "Add a feature".to_string(),
+            },
+            PrSummary {
+                number: 9,
+                title: "Fix a bug".to_string(),
+            },
+        ];
+        let mut out = vec![];
+        let number = prompt_pr(&prs, &mut out, &mut "#9\n".as_bytes()).unwrap();
+        assert_eq!(number, 9);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "No pull request found for the current branch. Open pull requests:\n  #12 Add a \
+            feature\n  #9 Fix a bug\nPull request number: "
+        );
+    }
+
+    #[parameterized(
+    all = {"\n", Some(vec![0, 1, 2, 3, 4])},
+    one = {"2", Some(vec![1])},
+    list_and_range = {"5, 1-2", Some(vec![0, 1, 4])},
+    open_range = {"4-", Some(vec![3, 4])},
+    overlapping = {"1-3,2-4", Some(vec![0, 1, 2, 3])},
+    zero = {"0", None},
+    past_the_end = {"6", None},
+    backwards = {"4-2", None},
+    not_a_number = {"main.rs", None},
+    )]
+    fn selecting_files(input: &str, expected: Option<Vec<usize>>) {
+        let expected = expected.map(|indices| indices.into_iter().collect::<BTreeSet<_>>());
+        assert_eq!(select_files(5, input), expected);
+    }
+
+    #[parameterized(
+    simple = {"KEY=VALUE", "KEY", "VALUE"},
+    empty_value = {"KEY=", "KEY", ""},
+    equals_in_value = {"KEY=a=b", "KEY", "a=b"},
+    )]
+    fn parse_env_var(env: &str, expected_key: &str, expected_value: &str) {
+        assert_eq!(
+            parse_env(env).unwrap(),
+            (expected_key.to_string(), expected_value.to_string())
+        );
+    }
+
+    #[parameterized(
+    no_equals = {"KEY"},
+    no_key = {"=VALUE"},
+    )]
+    fn parse_env_var_errors(env: &str) {
+        assert!(parse_env(env).is_err())
+    }
+
+    #[parameterized(
+    whole = {"5", Duration::from_secs(5)},
+    fraction = {"0.5", Duration::from_millis(500)},
+    )]
+    fn tool_timeout(seconds: &str, expected: Duration) {
+        assert_eq!(parse_tool_timeout(seconds).unwrap(), expected);
+    }
+
+    #[parameterized(
+    zero = {"0"},
+    negative = {"-1"},
+    word = {"never"},
+    infinite = {"inf"},
+    )]
+    fn tool_timeout_errors(seconds: &str) {
+        assert!(parse_tool_timeout(seconds).is_err());
+    }
+
+    #[test]
+    fn saved_to_entered_dir() {
+        let temp = temp_testdir::TempDir::default();
+        let new = temp.join("bar.txt");
+        std::fs::write(&new, "new").unwrap();
+        let diff = Diff::new_in(git_config::Difftool::default(), &*temp)
+            .unwrap()
+            .with_no_reverse(true);
+        let difftool = diff.local_patch(&new, String::new()).unwrap();
+        let save_dir = temp.join("saved");
+
+        assert_eq!(
+            save_answer(&difftool, &format!("{}\n", save_dir.display())).unwrap(),
+            Some(save_dir.clone())
+        );
+        for side in ["local", "remote"] {
+            assert_eq!(
+                std::fs::read_to_string(save_dir.join(side).join("bar.txt")).unwrap(),
+                "new"
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_saved_without_dir() {
+        let temp = temp_testdir::TempDir::default();
+        let new = temp.join("bar.txt");
+        std::fs::write(&new, "new").unwrap();
+        let diff = Diff::new_in(git_config::Difftool::default(), &*temp)
+            .unwrap()
+            .with_no_reverse(true);
+        let difftool = diff.local_patch(&new, String::new()).unwrap();
+
+        assert_eq!(save_answer(&difftool, "\n").unwrap(), None);
+    }
+
+    #[test]
+    fn tmpdir_parsed() {
+        let temp = temp_testdir::TempDir::default();
+        let tmpdir = temp.to_str().unwrap();
+        assert_eq!(parse_tmpdir(tmpdir).unwrap(), PathBuf::from(tmpdir));
+    }
+
+    #[test]
+    fn tmpdir_errors() {
+        let temp = temp_testdir::TempDir::default();
+        let file = temp.join("file");
+        std::fs::write(&file, "not a directory").unwrap();
+
+        for tmpdir in [temp.join("missing"), file] {
+            let tmpdir = tmpdir.to_str().unwrap();
+            assert_eq!(
+                parse_tmpdir(tmpdir).unwrap_err().downcast_ref::<Error>(),
+                Some(&Error::TmpDir(tmpdir.to_string()))
+            );
+        }
+    }
+
+    #[parameterized(
+    smallest = {"1", Some(1)},
+    largest = {"100", Some(100)},
+    zero = {"0", None},
+    too_large = {"101", None},
+    word = {"big", None},
+    )]
+    fn page_size(page_size: &str, expected: Option<usize>) {
+        assert_eq!(parse_page_size(page_size).ok(), expected);
+    }
+
+    #[test]
+    fn auto_jobs_is_positive_and_bounded() {
+        let jobs = parse_jobs("auto").unwrap().resolve();
+        assert!(jobs > 0);
+        assert!(jobs <= MAX_AUTO_JOBS);
+    }
+
+    #[parameterized(
+    one = {"1", 1},
+    twenty = {"20", 20},
+    )]
+    fn explicit_jobs(jobs: &str, expected: usize) {
+        assert_eq!(parse_jobs(jobs).unwrap().resolve(), expected);
+    }
+
+    #[parameterized(
+    zero = {"0"},
+    negative = {"-1"},
+    word = {"many"},
+    )]
+    fn jobs_errors(jobs: &str) {
+        assert!(parse_jobs(jobs).is_err())
+    }
+
+    #[parameterized(
+    added = {"A", &["added"]},
+    added_and_modified = {"AM", &["added", "modified"]},
+    all = {"ACDMRT", &["added", "copied", "removed", "modified", "renamed", "changed"]},
+    )]
+    fn diff_filter_to_statuses(filter: &str, expected: &[&str]) {
+        let filter = parse_diff_filter(filter).unwrap();
+        assert_eq!(diff_filter_statuses(&filter), expected);
+    }
+
+    #[parameterized(
+    lowercase = {"a"},
+    unknown = {"AX"},
+    )]
+    fn diff_filter_errors(filter: &str) {
+        assert!(parse_diff_filter(filter).is_err())
+    }
+
+    #[test]
+    fn web_suggested_without_difftool() {
+        let error = suggest_web(git_config::Error::NoDifftoolConfigured.into());
+        assert_eq!(error.downcast_ref::<Error>(), Some(&Error::NoDifftool));
+    }
+
+    #[test]
+    fn other_difftool_errors_unchanged() {
+        let error = suggest_web(git_config::Error::UnknownDifftool("foo".to_string()).into());
+        assert!(error.downcast_ref::<Error>().is_none());
+    }
+
+    #[parameterized(
+    simple = {"src/main.rs=abc123", "src/main.rs", "abc123"},
+    equals_in_file = {"a=b.txt=abc123", "a=b.txt", "abc123"},
+    )]
+    fn parse_base_blob_arg(base_blob: &str, expected_file: &str, expected_sha: &str) {
+        assert_eq!(
+            parse_base_blob(base_blob).unwrap(),
+            (expected_file.to_string(), expected_sha.to_string())
+        );
+    }
+
+    #[parameterized(
+    no_equals = {"src/main.rs"},
+    no_file = {"=abc123"},
+    no_sha = {"src/main.rs="},
+    )]
+    fn parse_base_blob_arg_errors(base_blob: &str) {
+        assert!(parse_base_blob(base_blob).is_err())
+    }
+
+    #[parameterized(
+    plain = {"png=imgdiff", "png", "imgdiff"},
+    with_dot = {".svg=imgdiff", ".svg", "imgdiff"},
+    )]
+    fn tool_for_parsed(tool_for: &str, expected_extension: &str, expected_tool: &str) {
+        assert_eq!(
+            parse_tool_for(tool_for).unwrap(),
+            (expected_extension.to_string(), expected_tool.to_string())
+        );
+    }
+
+    #[parameterized(
+    no_equals = {"png"},
+    no_extension = {"=imgdiff"},
+    only_dot = {".=imgdiff"},
+    no_tool = {"png="},
+    )]
+    fn tool_for_errors(tool_for: &str) {
+        assert_eq!(
+            parse_tool_for(tool_for)
+                .unwrap_err()
+                .downcast_ref::<Error>(),
+            Some(&Error::ToolFor(tool_for.to_string()))
+        );
+    }
+
+    #[parameterized(
+    shas = {"abc123..def456", "abc123", "def456"},
+    refs = {"main~3..feature", "main~3", "feature"},
+    )]
+    fn commit_range_parsed(commits: &str, expected_base: &str, expected_head: &str) {
+        assert_eq!(
+            parse_commits(commits).unwrap(),
+            (expected_base.to_string(), expected_head.to_string())
+        );
+    }
+
+    #[parameterized(
+    single_commit = {"abc123"},
+    no_base = {"..def456"},
+    no_head = {"abc123.."},
+    three_dots = {"abc123...def456"},
+    )]
+    fn commit_range_errors(commits: &str) {
+        assert_eq!(
+            parse_commits(commits).unwrap_err().downcast_ref::<Error>(),
+            Some(&Error::Commits(commits.to_string()))
+        );
+    }
+
     #[parameterized(
     ten = {"10", 10},
     twelve = {"12", 12},
@@ -240,28 +2171,68 @@ mod tests {
     )]
     fn parse_pr_from_a_number(number_str: &str, expected: usize) {
         let result = parse_pr(number_str).unwrap();
+        assert_eq!(result, PrArg::Number(expected));
+    }
+
+    #[test]
+    fn progress_counts_files() {
+        assert_eq!(progress_line(3, 180), "prefetched 3/180 files");
+    }
+
+    #[test]
+    fn files_from_skips_empty_lines() {
+        let input = "src/main.rs\n\n  \nsrc/**/*.md\r\nREADME.md";
         assert_eq!(
-            result,
-            PullRequest {
-                repo: "speedyleion/gh-difftool".to_string(),
-                number: expected
-            }
+            files_from_lines(input.as_bytes()).unwrap(),
+            ["src/main.rs", "src/**/*.md", "README.md"]
+        );
+    }
+
+    #[parameterized(
+    github = { None, "https://github.com/owner/repo/pull/1", None },
+    enterprise = { None, "https://ghe.corp.com/pages/owner/repo/pull/1", Some("ghe.corp.com") },
+    given_hostname = { Some("other.corp.com"), "https://ghe.corp.com/owner/repo/pull/1", Some("other.corp.com") },
+    number = { None, "1", None },
+    )]
+    fn hostname_for_pr(hostname: Option<&str>, pr: &str, expected: Option<&str>) {
+        let pr = parse_pr(pr).unwrap();
+        assert_eq!(
+            pr_hostname(hostname.map(String::from), Some(&pr)).as_deref(),
+            expected
         );
     }
 
+    #[test]
+    fn parse_pr_from_branch() {
+        let result = parse_pr("feature/my-branch").unwrap();
+        assert_eq!(result, PrArg::Branch("feature/my-branch".to_string()));
+    }
+
     #[parameterized(
-    gh_difftool = {"https://github.com/speedyleion/gh-difftool/pull/10", "speedyleion/gh-difftool", 10},
-    custom_1 = {"https://some_host.what/an-owner/a-repo-name/pull/3", "an-owner/a-repo-name", 3},
-    custom_2 = {"https://my_domain.com/the_best/bad_code/pull/21", "the_best/bad_code", 21},
+    gh_difftool = {"https://github.com/speedyleion/gh-difftool/pull/10", "speedyleion/gh-difftool", 10, "github.com"},
+    custom_1 = {"https://some_host.what/an-owner/a-repo-name/pull/3", "an-owner/a-repo-name", 3, "some_host.what"},
+    custom_2 = {"https://my_domain.com/the_best/bad_code/pull/21", "the_best/bad_code", 21, "my_domain.com"},
+    enterprise = {"https://ghe.corp.com/an-owner/a-repo/pull/123", "an-owner/a-repo", 123, "ghe.corp.com"},
+    enterprise_with_prefix = {"https://ghe.corp.com/pages/an-owner/a-repo/pull/123", "an-owner/a-repo", 123, "ghe.corp.com"},
+    enterprise_with_long_prefix = {"https://ghe.corp.com/a/b/owner/repo/pull/7", "owner/repo", 7, "ghe.corp.com"},
+    trailing_files_segment = {"https://github.com/owner/repo/pull/42/files", "owner/repo", 42, "github.com"},
     )]
-    fn parse_pr_from_url(url: &str, expected_repo: &str, expected_number: usize) {
+    fn parse_pr_from_url(
+        url: &str,
+        expected_repo: &str,
+        expected_number: usize,
+        expected_host: &str,
+    ) {
         let result = parse_pr(url).unwrap();
         assert_eq!(
             result,
-            PullRequest {
-                repo: expected_repo.to_string(),
-                number: expected_number
-            }
+            PrArg::Url(
+                PullRequest {
+                    repo: expected_repo.to_string(),
+                    number: expected_number
+                },
+                expected_host.to_string()
+            )
         );
     }
 }
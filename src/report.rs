@@ -0,0 +1,161 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! A markdown summary of which files were looked at while reviewing a pull request, meant to be
+//! pasted into a pull request comment
+
+use crate::change_set::ChangeSet;
+use crate::gh_interface::PullRequest;
+use std::fmt::Write;
+
+/// What happened to a file while reviewing
+///
+/// Ordered from least to most reviewed, so a file viewed once stays viewed even if it's skipped
+/// when coming back to it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Outcome {
+    /// The review ended before getting to the file
+    #[default]
+    NotViewed,
+    /// The file was skipped at the prompt
+    Skipped,
+    /// The difftool was launched for the file
+    Viewed,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::NotViewed => "not viewed",
+            Outcome::Skipped => "skipped",
+            Outcome::Viewed => "viewed",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Entry {
+    filename: String,
+    status: String,
+    outcome: Outcome,
+}
+
+/// The outcome of each file of a review
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    entries: Vec<Entry>,
+}
+
+impl Report {
+    /// A report with each change of `change_set` not viewed yet
+    pub fn new(change_set: &ChangeSet) -> Self {
+        let entries = change_set
+            .changes
+            .iter()
+            .map(|change| Entry {
+                filename: change.filename.clone(),
+                status: change.status.clone(),
+                outcome: Outcome::NotViewed,
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Record the `outcome` of the change at `index`, keeping the most reviewed outcome
+    pub fn record(&mut self, index: usize, outcome: Outcome) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.outcome = entry.outcome.max(outcome);
+        }
+    }
+
+    /// The report as a markdown table with a row per change of `pr`
+    pub fn markdown(&self, pr: &PullRequest) -> String {
+        let viewed = self
+            .entries
+            .iter()
+            .filter(|entry| entry.outcome == Outcome::Viewed)
+            .count();
+        let mut markdown = format!("## Review of {}#{}\n\n", pr.repo, pr.number);
+        writeln!(
+            markdown,
+            "Viewed {viewed} of {} files.\n",
+            self.entries.len()
+        )
+        .expect("Writing to a String can't fail");
+        markdown.push_str("| File | Status | Review |\n");
+        markdown.push_str("| --- | --- | --- |\n");
+        for entry in &self.entries {
+            // A `|` would end the cell early, even inside of a code span
+            let filename = entry.filename.replace('|', "\\|");
+            writeln!(
+                markdown,
+                "| `{filename}` | {} | {} |",
+                entry.status,
+                entry.outcome.as_str()
+            )
+            .expect("Writing to a String can't fail");
+        }
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change_set::Change;
+
+    fn change(filename: &str, status: &str) -> Change {
+        Change {
+            filename: filename.to_string(),
+            status: status.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn markdown_row_per_change() {
+        let change_set = ChangeSet {
+            changes: vec![
+                change("src/main.rs", "modified"),
+                change("src/new.rs", "added"),
+                change("a|b.txt", "removed"),
+            ],
+        };
+        let mut report = Report::new(&change_set);
+        report.record(0, Outcome::Viewed);
+        report.record(1, Outcome::Skipped);
+        let pr = PullRequest {
+            repo: "owner/repo".to_string(),
+            number: 7,
+        };
+
+        assert_eq!(
+            report.markdown(&pr),
+            concat!(
+                "## Review of owner/repo#7\n",
+                "\n",
+                "Viewed 1 of 3 files.\n",
+                "\n",
+                "| File | Status | Review |\n",
+                "| --- | --- | --- |\n",
+                "| `src/main.rs` | modified | viewed |\n",
+                "| `src/new.rs` | added | skipped |\n",
+                "| `a\\|b.txt` | removed | not viewed |\n",
+            )
+        );
+    }
+
+    #[test]
+    fn viewed_stays_viewed() {
+        let change_set = ChangeSet {
+            changes: vec![change("src/main.rs", "modified")],
+        };
+        let mut report = Report::new(&change_set);
+        report.record(0, Outcome::Viewed);
+        report.record(0, Outcome::Skipped);
+        report.record(0, Outcome::NotViewed);
+        assert_eq!(report.entries[0].outcome, Outcome::Viewed);
+    }
+}
@@ -0,0 +1,181 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! The point in a pull request's history to review from, for `--since`
+//!
+//! Each resolves to a previous head commit of the pull request, which is then diffed against the
+//! current head like `--interdiff`. The dates of `--touched-since` are parsed here as well.
+
+use anyhow::Result;
+use std::time::Duration;
+
+/// Where to review a pull request from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Since {
+    /// The commits made within the duration before now
+    Ago(Duration),
+    /// The commit of the last review by the current user
+    LastReview,
+    /// The head of the pull request before the last push
+    LastPush,
+}
+
+#[derive(Debug, displaydoc::Display, Eq, PartialEq)]
+pub enum Error {
+    /// Since must be "last-review", "last-push", or a duration like "2d": {0}
+    Since(String),
+    /// Dates must be a day like "2024-01-02" or a UTC time like "2024-01-02T03:04:05Z": {0}
+    Date(String),
+}
+
+impl std::error::Error for Error {}
+
+/// Parse `--since` from the command line
+///
+/// A duration is a positive number followed by one of the units `m` minutes, `h` hours, `d`
+/// days, or `w` weeks.
+pub fn parse_since(since: &str) -> Result<Since> {
+    match since {
+        "last-review" => return Ok(Since::LastReview),
+        "last-push" => return Ok(Since::LastPush),
+        _ => (),
+    }
+    let error = || Error::Since(since.to_string());
+    let (count, unit_seconds) = [
+        ("m", 60),
+        ("h", 60 * 60),
+        ("d", 24 * 60 * 60),
+        ("w", 7 * 24 * 60 * 60),
+    ]
+    .into_iter()
+    .find_map(|(unit, seconds)| Some((since.strip_suffix(unit)?, seconds)))
+    .ok_or_else(error)?;
+    let count = count
+        .parse::<u64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(error)?;
+    let seconds = count.checked_mul(unit_seconds).ok_or_else(error)?;
+    Ok(Since::Ago(Duration::from_secs(seconds)))
+}
+
+/// Parse `--touched-since` from the command line, as seconds since the Unix epoch
+///
+/// A date is a day like "2024-01-02", starting at midnight UTC, or a UTC time like
+/// "2024-01-02T03:04:05Z".
+pub fn parse_date(date: &str) -> Result<u64> {
+    let time = if date.contains('T') {
+        timestamp(date)
+    } else {
+        timestamp(&format!("{date}T00:00:00Z"))
+    };
+    Ok(time.ok_or_else(|| Error::Date(date.to_string()))?)
+}
+
+/// The seconds since the Unix epoch of a UTC `date` like "2024-01-02T03:04:05Z", how GitHub's
+/// API gives dates
+///
+/// Dates before the epoch, after the year 9999, or with a field out of range are `None`.
+pub fn timestamp(date: &str) -> Option<u64> {
+    let date = date.strip_suffix('Z')?;
+    let (day, time) = date.split_once('T')?;
+    let mut day = day.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (day.next()?.ok()?, day.next()?.ok()?, day.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..=23).contains(&hour)
+        || !(0..=59).contains(&minute)
+        || !(0..=59).contains(&second)
+    {
+        return None;
+    }
+
+    // Days from the epoch of the civil date, from Howard Hinnant's `days_from_civil`
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    u64::try_from(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yare::parameterized;
+
+    #[parameterized(
+    last_review = { "last-review", Since::LastReview },
+    last_push = { "last-push", Since::LastPush },
+    minutes = { "30m", Since::Ago(Duration::from_secs(30 * 60)) },
+    hours = { "5h", Since::Ago(Duration::from_secs(5 * 60 * 60)) },
+    days = { "2d", Since::Ago(Duration::from_secs(2 * 24 * 60 * 60)) },
+    weeks = { "1w", Since::Ago(Duration::from_secs(7 * 24 * 60 * 60)) },
+    )]
+    fn since_parsed(since: &str, expected: Since) {
+        assert_eq!(parse_since(since).unwrap(), expected);
+    }
+
+    #[parameterized(
+    empty = { "" },
+    no_unit = { "2" },
+    no_count = { "d" },
+    zero = { "0d" },
+    unknown_unit = { "2y" },
+    unknown_name = { "last-merge" },
+    multi_byte_unit = { "2é" },
+    overflow = { "99999999999999999w" },
+    )]
+    fn since_errors(since: &str) {
+        assert_eq!(
+            parse_since(since).unwrap_err().downcast_ref::<Error>(),
+            Some(&Error::Since(since.to_string()))
+        );
+    }
+
+    #[parameterized(
+    day = { "2024-02-29", 1709164800 },
+    time = { "2024-02-29T12:30:15Z", 1709209815 },
+    )]
+    fn date_parsed(date: &str, expected: u64) {
+        assert_eq!(parse_date(date).unwrap(), expected);
+    }
+
+    #[parameterized(
+    duration = { "2d" },
+    invalid_month = { "2024-13-01" },
+    huge_year = { "99999999999999999-01-01" },
+    invalid_time = { "2024-01-01T99:99:99Z" },
+    not_utc = { "2024-02-29T12:30:15+01:00" },
+    )]
+    fn date_errors(date: &str) {
+        assert_eq!(
+            parse_date(date).unwrap_err().downcast_ref::<Error>(),
+            Some(&Error::Date(date.to_string()))
+        );
+    }
+
+    #[parameterized(
+    epoch = { "1970-01-01T00:00:00Z", Some(0) },
+    leap_day = { "2024-02-29T12:30:15Z", Some(1709209815) },
+    end_of_year = { "2023-12-31T23:59:59Z", Some(1704067199) },
+    not_utc = { "2024-02-29T12:30:15+01:00", None },
+    huge_year = { "99999999999999999-01-01T00:00:00Z", None },
+    before_epoch = { "1969-12-31T23:59:59Z", None },
+    invalid_time = { "2024-01-01T99:99:99Z", None },
+    not_a_date = { "yesterday", None },
+    )]
+    fn timestamp_of_date(date: &str, expected: Option<u64>) {
+        assert_eq!(timestamp(date), expected);
+    }
+}
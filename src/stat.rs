@@ -0,0 +1,246 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! A git style diffstat of a change set
+
+use crate::change_set::ChangeSet;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The width to use when the terminal width isn't known, same as git
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// The width of the terminal stdout goes to, or [DEFAULT_WIDTH] when not a terminal
+pub fn terminal_width() -> usize {
+    terminal_size::terminal_size_of(std::io::stdout())
+        .map(|(width, _)| usize::from(width.0))
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// A git style diffstat of `change_set`
+///
+/// Each file gets a line of `filename | count +++---`, where the `+` and `-` bar is scaled down
+/// to fit in `width` columns. Binary files show `Bin` instead of the count and bar. The last
+/// line summarizes the files changed, insertions, and deletions.
+///
+/// # Arguments
+/// * `change_set` - The changes to summarize
+/// * `width` - The number of columns available, usually the terminal width
+pub fn diffstat(change_set: &ChangeSet, width: usize) -> String {
+    let changes = &change_set.changes;
+    let name_width = changes
+        .iter()
+        .map(|c| c.filename.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_changes = changes.iter().map(|c| c.changes).max().unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+    // " name | count bar"
+    let bar_width = width.saturating_sub(name_width + count_width + 5).max(1);
+
+    let mut stat = String::new();
+    for change in changes {
+        let name = &change.filename;
+        if change.is_binary() {
+            writeln!(stat, " {name:<name_width$} | Bin").expect("Writing to a String can't fail");
+            continue;
+        }
+        let count = change.changes;
+        let (plus, minus) = bar(change.additions, change.deletions, max_changes, bar_width);
+        writeln!(
+            stat,
+            " {name:<name_width$} | {count:>count_width$} {}{}",
+            "+".repeat(plus),
+            "-".repeat(minus)
+        )
+        .expect("Writing to a String can't fail");
+    }
+
+    let insertions = changes.iter().map(|c| c.additions).sum::<usize>();
+    let deletions = changes.iter().map(|c| c.deletions).sum::<usize>();
+    writeln!(
+        stat,
+        " {} changed, {}, {}",
+        plural(changes.len(), "file", "files"),
+        plural(insertions, "insertion(+)", "insertions(+)"),
+        plural(deletions, "deletion(-)", "deletions(-)"),
+    )
+    .expect("Writing to a String can't fail");
+    stat
+}
+
+/// How to order the directories of [`changed_dirs`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum DirOrder {
+    /// Most changed files first, directories with the same count by name
+    #[default]
+    Count,
+    /// By directory name
+    Name,
+}
+
+/// Each directory with files changed in `change_set` and the number of files changed in it
+///
+/// Files are counted in the directory they're directly in, files at the root of the repo are in
+/// `.`.
+pub fn changed_dirs(change_set: &ChangeSet, order: DirOrder) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for change in &change_set.changes {
+        let dir = change.filename.rsplit_once('/').map_or(".", |(dir, _)| dir);
+        *counts.entry(dir).or_default() += 1;
+    }
+    let mut dirs = counts
+        .into_iter()
+        .map(|(dir, count)| (dir.to_string(), count))
+        .collect::<Vec<_>>();
+    match order {
+        DirOrder::Count => {
+            dirs.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)))
+        }
+        DirOrder::Name => dirs.sort(),
+    }
+    dirs
+}
+
+/// The [`changed_dirs`] with a line of `count dir` for each
+pub fn dirstat(change_set: &ChangeSet, order: DirOrder) -> String {
+    let dirs = changed_dirs(change_set, order);
+    let count_width = dirs
+        .iter()
+        .map(|(_, count)| count.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let mut stat = String::new();
+    for (dir, count) in dirs {
+        writeln!(stat, " {count:>count_width$} {dir}").expect("Writing to a String can't fail");
+    }
+    stat
+}
+
+/// The number of `+` and `-` to show for a file
+///
+/// When the largest change doesn't fit in `bar_width` all bars are scaled down proportionally,
+/// though any non zero count will always get at least one character.
+fn bar(additions: usize, deletions: usize, max_changes: usize, bar_width: usize) -> (usize, usize) {
+    if max_changes <= bar_width {
+        return (additions, deletions);
+    }
+    let scale = |count: usize| {
+        if count == 0 {
+            0
+        } else {
+            (count * bar_width / max_changes).max(1)
+        }
+    };
+    (scale(additions), scale(deletions))
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("{count} {singular}")
+    } else {
+        format!("{count} {plural}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change_set::Change;
+
+    fn change(filename: &str, additions: usize, deletions: usize) -> Change {
+        Change {
+            filename: filename.to_string(),
+            patch: Some("patch".to_string()),
+            status: "modified".to_string(),
+            additions,
+            deletions,
+            changes: additions + deletions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stat_fits_in_width() {
+        let change_set = ChangeSet {
+            changes: vec![change("src/main.rs", 3, 2), change("Cargo.toml", 1, 0)],
+        };
+        let expected = concat!(
+            " src/main.rs | 5 +++--\n",
+            " Cargo.toml  | 1 +\n",
+            " 2 files changed, 4 insertions(+), 2 deletions(-)\n",
+        );
+        assert_eq!(diffstat(&change_set, 80), expected);
+    }
+
+    #[test]
+    fn stat_scales_to_width() {
+        let change_set = ChangeSet {
+            changes: vec![change("a.rs", 100, 100), change("b.rs", 1, 0)],
+        };
+        let expected = concat!(
+            " a.rs | 200 ++++++++++----------\n",
+            " b.rs |   1 +\n",
+            " 2 files changed, 101 insertions(+), 100 deletions(-)\n",
+        );
+        assert_eq!(diffstat(&change_set, 32), expected);
+    }
+
+    #[test]
+    fn stat_binary_files() {
+        let mut image = change("image.png", 0, 0);
+        image.patch = None;
+        let change_set = ChangeSet {
+            changes: vec![image, change("lib.rs", 0, 1)],
+        };
+        let expected = concat!(
+            " image.png | Bin\n",
+            " lib.rs    | 1 -\n",
+            " 2 files changed, 0 insertions(+), 1 deletion(-)\n",
+        );
+        assert_eq!(diffstat(&change_set, 80), expected);
+    }
+
+    #[test]
+    fn dirs_of_mixed_paths() {
+        let change_set = ChangeSet {
+            changes: vec![
+                change("src/main.rs", 1, 0),
+                change("Cargo.toml", 1, 0),
+                change("src/diff/tests.rs", 1, 0),
+                change("src/lib.rs", 1, 0),
+                change("docs/a.md", 1, 0),
+                change("README.md", 1, 0),
+            ],
+        };
+        assert_eq!(
+            changed_dirs(&change_set, DirOrder::Count),
+            [
+                (".".to_string(), 2),
+                ("src".to_string(), 2),
+                ("docs".to_string(), 1),
+                ("src/diff".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            changed_dirs(&change_set, DirOrder::Name),
+            [
+                (".".to_string(), 2),
+                ("docs".to_string(), 1),
+                ("src".to_string(), 2),
+                ("src/diff".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn stat_one_file() {
+        let change_set = ChangeSet {
+            changes: vec![change("lib.rs", 1, 0)],
+        };
+        assert!(diffstat(&change_set, 80)
+            .ends_with(" 1 file changed, 1 insertion(+), 0 deletions(-)\n"));
+    }
+}
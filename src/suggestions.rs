@@ -0,0 +1,202 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Suggested changes from the review comments of a pull request
+//!
+//! A reviewer suggests a change by putting the replacement lines in a fenced code block with the
+//! `suggestion` info string, the block replaces the lines the comment was made on.
+
+use std::collections::HashMap;
+
+/// A suggested replacement for some lines in the head version of a file
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Suggestion {
+    /// The file the suggestion is for
+    pub path: String,
+    /// The first line to replace, 1 based
+    pub start_line: usize,
+    /// The last line to replace, inclusive
+    pub end_line: usize,
+    /// The lines to replace with, without a trailing newline
+    pub replacement: String,
+}
+
+/// The contents of each `suggestion` block in a review comment's `body`
+///
+/// A block with no lines suggests removing the commented lines.
+pub fn suggestion_blocks(body: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut lines = body.lines().map(|line| line.trim_end_matches('\r'));
+    while let Some(line) = lines.next() {
+        let line = line.trim_start();
+        let fence_len = line.len() - line.trim_start_matches('`').len();
+        if fence_len < 3 || line[fence_len..].trim() != "suggestion" {
+            continue;
+        }
+        let fence = &line[..fence_len];
+        let block = lines
+            .by_ref()
+            .take_while(|line| line.trim() != fence)
+            .collect::<Vec<_>>();
+        blocks.push(block.join("\n"));
+    }
+    blocks
+}
+
+/// Group `suggestions` by the file they're for, keeping the order within each file
+pub fn by_path(suggestions: Vec<Suggestion>) -> HashMap<String, Vec<Suggestion>> {
+    let mut by_path = HashMap::<String, Vec<Suggestion>>::new();
+    for suggestion in suggestions {
+        by_path
+            .entry(suggestion.path.clone())
+            .or_default()
+            .push(suggestion);
+    }
+    by_path
+}
+
+/// `contents` with the `suggestions` applied
+///
+/// Like GitHub's batch commit of suggestions, a suggestion that overlaps the lines of an earlier
+/// one is left out. Suggestions for lines past the end of `contents` are left out as well.
+///
+/// # Arguments
+/// * `contents` - The head version of the file
+/// * `suggestions` - The suggestions for the file, in the order they were made
+pub fn apply(contents: &str, suggestions: &[Suggestion]) -> String {
+    let eol = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines = contents.split_inclusive('\n').collect::<Vec<_>>();
+
+    let mut applied: Vec<&Suggestion> = vec![];
+    for suggestion in suggestions {
+        let in_file = suggestion.start_line >= 1
+            && suggestion.start_line <= suggestion.end_line
+            && suggestion.end_line <= lines.len();
+        let overlaps = applied.iter().any(|other| {
+            suggestion.start_line <= other.end_line && other.start_line <= suggestion.end_line
+        });
+        if in_file && !overlaps {
+            applied.push(suggestion);
+        }
+    }
+    // Replace from the bottom up so the line numbers of the remaining suggestions stay valid
+    applied.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.start_line));
+
+    let replacements = applied
+        .iter()
+        .map(|suggestion| {
+            suggestion
+                .replacement
+                .lines()
+                .map(|line| format!("{line}{eol}"))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let ends_with_eol = contents.ends_with('\n');
+    for (suggestion, replacement) in applied.iter().zip(&replacements) {
+        lines.splice(
+            suggestion.start_line - 1..suggestion.end_line,
+            replacement.iter().map(String::as_str),
+        );
+    }
+
+    let mut applied = lines.concat();
+    if !ends_with_eol && applied.ends_with(eol) {
+        applied.truncate(applied.len() - eol.len());
+    }
+    applied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(start_line: usize, end_line: usize, replacement: &str) -> Suggestion {
+        Suggestion {
+            path: "src/main.rs".to_string(),
+            start_line,
+            end_line,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn suggestion_extracted_from_body() {
+        let body = "Maybe a better name?\r\n```suggestion\r\nlet count = 3;\r\n```\r\nThoughts?";
+        assert_eq!(suggestion_blocks(body), ["let count = 3;"]);
+    }
+
+    #[test]
+    fn multiple_suggestions_and_other_code_blocks() {
+        let body = concat!(
+            "```rust\n",
+            "let ignored = 1;\n",
+            "```\n",
+            "````suggestion\n",
+            "first\n",
+            "```\n",
+            "````\n",
+            "```suggestion\n",
+            "```\n",
+        );
+        assert_eq!(suggestion_blocks(body), ["first\n```", ""]);
+    }
+
+    #[test]
+    fn suggestion_applied_to_contents() {
+        let contents = "fn main() {\n    let c = 3;\n    println!(\"{c}\");\n}\n";
+        let suggestions = [suggestion(
+            2,
+            3,
+            "    let count = 3;\n    println!(\"{count}\");",
+        )];
+        assert_eq!(
+            apply(contents, &suggestions),
+            "fn main() {\n    let count = 3;\n    println!(\"{count}\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn suggestions_applied_bottom_up() {
+        let contents = "one\ntwo\nthree\nfour\n";
+        let suggestions = [
+            suggestion(1, 1, "1\n1.5"),
+            suggestion(3, 4, ""),
+            suggestion(2, 2, "2"),
+        ];
+        assert_eq!(apply(contents, &suggestions), "1\n1.5\n2\n");
+    }
+
+    #[test]
+    fn overlapping_and_out_of_range_suggestions_skipped() {
+        let contents = "one\r\ntwo\r\nthree";
+        let suggestions = [
+            suggestion(2, 3, "second\nthird"),
+            suggestion(3, 3, "3"),
+            suggestion(4, 4, "4"),
+        ];
+        assert_eq!(apply(contents, &suggestions), "one\r\nsecond\r\nthird");
+    }
+
+    #[test]
+    fn grouped_by_path() {
+        let mut other = suggestion(1, 1, "other");
+        other.path = "README.md".to_string();
+        let grouped = by_path(vec![
+            suggestion(2, 2, "first"),
+            other.clone(),
+            suggestion(1, 1, "second"),
+        ]);
+        assert_eq!(grouped["README.md"], [other]);
+        assert_eq!(
+            grouped["src/main.rs"],
+            [suggestion(2, 2, "first"), suggestion(1, 1, "second")]
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Helpers shared by the integration tests
+
+// The fake `gh` is a shell script
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use temp_testdir::TempDir;
+
+/// Write `script` as a `gh` in `dir`, returning a `PATH` which finds it before any real `gh`
+pub fn fake_gh(dir: &TempDir, script: &str) -> String {
+    let gh = dir.join("gh");
+    fs::write(&gh, script).unwrap();
+    fs::set_permissions(&gh, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var("PATH").unwrap_or_default();
+    format!("{}:{path}", dir.display())
+}
@@ -0,0 +1,32 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+// The fake `gh` is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use assert_cmd::Command;
+use common::fake_gh;
+use temp_testdir::TempDir;
+
+/// A `gh` which answers every request with a pull request without any files
+const EMPTY_PR_GH: &str = "#!/bin/sh\nprintf 'HTTP/2.0 200 OK\\r\\n\\r\\n[]\\n'\n";
+
+#[test]
+fn no_changed_files_reported() {
+    let temp = TempDir::default();
+    let mut cmd = Command::cargo_bin("gh-difftool").unwrap();
+    let assert = cmd
+        .env("PATH", fake_gh(&temp, EMPTY_PR_GH))
+        .current_dir(&*temp)
+        .args(["--name-only", "--repo", "owner/repo", "7"])
+        .assert();
+    let assert = assert.success();
+    let output = assert.get_output();
+    assert!(output.stdout.is_empty());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("No changed files in owner/repo#7"));
+}
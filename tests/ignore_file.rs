@@ -0,0 +1,47 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+// The fake `gh` is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use assert_cmd::Command;
+use common::fake_gh;
+use std::fs;
+use temp_testdir::TempDir;
+use yare::parameterized;
+
+/// A `gh` in a checkout of `me/repo`, every pull request has the one file `a.txt`
+const ONE_FILE_GH: &str = r#"#!/bin/sh
+case "$*" in
+    "repo view"*)
+        echo '{"name": "repo", "owner": {"login": "me"}}'
+        exit 0
+        ;;
+esac
+printf 'HTTP/2.0 200 OK\r\n\r\n[{"filename": "a.txt", "contents_url": "https://api.github.com/repos/me/repo/contents/a.txt?ref=abc", "status": "modified", "patch": "@@ -1 +1 @@\\n-a\\n+b"}]\n'
+"#;
+
+#[parameterized(
+current_repo_url = { "https://github.com/me/repo/pull/3", "" },
+other_repo_url = { "https://github.com/other/repo/pull/3", "a.txt\n" },
+)]
+fn ignore_file_of_current_repo_only(pr: &str, expected: &str) {
+    let temp = TempDir::default();
+    let checkout = temp.join("checkout");
+    fs::create_dir_all(checkout.join(".git")).unwrap();
+    fs::write(checkout.join(".gh-difftoolignore"), "*.txt\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("gh-difftool").unwrap();
+    let assert = cmd
+        .env("PATH", fake_gh(&temp, ONE_FILE_GH))
+        .current_dir(&checkout)
+        .args(["--name-only", pr])
+        .assert();
+    let assert = assert.success();
+    let stdout = std::str::from_utf8(&assert.get_output().stdout).unwrap();
+    assert_eq!(stdout, expected);
+}
@@ -0,0 +1,68 @@
+//          Copyright Nick G 2026.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+mod common;
+
+use assert_cmd::Command;
+
+#[test]
+fn invalid_pr_url_only_on_stderr() {
+    let mut cmd = Command::cargo_bin("gh-difftool").unwrap();
+    let assert = cmd
+        .arg("--name-only")
+        .arg("https://github.com/speedyleion/gh-difftool/issues/10")
+        .assert();
+    let assert = assert.failure();
+    let output = assert.get_output();
+    assert!(output.stdout.is_empty());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("PR URL is not valid"));
+}
+
+#[test]
+fn invalid_env_only_on_stderr() {
+    let mut cmd = Command::cargo_bin("gh-difftool").unwrap();
+    let assert = cmd
+        .arg("--env")
+        .arg("NOT_KEY_VALUE")
+        .arg("https://github.com/speedyleion/gh-difftool/pull/10")
+        .assert();
+    let assert = assert.failure();
+    let output = assert.get_output();
+    assert!(output.stdout.is_empty());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("KEY=VALUE"));
+}
+
+/// A `gh` which lists one changed file for a pull request and fails to download it
+#[cfg(unix)]
+const FAILING_DOWNLOAD_GH: &str = r#"#!/bin/sh
+case "$*" in
+    *contents*)
+        echo 'gh: Not Found (HTTP 404)' >&2
+        exit 1
+        ;;
+esac
+printf 'HTTP/2.0 200 OK\r\n\r\n[{"filename": "a.txt", "contents_url": "https://api.github.com/repos/owner/repo/contents/a.txt?ref=abc", "status": "modified", "patch": "@@ -1 +1 @@\\n-a\\n+b"}]\n'
+"#;
+
+#[cfg(unix)]
+#[test]
+fn failed_download_only_on_stderr() {
+    let temp = temp_testdir::TempDir::default();
+    let mut cmd = Command::cargo_bin("gh-difftool").unwrap();
+    let assert = cmd
+        .env("PATH", common::fake_gh(&temp, FAILING_DOWNLOAD_GH))
+        .current_dir(&*temp)
+        .args(["--tool-command", "true $LOCAL $REMOTE"])
+        .args(["--repo", "owner/repo", "7"])
+        .assert();
+    // One file failing to download doesn't fail the review
+    let assert = assert.success();
+    let output = assert.get_output();
+    assert!(output.stdout.is_empty(), "{output:?}");
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("Not Found"), "{stderr}");
+}